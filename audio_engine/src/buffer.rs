@@ -0,0 +1,286 @@
+
+
+
+use crate::error::SourceError;
+use crate::mixer::{ next_id, SoundSource };
+
+use std::sync::Arc;
+use std::time::Duration;
+
+
+
+/// the frames read from a source at a time while decoding
+const DECODE_FRAMES: usize = 4096;
+
+
+
+#[derive(Clone)]
+enum Samples {
+	F32(Arc<[f32]>),
+	I16(Arc<[i16]>)
+}
+
+impl Samples {
+
+
+	fn len (&self) -> usize {
+		match self {
+			Samples::F32(samples) => samples.len(),
+			Samples::I16(samples) => samples.len()
+		}
+	}
+
+
+}
+
+
+
+/// decoded sound samples, shared by every sound played from them
+///
+/// a sound buffer is decoded or loaded once, and cloning it only clones
+/// an `Arc`. each [`BufferSource`] made with
+/// [`source`](SoundBuffer::source) plays it from its own position, so
+/// the same asset can be played many times at once without decoding it
+/// again
+#[derive(Clone)]
+pub struct SoundBuffer {
+	/// shared by the clones, identifies the asset in `instance_limit`
+	id: u64,
+	samples: Samples,
+	channels: u16,
+	sample_rate: u32,
+	loop_region: Option<(u64, Option<u64>)>,
+	max_instances: Option<usize>
+}
+
+impl SoundBuffer {
+
+
+	/// a buffer of interleaved samples in the range `-1.0..=1.0`
+	pub fn from_samples (
+		channels: u16,
+		sample_rate: u32,
+		samples: impl Into<Arc<[f32]>>
+	) -> Result<Self, &'static str> {
+		Self::new(Samples::F32(samples.into()), channels, sample_rate)
+	}
+
+
+	/// a buffer of interleaved 16 bit samples, which takes half the
+	/// memory of float samples
+	pub fn from_i16_samples (
+		channels: u16,
+		sample_rate: u32,
+		samples: impl Into<Arc<[i16]>>
+	) -> Result<Self, &'static str> {
+		Self::new(Samples::I16(samples.into()), channels, sample_rate)
+	}
+
+
+	/// decode the whole `source` in a buffer, with its loop region
+	pub fn decode <T: SoundSource> (source: T) -> Result<Self, SourceError> {
+		let (channels, sample_rate, loop_region) = (source.channels(), source.sample_rate(), source.loop_region());
+		let samples = decode(source)?;
+		Self::new(Samples::F32(samples.into()), channels, sample_rate)
+			.map(|buffer| buffer.with_loop_region(loop_region))
+			.map_err(|_| SourceError::Malformed)
+	}
+
+
+	/// decode the whole `source` in a buffer of 16 bit samples, with its
+	/// loop region
+	///
+	/// this takes half the memory of [`decode`](SoundBuffer::decode),
+	/// for sources that have no more than 16 bits of precision
+	pub fn decode_i16 <T: SoundSource> (source: T) -> Result<Self, SourceError> {
+		let (channels, sample_rate, loop_region) = (source.channels(), source.sample_rate(), source.loop_region());
+		let samples: Vec<i16> = decode(source)?.into_iter()
+			.map(|x| (x * 32768.0).round().clamp(-32768.0, 32767.0) as i16)
+			.collect();
+		Self::new(Samples::I16(samples.into()), channels, sample_rate)
+			.map(|buffer| buffer.with_loop_region(loop_region))
+			.map_err(|_| SourceError::Malformed)
+	}
+
+
+	fn new (samples: Samples, channels: u16, sample_rate: u32) -> Result<Self, &'static str> {
+		if channels == 0 {
+			return Err("buffer has no channels");
+		}
+		if sample_rate == 0 {
+			return Err("buffer has no sample rate");
+		}
+		if !samples.len().is_multiple_of(channels as usize) {
+			return Err("buffer length is not a multiple of its channels");
+		}
+		Ok(Self {
+			id: next_id(),
+			samples,
+			channels,
+			sample_rate,
+			loop_region: None,
+			max_instances: None
+		})
+	}
+
+
+	/// set the [loop region](SoundSource::loop_region) of the sounds
+	/// played from this buffer
+	pub fn with_loop_region (mut self, loop_region: Option<(u64, Option<u64>)>) -> Self {
+		self.loop_region = loop_region;
+		self
+	}
+
+
+	/// limit how many sounds played from this buffer, or from its
+	/// clones, can play at once
+	///
+	/// see [`SoundSource::instance_limit`]
+	pub fn with_max_instances (mut self, max: usize) -> Self {
+		self.max_instances = Some(max);
+		self
+	}
+
+
+	pub fn channels (&self) -> u16 {
+		self.channels
+	}
+
+
+	pub fn sample_rate (&self) -> u32 {
+		self.sample_rate
+	}
+
+
+	/// the length of the buffer in frames
+	pub fn len_frames (&self) -> u64 {
+		(self.samples.len() / self.channels as usize) as u64
+	}
+
+
+	pub fn duration (&self) -> Duration {
+		Duration::from_secs_f64(self.len_frames() as f64 / self.sample_rate as f64)
+	}
+
+
+	/// a new source that plays this buffer from the start
+	pub fn source (&self) -> BufferSource {
+		BufferSource {
+			buffer: self.clone(),
+			position: 0
+		}
+	}
+
+
+}
+
+
+
+/// read all of `source`, or return the error that ended it
+fn decode <T: SoundSource> (mut source: T) -> Result<Vec<f32>, SourceError> {
+	let chunk = DECODE_FRAMES * source.channels() as usize;
+	let mut samples = Vec::new();
+	loop {
+		let len = samples.len();
+		samples.resize(len + chunk, 0.0);
+		let n = source.write_samples(&mut samples[len..]);
+		samples.truncate(len + n);
+		if n < chunk {
+			break;
+		}
+	}
+	match source.take_error() {
+		Some(err) => Err(err),
+		None => Ok(samples)
+	}
+}
+
+
+
+/// a [`SoundBuffer`] played from its own position
+pub struct BufferSource {
+	buffer: SoundBuffer,
+	/// the next frame to be written
+	position: u64
+}
+
+impl BufferSource {
+
+
+	pub fn new (buffer: SoundBuffer) -> Self {
+		buffer.source()
+	}
+
+
+	/// the buffer this plays
+	pub fn buffer (&self) -> &SoundBuffer {
+		&self.buffer
+	}
+
+
+}
+
+impl SoundSource for BufferSource {
+
+
+	fn channels (&self) -> u16 {
+		self.buffer.channels
+	}
+
+
+	fn sample_rate (&self) -> u32 {
+		self.buffer.sample_rate
+	}
+
+
+	fn reset (&mut self) {
+		self.position = 0;
+	}
+
+
+	fn write_samples (&mut self, buffer: &mut [f32]) -> usize {
+
+		let channels = self.buffer.channels as usize;
+		let start = self.position as usize * channels;
+		let len = buffer.len().min(self.buffer.samples.len() - start);
+		match self.buffer.samples {
+			Samples::F32(ref samples) => buffer[..len].copy_from_slice(&samples[start..start + len]),
+			Samples::I16(ref samples) => {
+				for (b, &x) in buffer[..len].iter_mut().zip(&samples[start..start + len]) {
+					*b = x as f32 / 32768.0;
+				}
+			}
+		}
+		self.position += (len / channels) as u64;
+		len
+
+	}
+
+
+	fn seek (&mut self, frame: u64) -> Result<(), SourceError> {
+		self.position = frame.min(self.buffer.len_frames());
+		Ok(())
+	}
+
+
+	fn len_frames (&self) -> Option<u64> {
+		Some(self.buffer.len_frames())
+	}
+
+
+	fn position (&self) -> Option<u64> {
+		Some(self.position)
+	}
+
+
+	fn loop_region (&self) -> Option<(u64, Option<u64>)> {
+		self.buffer.loop_region
+	}
+
+
+	fn instance_limit (&self) -> Option<(u64, usize)> {
+		self.buffer.max_instances.map(|max| (self.buffer.id, max))
+	}
+
+
+}
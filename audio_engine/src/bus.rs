@@ -0,0 +1,157 @@
+
+
+
+use crate::effect::Effect;
+use crate::mixer::{ BLOCK_FRAMES, Command, CommandSender };
+
+
+
+pub(crate) type BusId = u64;
+
+
+
+/// a group of sounds and other buses, mixed together before being
+/// sent to its parent bus
+///
+/// every engine has a [master](crate::AudioEngine::master) bus, which
+/// is the output of the mixer. other buses are created with
+/// [`AudioEngine::new_bus`](crate::AudioEngine::new_bus), and sounds
+/// are routed to them with [`AudioEngine::new_sound`](crate::AudioEngine::new_sound)
+///
+/// if this is dropped, the sounds and buses routed to it are routed
+/// to its parent instead
+pub struct Bus {
+
+	sender: CommandSender,
+	id: BusId,
+	name: String
+
+}
+
+impl Bus {
+
+
+	pub(crate) fn new (sender: CommandSender, id: BusId, name: &str) -> Self {
+		Self {
+			sender,
+			id,
+			name: name.to_owned()
+		}
+	}
+
+
+	pub(crate) fn id (&self) -> BusId {
+		self.id
+	}
+
+
+	fn send (&self, command: Command) {
+		self.sender.send(command);
+	}
+
+
+	/// the name the bus was created with
+	pub fn name (&self) -> &str {
+		&self.name
+	}
+
+
+	/// set the volume applied to the mix of the bus
+	pub fn set_volume (&self, volume: f32) {
+		self.send(Command::SetBusVolume(self.id, volume));
+	}
+
+
+	/// silence the bus, the sounds routed to it keep playing
+	pub fn set_muted (&self, muted: bool) {
+		self.send(Command::SetBusMuted(self.id, muted));
+	}
+
+
+	/// pause all sounds routed to the bus or to any of its children
+	///
+	/// the sounds stay where they were, and continue when the bus is
+	/// resumed. this doesn't change [`Sound::is_playing`](crate::Sound::is_playing)
+	pub fn pause (&self) {
+		self.send(Command::SetBusPaused(self.id, true));
+	}
+
+
+	/// continue the sounds paused by [`pause`](Bus::pause)
+	pub fn resume (&self) {
+		self.send(Command::SetBusPaused(self.id, false));
+	}
+
+
+	/// add an effect to the end of the effect chain of the bus, which
+	/// is applied to the mix of the bus before its volume
+	pub fn add_effect (&self, effect: impl Effect + 'static) {
+		self.send(Command::AddBusEffect(self.id, Box::new(effect)));
+	}
+
+
+	/// remove all effects of the bus
+	pub fn clear_effects (&self) {
+		self.send(Command::ClearBusEffects(self.id));
+	}
+
+
+}
+
+impl Drop for Bus {
+	fn drop (&mut self) {
+		self.send(Command::DropBus(self.id));
+	}
+}
+
+
+
+/// the mixer side of a [`Bus`]
+pub(crate) struct BusInner {
+
+	pub id: BusId,
+	/// the index of the parent bus in the mixer, buses always come
+	/// after their parent. unused by the master bus
+	pub parent: usize,
+	pub volume: f32,
+	pub muted: bool,
+	pub paused: bool,
+	/// if this or any parent bus is paused, updated on every block
+	pub halted: bool,
+	pub effects: Vec<Box<dyn Effect>>,
+	/// the mix of the bus, with [`BLOCK_FRAMES`] frames
+	pub buf: Vec<f32>
+
+}
+
+impl BusInner {
+
+
+	/// the number of effects a bus can have without the audio thread
+	/// allocating
+	const EFFECTS_CAPACITY: usize = 4;
+
+
+	pub fn new (id: BusId, channels: u16) -> Self {
+		Self {
+			id,
+			parent: 0,
+			volume: 1.0,
+			muted: false,
+			paused: false,
+			halted: false,
+			effects: Vec::with_capacity(Self::EFFECTS_CAPACITY),
+			buf: vec![0.0; BLOCK_FRAMES * channels as usize]
+		}
+	}
+
+
+	/// apply the effects to the first `len` samples of the mix
+	pub fn process (&mut self, len: usize, channels: u16, sample_rate: u32) {
+		for effect in self.effects.iter_mut() {
+			effect.process(&mut self.buf[..len], channels, sample_rate);
+		}
+	}
+
+
+}
@@ -2,32 +2,237 @@
 
 
 
-use crate::mixer::SoundSource;
+use crate::effect::Param;
+use crate::error::SourceError;
+use crate::mixer::{SoundSource, BLOCK_FRAMES};
+use crate::pan::{Panner, Panning};
 
-use std::vec;
 
 
+/// The gain of each input channel in each output channel, used by [`ChannelConverter`].
+///
+/// Channels are in the WAVE order: mono, stereo (L, R), quad (L, R, BL, BR), 5.1 (L, R, C, LFE,
+/// BL, BR) and 7.1 (L, R, C, LFE, BL, BR, SL, SR).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelMatrix {
+	inputs: u16,
+	outputs: u16,
+	/// `gains[o * inputs + i]` is the gain from input channel `i` to output channel `o`.
+	gains: Box<[f32]>,
+}
+impl ChannelMatrix {
+	/// Create a matrix from a user supplied list of gains.
+	///
+	/// `gains` has one row of `inputs` gains for each of the `outputs` channels. Return a `Err` if
+	/// its length doesn't match, or if any of the number of channels is 0.
+	pub fn new(inputs: u16, outputs: u16, gains: Vec<f32>) -> Result<Self, &'static str> {
+		if inputs == 0 || outputs == 0 {
+			return Err("a channel matrix needs at least one input and one output");
+		}
+		if gains.len() != inputs as usize * outputs as usize {
+			return Err("the number of gains doesn't match the number of channels");
+		}
+		Ok(Self {
+			inputs,
+			outputs,
+			gains: gains.into_boxed_slice(),
+		})
+	}
+
+	/// The matrix that copies each channel to itself.
+	pub fn identity(channels: u16) -> Self {
+		let mut this = Self::zero(channels, channels);
+		for c in 0..channels as usize {
+			this.set(c, c, 1.0);
+		}
+		this
+	}
+
+	/// The standard up or down mix from `inputs` to `outputs` channels.
+	///
+	/// Conversions between mono, stereo, quad and 5.1 follow the speaker mixing rules of the Web
+	/// Audio API, and 7.1 is mixed to 5.1 by merging the side and back channels. For other
+	/// layouts, mono is duplicated to every channel, every channel is averaged to mono, and
+	/// otherwise channels are copied by index, dropping or zeroing the remaining ones.
+	pub fn standard(inputs: u16, outputs: u16) -> Self {
+		if inputs == outputs {
+			return Self::identity(inputs);
+		}
+		if inputs == 8 && matches!(outputs, 1 | 2 | 4 | 6) {
+			return Self::standard(6, outputs).then(&Self::seven_one_to_five_one());
+		}
+		if outputs == 8 && matches!(inputs, 1 | 2 | 4 | 6) {
+			return Self::five_one_to_seven_one().then(&Self::standard(inputs, 6));
+		}
+
+		const HALF_SQRT: f32 = std::f32::consts::FRAC_1_SQRT_2;
+		let rows: &[&[f32]] = match (inputs, outputs) {
+			(1, 2) => &[&[1.0], &[1.0]],
+			(1, 4) => &[&[1.0], &[1.0], &[0.0], &[0.0]],
+			(1, 6) => &[&[0.0], &[0.0], &[1.0], &[0.0], &[0.0], &[0.0]],
+			(2, 1) => &[&[0.5, 0.5]],
+			(2, 4) => &[&[1.0, 0.0], &[0.0, 1.0], &[0.0, 0.0], &[0.0, 0.0]],
+			(2, 6) => &[
+				&[1.0, 0.0],
+				&[0.0, 1.0],
+				&[0.0, 0.0],
+				&[0.0, 0.0],
+				&[0.0, 0.0],
+				&[0.0, 0.0],
+			],
+			(4, 1) => &[&[0.25, 0.25, 0.25, 0.25]],
+			(4, 2) => &[&[0.5, 0.0, 0.5, 0.0], &[0.0, 0.5, 0.0, 0.5]],
+			(4, 6) => &[
+				&[1.0, 0.0, 0.0, 0.0],
+				&[0.0, 1.0, 0.0, 0.0],
+				&[0.0, 0.0, 0.0, 0.0],
+				&[0.0, 0.0, 0.0, 0.0],
+				&[0.0, 0.0, 1.0, 0.0],
+				&[0.0, 0.0, 0.0, 1.0],
+			],
+			(6, 1) => &[&[HALF_SQRT, HALF_SQRT, 1.0, 0.0, 0.5, 0.5]],
+			(6, 2) => &[
+				&[1.0, 0.0, HALF_SQRT, 0.0, HALF_SQRT, 0.0],
+				&[0.0, 1.0, HALF_SQRT, 0.0, 0.0, HALF_SQRT],
+			],
+			(6, 4) => &[
+				&[1.0, 0.0, HALF_SQRT, 0.0, 0.0, 0.0],
+				&[0.0, 1.0, HALF_SQRT, 0.0, 0.0, 0.0],
+				&[0.0, 0.0, 0.0, 0.0, 1.0, 0.0],
+				&[0.0, 0.0, 0.0, 0.0, 0.0, 1.0],
+			],
+			_ => return Self::discrete(inputs, outputs),
+		};
+		Self::from_rows(inputs, rows)
+	}
+
+	/// The number of input channels.
+	pub fn inputs(&self) -> u16 {
+		self.inputs
+	}
+
+	/// The number of output channels.
+	pub fn outputs(&self) -> u16 {
+		self.outputs
+	}
+
+	/// The gain from input channel `input` to output channel `output`.
+	pub fn gain(&self, input: usize, output: usize) -> f32 {
+		self.gains[output * self.inputs as usize + input]
+	}
+
+	fn zero(inputs: u16, outputs: u16) -> Self {
+		Self {
+			inputs,
+			outputs,
+			gains: vec![0.0; inputs as usize * outputs as usize].into_boxed_slice(),
+		}
+	}
+
+	fn set(&mut self, input: usize, output: usize, gain: f32) {
+		self.gains[output * self.inputs as usize + input] = gain;
+	}
+
+	fn from_rows(inputs: u16, rows: &[&[f32]]) -> Self {
+		let gains = rows.iter().flat_map(|row| row.iter().copied()).collect();
+		Self::new(inputs, rows.len() as u16, gains).unwrap()
+	}
+
+	/// The fallback for layouts without a standard mix.
+	fn discrete(inputs: u16, outputs: u16) -> Self {
+		let mut this = Self::zero(inputs, outputs);
+		if inputs == 1 {
+			for o in 0..outputs as usize {
+				this.set(0, o, 1.0);
+			}
+		} else if outputs == 1 {
+			for i in 0..inputs as usize {
+				this.set(i, 0, 1.0 / inputs as f32);
+			}
+		} else {
+			for c in 0..inputs.min(outputs) as usize {
+				this.set(c, c, 1.0);
+			}
+		}
+		this
+	}
+
+	fn seven_one_to_five_one() -> Self {
+		const HALF_SQRT: f32 = std::f32::consts::FRAC_1_SQRT_2;
+		let mut this = Self::zero(8, 6);
+		for c in 0..4 {
+			this.set(c, c, 1.0);
+		}
+		this.set(4, 4, HALF_SQRT);
+		this.set(6, 4, HALF_SQRT);
+		this.set(5, 5, HALF_SQRT);
+		this.set(7, 5, HALF_SQRT);
+		this
+	}
+
+	fn five_one_to_seven_one() -> Self {
+		let mut this = Self::zero(6, 8);
+		for c in 0..6 {
+			this.set(c, c, 1.0);
+		}
+		this
+	}
+
+	/// The matrix that applies `first` and then `self`.
+	fn then(&self, first: &Self) -> Self {
+		debug_assert_eq!(first.outputs, self.inputs);
+		let mut this = Self::zero(first.inputs, self.outputs);
+		for o in 0..self.outputs as usize {
+			for i in 0..first.inputs as usize {
+				let gain = (0..self.inputs as usize)
+					.map(|m| self.gain(m, o) * first.gain(i, m))
+					.sum();
+				this.set(i, o, gain);
+			}
+		}
+		this
+	}
+}
 
 /// Convert a SoundSource to a diferent number of channels.
 ///
-/// This struct is able to convert from 1 channel to many (by duplicating the signal), or from many
-/// channels to 1 (by averaging all channels). This panics for any other combination.
+/// Each output channel is a weighted sum of the input channels, given by a [`ChannelMatrix`].
 pub struct ChannelConverter<T: SoundSource> {
 	inner: T,
-	channels: u16,
+	matrix: ChannelMatrix,
+	/// scratch buffer for up to `BLOCK_FRAMES` frames of `inner`.
+	in_buffer: Box<[f32]>,
 }
 impl<T: SoundSource> ChannelConverter<T> {
 	/// Create a new ChannelConverter.
 	///
 	/// This will convert from the number of channels of `inner`, outputing the given number of
-	/// `channels`.
+	/// `channels`, using the [standard](ChannelMatrix::standard) mix.
 	pub fn new(inner: T, channels: u16) -> Self {
-		Self { inner, channels }
+		let matrix = ChannelMatrix::standard(inner.channels(), channels);
+		Self::with_matrix(inner, matrix)
+	}
+
+	/// Create a new ChannelConverter that mixes the channels with a given `matrix`.
+	///
+	/// Panics if the inputs of `matrix` don't match the number of channels of `inner`.
+	pub fn with_matrix(inner: T, matrix: ChannelMatrix) -> Self {
+		assert_eq!(
+			inner.channels(),
+			matrix.inputs(),
+			"the matrix inputs don't match the number of channels"
+		);
+		let in_buffer = vec![0.0; BLOCK_FRAMES * inner.channels() as usize];
+		Self {
+			inner,
+			matrix,
+			in_buffer: in_buffer.into_boxed_slice(),
+		}
 	}
 }
 impl<T: SoundSource> SoundSource for ChannelConverter<T> {
 	fn channels(&self) -> u16 {
-		self.channels
+		self.matrix.outputs()
 	}
 	fn sample_rate(&self) -> u32 {
 		self.inner.sample_rate()
@@ -35,74 +240,219 @@ impl<T: SoundSource> SoundSource for ChannelConverter<T> {
 	fn reset(&mut self) {
 		self.inner.reset()
 	}
-	fn write_samples(&mut self, buffer: &mut [i16]) -> usize {
-		if self.inner.channels() == 1 {
-			let len = buffer.len() / self.channels as usize;
-			let len = self.inner.write_samples(&mut buffer[0..len]);
-
-			for i in (0..len).rev() {
-				for c in 0..self.channels as usize {
-					buffer[i * self.channels as usize + c] = buffer[i];
+	fn seek(&mut self, frame: u64) -> Result<(), SourceError> {
+		self.inner.seek(frame)
+	}
+	fn len_frames(&self) -> Option<u64> {
+		self.inner.len_frames()
+	}
+	fn position(&self) -> Option<u64> {
+		self.inner.position()
+	}
+	fn loop_region(&self) -> Option<(u64, Option<u64>)> {
+		self.inner.loop_region()
+	}
+	fn take_error(&mut self) -> Option<SourceError> {
+		self.inner.take_error()
+	}
+	fn write_samples(&mut self, buffer: &mut [f32]) -> usize {
+		let in_channels = self.matrix.inputs() as usize;
+		let out_channels = self.matrix.outputs() as usize;
+		let mut written = 0;
+		for chunk in buffer.chunks_mut(BLOCK_FRAMES * out_channels) {
+			let frames = chunk.len() / out_channels;
+			let in_buffer = &mut self.in_buffer[..frames * in_channels];
+			let len = self.inner.write_samples(in_buffer);
+			let out_frames = chunk.chunks_exact_mut(out_channels);
+			for (in_frame, out_frame) in in_buffer[..len].chunks_exact(in_channels).zip(out_frames) {
+				for (o, out) in out_frame.iter_mut().enumerate() {
+					let row = &self.matrix.gains[o * in_channels..(o + 1) * in_channels];
+					*out = row.iter().zip(in_frame).map(|(g, x)| g * x).sum();
 				}
 			}
-			len * self.channels as usize
-		} else if self.channels == 1 {
-			let mut in_buffer = vec![0i16; buffer.len() * self.inner.channels() as usize];
-			let len = self.inner.write_samples(&mut in_buffer);
-			let mut sum: i32 = 0;
-			for i in 0..len {
-				sum += in_buffer[i] as i32;
-				if (i + 1) % self.inner.channels() as usize == 0 {
-					buffer[i / self.inner.channels() as usize] =
-						(sum / self.inner.channels() as i32) as i16;
-					sum = 0;
+			written += len / in_channels * out_channels;
+			if len < in_buffer.len() {
+				break;
+			}
+		}
+		written
+	}
+}
+
+/// The interpolation used by a [`SampleRateConverter`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResampleQuality {
+	/// Linear interpolation between the two nearest samples. Cheap, but aliases audibly on high
+	/// frequencies.
+	#[default]
+	Linear,
+	/// A band-limited interpolation with a Kaiser windowed sinc, with 48 taps when upsampling.
+	Sinc,
+}
+
+/// The interpolation kernel of a [`SampleRateConverter`].
+enum Kernel {
+	Linear,
+	/// A windowed sinc sampled at `SINC_PHASES + 1` fractional positions, with `2 * half` taps
+	/// each.
+	Sinc { half: usize, table: Box<[f32]> },
+}
+
+/// The number of fractional positions in the table of a sinc kernel. Positions in between are
+/// linearly interpolated.
+const SINC_PHASES: usize = 512;
+/// Half the number of taps of a sinc kernel, when upsampling.
+const SINC_HALF_TAPS: usize = 24;
+/// The beta parameter of the Kaiser window of a sinc kernel.
+const SINC_KAISER_BETA: f64 = 9.0;
+/// The cutoff of a sinc kernel, relative to the lowest Nyquist frequency.
+const SINC_CUTOFF: f64 = 0.92;
+
+impl Kernel {
+	fn new(quality: ResampleQuality, input_sample_rate: u32, output_sample_rate: u32) -> Self {
+		match quality {
+			ResampleQuality::Linear => Kernel::Linear,
+			ResampleQuality::Sinc => {
+				// when downsampling, the cutoff is lowered to the output Nyquist frequency, and
+				// the kernel is widened to keep the same transition band.
+				let ratio = (output_sample_rate as f64 / input_sample_rate as f64).min(1.0);
+				let cutoff = SINC_CUTOFF * ratio;
+				let half = (SINC_HALF_TAPS as f64 / ratio).ceil() as usize;
+				let taps = 2 * half;
+
+				let mut table = vec![0.0; (SINC_PHASES + 1) * taps];
+				let mut row_f64 = vec![0.0; taps];
+				for (phase, row) in table.chunks_exact_mut(taps).enumerate() {
+					let t = phase as f64 / SINC_PHASES as f64;
+					for (j, h) in row_f64.iter_mut().enumerate() {
+						let x = j as f64 - (half - 1) as f64 - t;
+						*h = cutoff * sinc(cutoff * x) * kaiser(x / half as f64, SINC_KAISER_BETA);
+					}
+					// normalize each phase, so a constant signal stays constant
+					let sum = row_f64.iter().sum::<f64>();
+					for (out, h) in row.iter_mut().zip(&row_f64) {
+						*out = (h / sum) as f32;
+					}
+				}
+
+				Kernel::Sinc {
+					half,
+					table: table.into_boxed_slice(),
 				}
 			}
-			len / self.inner.channels() as usize
-		} else {
-			unimplemented!("ChannelConventer only convert from 1 channel, or to 1 channel")
+		}
+	}
+
+	/// The number of input frames needed before and after the current one.
+	fn support(&self) -> (usize, usize) {
+		match self {
+			Kernel::Linear => (0, 1),
+			Kernel::Sinc { half, .. } => (half - 1, *half),
 		}
 	}
 }
 
-/// Do a sample rate convertion using linear interpolation.
+fn sinc(x: f64) -> f64 {
+	if x.abs() < 1e-9 {
+		1.0
+	} else {
+		let x = x * std::f64::consts::PI;
+		x.sin() / x
+	}
+}
+
+/// The Kaiser window, for `x` in `-1.0..=1.0`.
+fn kaiser(x: f64, beta: f64) -> f64 {
+	/// The zeroth order modified Bessel function of the first kind.
+	fn bessel_i0(x: f64) -> f64 {
+		let mut sum = 1.0;
+		let mut term = 1.0;
+		let mut k = 1.0;
+		while term > sum * 1e-12 {
+			term *= (x / (2.0 * k)) * (x / (2.0 * k));
+			sum += term;
+			k += 1.0;
+		}
+		sum
+	}
+	if x.abs() > 1.0 {
+		return 0.0;
+	}
+	bessel_i0(beta * (1.0 - x * x).sqrt()) / bessel_i0(beta)
+}
+
+/// Do a sample rate convertion, by [linear](ResampleQuality::Linear) or
+/// [sinc](ResampleQuality::Sinc) interpolation.
+///
+/// The inner sound can also be played faster or slower, changing its pitch, with the
+/// [`speed`](SampleRateConverter::speed) parameter. When the sample rates match and the speed is
+/// 1, the samples are passed through untouched.
 pub struct SampleRateConverter<T: SoundSource> {
 	inner: T,
 	/// The output sample_rate
 	output_sample_rate: u32,
-	/// a buffer contained a `in_len` of input samples, that will be completelly converted in
-	/// `out_len` of ouput samples.
-	in_buffer: Box<[i16]>,
-	out_len: usize,
-	/// The current length of valid samples in `in_buffer`.
+	kernel: Kernel,
+	/// A window of input frames. The frames before `before` frames from the start of the sound
+	/// are zero.
+	in_buffer: Box<[f32]>,
+	/// The current number of valid frames in `in_buffer`.
 	len: usize,
-	/// The index of the next sample to be generated in the `out_buffer`. `out_buffer` don't exist
-	/// in fact, and it samples are directly outputed in `write_samples`.
-	iter: usize,
+	/// The input frame in `in_buffer` right before the position of the next output frame.
+	pos: usize,
+	/// The fractional part of the position of the next output frame.
+	frac: f64,
+	/// How many input frames each output frame advances, at a speed of 1.
+	step: f64,
+	/// The playback speed.
+	speed: Param,
+	/// The speed at the end of the last output, which the speed is ramped from over the next
+	/// one. `None` before the first output.
+	last_speed: Option<f64>,
+	/// If the samples of `inner` are passed through. The last frames passed are kept as the
+	/// frames before the start of `in_buffer`, in case the speed changes.
+	bypass: bool,
+	/// The frame in `in_buffer` where the inner sound ended, if it already ended. The frames
+	/// after it are zero.
+	end: Option<usize>,
 }
 impl<T: SoundSource> SampleRateConverter<T> {
-	/// Create a new SampleRateConverter.
+	/// Create a new SampleRateConverter, using linear interpolation.
 	///
 	/// This will convert from the sample rate of `inner`, outputing with the given `sample_rate`.
 	pub fn new(inner: T, output_sample_rate: u32) -> Self {
-		use gcd::Gcd;
+		Self::with_quality(inner, output_sample_rate, ResampleQuality::Linear)
+	}
 
-		// divide the input sample_rate and the ouput sample_rate by its gcd, to find to smallest
-		// pair of input/output buffers that can be fully converted between.
-		let gcd = inner.sample_rate().gcd(output_sample_rate) as usize;
-		let in_len = inner.sample_rate() as usize / gcd * inner.channels() as usize;
-		let out_len = output_sample_rate as usize / gcd * inner.channels() as usize;
+	/// Create a new SampleRateConverter, with the given interpolation `quality`.
+	pub fn with_quality(inner: T, output_sample_rate: u32, quality: ResampleQuality) -> Self {
+		Self::with_speed(inner, output_sample_rate, quality, Param::new(1.0))
+	}
 
+	/// Create a new SampleRateConverter, playing at the speed given by a shared `speed`.
+	pub(crate) fn with_speed(
+		inner: T,
+		output_sample_rate: u32,
+		quality: ResampleQuality,
+		speed: Param,
+	) -> Self {
+		let kernel = Kernel::new(quality, inner.sample_rate(), output_sample_rate);
+		let (before, after) = kernel.support();
 		let channels = inner.channels() as usize;
-
-		// in_buffer also contains the first sample of the next buffer.
-		let in_buffer = vec![0; in_len + channels].into_boxed_slice();
+		// room for a block of new frames after the ones kept between refills, and for the
+		// padding at the end.
+		let frames = BLOCK_FRAMES + 2 * (before + after) + 1;
 
 		let mut this = Self {
-			len: in_buffer.len() - 1,
-			in_buffer,
-			iter: out_len,
-			out_len,
+			step: inner.sample_rate() as f64 / output_sample_rate as f64,
+			in_buffer: vec![0.0; frames * channels].into_boxed_slice(),
+			len: 0,
+			pos: 0,
+			frac: 0.0,
+			speed,
+			last_speed: None,
+			bypass: false,
+			end: None,
+			kernel,
 			inner,
 			output_sample_rate,
 		};
@@ -111,6 +461,91 @@ impl<T: SoundSource> SampleRateConverter<T> {
 
 		this
 	}
+
+	/// The playback speed, where 2 plays the inner sound twice as fast and an octave higher.
+	///
+	/// The returned handle can be changed while the converter is playing, the speed then changes
+	/// gradually over the next call to `write_samples`. Speeds above 1 are not band-limited, so
+	/// they can alias.
+	pub fn speed(&self) -> Param {
+		self.speed.clone()
+	}
+
+	/// Convert a frame of `inner` to the output sample rate.
+	fn to_output(&self, frame: f64) -> u64 {
+		(frame * self.output_sample_rate as f64 / self.inner.sample_rate() as f64).round() as u64
+	}
+
+	/// Convert a frame at the output sample rate to a frame of `inner`.
+	fn to_input(&self, frame: u64) -> u64 {
+		(frame as f64 * self.inner.sample_rate() as f64 / self.output_sample_rate as f64).round() as u64
+	}
+
+	/// Forget the frames read from `inner`, so the next output frame is its next frame.
+	fn clear(&mut self) {
+		let channels = self.inner.channels() as usize;
+		let (before, _) = self.kernel.support();
+		self.in_buffer[..before * channels].fill(0.0);
+		self.len = before;
+		self.pos = before;
+		self.frac = 0.0;
+		self.end = None;
+		self.last_speed = None;
+		self.bypass = self.output_sample_rate == self.inner.sample_rate();
+	}
+
+	/// Keep the last frames written while bypassing as the frames before the next position.
+	fn keep_history(&mut self, output: &[f32]) {
+		let channels = self.inner.channels() as usize;
+		let (before, _) = self.kernel.support();
+		let history = before * channels;
+		let new = output.len().min(history);
+		self.in_buffer.copy_within(new..history, 0);
+		self.in_buffer[history - new..history].copy_from_slice(&output[output.len() - new..]);
+	}
+
+	/// Drop the frames that are no longer needed, and read new ones from `inner`.
+	fn refill(&mut self) {
+		let channels = self.inner.channels() as usize;
+		let (before, after) = self.kernel.support();
+		// the last `after` frames are kept free for the zero padding at the end of the sound.
+		let readable = self.in_buffer.len() / channels - after;
+
+		// keep the frames still needed by the kernel, and skip the ones jumped over.
+		let start = self.pos - before;
+		let mut skip = start.saturating_sub(self.len);
+		let start = start.min(self.len);
+		self.in_buffer
+			.copy_within(start * channels..self.len * channels, 0);
+		self.len -= start;
+		self.pos = before;
+
+		let mut end = None;
+		while skip > 0 && end.is_none() {
+			let free = (readable - self.len).min(skip);
+			let read = self
+				.inner
+				.write_samples(&mut self.in_buffer[self.len * channels..(self.len + free) * channels]);
+			if read < free * channels {
+				end = Some(self.len);
+			}
+			skip -= read / channels;
+		}
+		if end.is_none() {
+			let free = &mut self.in_buffer[self.len * channels..readable * channels];
+			let read = self.inner.write_samples(free);
+			if read < free.len() {
+				end = Some(self.len + read / channels);
+			}
+			self.len += read / channels;
+		}
+
+		if let Some(end) = end {
+			self.in_buffer[end * channels..(end + after) * channels].fill(0.0);
+			self.len = end + after;
+			self.end = Some(end);
+		}
+	}
 }
 impl<T: SoundSource> SoundSource for SampleRateConverter<T> {
 	fn channels(&self) -> u16 {
@@ -121,59 +556,183 @@ impl<T: SoundSource> SoundSource for SampleRateConverter<T> {
 	}
 	fn reset(&mut self) {
 		self.inner.reset();
-
-		let channels = self.inner.channels() as usize;
-		self.len = self.inner.write_samples(&mut self.in_buffer[..]) - channels;
-		self.iter = 0;
+		self.clear();
+	}
+	fn seek(&mut self, frame: u64) -> Result<(), SourceError> {
+		self.inner.seek(self.to_input(frame))?;
+		self.clear();
+		Ok(())
+	}
+	fn len_frames(&self) -> Option<u64> {
+		self.inner.len_frames().map(|len| self.to_output(len as f64))
+	}
+	fn position(&self) -> Option<u64> {
+		// the frames after `pos` were already read from `inner`
+		let read = self.end.unwrap_or(self.len) as f64;
+		let ahead = read - self.pos as f64 - self.frac;
+		self.inner
+			.position()
+			.map(|position| self.to_output((position as f64 - ahead).max(0.0)))
+	}
+	fn loop_region(&self) -> Option<(u64, Option<u64>)> {
+		self.inner.loop_region().map(|(start, end)| {
+			(
+				self.to_output(start as f64),
+				end.map(|end| self.to_output(end as f64)),
+			)
+		})
 	}
-	fn write_samples(&mut self, buffer: &mut [i16]) -> usize {
+	fn take_error(&mut self) -> Option<SourceError> {
+		self.inner.take_error()
+	}
+	fn write_samples(&mut self, buffer: &mut [f32]) -> usize {
 		let channels = self.inner.channels() as usize;
 
-		if self.output_sample_rate == self.inner.sample_rate() {
-			return self.inner.write_samples(buffer);
+		let speed = self.speed.get().max(0.0) as f64;
+		let from = self.last_speed.unwrap_or(speed);
+		self.last_speed = Some(speed);
+		if self.bypass {
+			if from == speed && self.step * speed == 1.0 {
+				let len = self.inner.write_samples(buffer);
+				self.keep_history(&buffer[..len]);
+				return len;
+			}
+			// continue from the frames kept in the buffer
+			self.bypass = false;
 		}
 
+		let (_, after) = self.kernel.support();
 		let mut i = 0;
 		while i < buffer.len() {
-			let in_len = self.in_buffer.len() - channels;
-			fn div_up(a: usize, b: usize) -> usize {
-				a / b + (a % b != 0) as usize
-			}
-			let curr_out_len = div_up(self.out_len * self.len, in_len) / channels * channels;
-
-			// if next sample is out of bounds, reset in_buffer
-			if self.iter >= curr_out_len {
-				// if self.len is smaller than in_len, the inner sound already finished.
-				if self.len < in_len {
-					return i;
+			match self.end {
+				Some(end) if self.pos >= end => return i,
+				None if self.pos + after >= self.len => {
+					self.refill();
+					continue;
 				}
-
-				// the last sample of the last buffer is the start sample of this buffer.
-				self.in_buffer.copy_within(self.len.., 0);
-
-				self.len = self.inner.write_samples(&mut self.in_buffer[channels..]);
-				self.iter = 0;
+				_ => {}
 			}
 
-			// j is the float position in in_buffer.
-			let j = ((self.iter / channels) * in_len) as f32 / self.out_len as f32;
-
-			let t = j.fract();
-			let j = j as usize * channels;
+			let out = &mut buffer[i..i + channels];
+			match self.kernel {
+				Kernel::Linear => {
+					// interpolate by t, curr and next sample
+					let t = self.frac as f32;
+					let curr = &self.in_buffer[self.pos * channels..][..channels];
+					let next = &self.in_buffer[(self.pos + 1) * channels..][..channels];
+					for c in 0..channels {
+						out[c] = curr[c] * (1.0 - t) + next[c] * t;
+					}
+				}
+				Kernel::Sinc { half, ref table } => {
+					// interpolate the kernel between the two nearest phases
+					let taps = 2 * half;
+					let phase = self.frac * SINC_PHASES as f64;
+					let p = phase as usize;
+					let a = (phase - p as f64) as f32;
+					let h0 = &table[p * taps..][..taps];
+					let h1 = &table[(p + 1) * taps..][..taps];
 
-			for c in 0..channels {
-				// interpolate by t, curr and next sample
-				buffer[i + c] = (self.in_buffer[j + c] as f32 * (1.0 - t)
-					+ self.in_buffer[j + c + channels] as f32 * t)
-					as i16;
+					let first = (self.pos + 1 - half) * channels;
+					let frames = self.in_buffer[first..first + taps * channels].chunks_exact(channels);
+					out.fill(0.0);
+					for ((h0, h1), frame) in h0.iter().zip(h1).zip(frames) {
+						let h = h0 + (h1 - h0) * a;
+						for (o, x) in out.iter_mut().zip(frame) {
+							*o += h * x;
+						}
+					}
+				}
 			}
 
-			self.iter += channels;
 			i += channels;
+			// ramp the speed linearly over the output
+			let t = (i / channels) as f64 / (buffer.len() / channels) as f64;
+			self.frac += self.step * (from + (speed - from) * t);
+			let advance = self.frac.floor();
+			self.pos += advance as usize;
+			self.frac -= advance;
 		}
 
 		buffer.len()
 	}
 }
 
+/// The settings used to convert sounds to the output config.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Conversion {
+	/// user supplied matrices, used instead of the standard ones.
+	pub matrices: Vec<ChannelMatrix>,
+	pub quality: ResampleQuality,
+}
+impl Conversion {
+	/// Use `matrix` for conversions between its number of channels, replacing any matrix
+	/// previously set for them.
+	pub fn set_matrix(&mut self, matrix: ChannelMatrix) {
+		self.matrices
+			.retain(|x| x.inputs() != matrix.inputs() || x.outputs() != matrix.outputs());
+		self.matrices.push(matrix);
+	}
+
+	/// If a source with `source` channels is panned before being converted to `channels`.
+	///
+	/// A matrix set by the user for this conversion is used instead of panning.
+	pub fn pans(&self, source: u16, channels: u16) -> bool {
+		Panning::applies(source, channels)
+			&& !self.matrices.iter().any(|x| x.inputs() == source && x.outputs() == channels)
+	}
+}
+
+/// Wrap `source` in the converters needed to output the given number of `channels` and
+/// `sample_rate`.
+///
+/// The channel conversion uses the first matrix in `conversion` that fits, or the standard one.
+/// Resampling is done on the side with the least channels. If `panning` is given, the source is
+/// panned to stereo before being converted to the output channels. If `speed` is given, the
+/// source is always wrapped in a [`SampleRateConverter`] playing at that speed.
+pub(crate) fn conform(
+	source: Box<dyn SoundSource + Send>,
+	channels: u16,
+	sample_rate: u32,
+	conversion: &Conversion,
+	panning: Option<&Panning>,
+	speed: Option<&Param>,
+) -> Box<dyn SoundSource + Send> {
+	let wrap_channels = |source: Box<dyn SoundSource + Send>| -> Box<dyn SoundSource + Send> {
+		if source.channels() == channels {
+			return source;
+		}
+		let matrix = conversion
+			.matrices
+			.iter()
+			.find(|x| x.inputs() == source.channels() && x.outputs() == channels)
+			.cloned()
+			.unwrap_or_else(|| ChannelMatrix::standard(source.channels(), channels));
+		Box::new(ChannelConverter::with_matrix(source, matrix))
+	};
+	let wrap_sample_rate = |source: Box<dyn SoundSource + Send>| -> Box<dyn SoundSource + Send> {
+		match speed {
+			Some(speed) => Box::new(SampleRateConverter::with_speed(
+				source,
+				sample_rate,
+				conversion.quality,
+				speed.clone(),
+			)),
+			None if source.sample_rate() == sample_rate => source,
+			None => Box::new(SampleRateConverter::with_quality(
+				source,
+				sample_rate,
+				conversion.quality,
+			)),
+		}
+	};
 
+	if let Some(panning) = panning {
+		let panner = Panner::new(wrap_sample_rate(source), panning.clone());
+		wrap_channels(Box::new(panner))
+	} else if source.channels() > channels {
+		wrap_sample_rate(wrap_channels(source))
+	} else {
+		wrap_channels(wrap_sample_rate(source))
+	}
+}
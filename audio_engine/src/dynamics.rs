@@ -0,0 +1,287 @@
+
+
+
+use crate::effect::{ Effect, Param };
+
+use std::collections::VecDeque;
+
+
+
+/// convert a gain in dB to a linear gain
+fn db_to_gain (db: f32) -> f32 {
+	10f32.powf(db / 20.0)
+}
+
+
+/// the coefficient of a one pole smoother that covers about 63% of
+/// the distance to its target in `ms` milliseconds
+fn smoothing (ms: f32, sample_rate: u32) -> f32 {
+	let frames = ms.max(0.0) * 0.001 * sample_rate as f32;
+	if frames < 1.0 {
+		0.0
+	} else {
+		(-1.0 / frames).exp()
+	}
+}
+
+
+
+/// a look-ahead peak limiter
+///
+/// the output never goes above the ceiling. the samples are delayed by
+/// [`LOOKAHEAD`](Limiter::LOOKAHEAD) milliseconds, so the gain can be
+/// lowered smoothly before a peak arrives instead of clipping it. the
+/// gain goes back up with the release time
+///
+/// the ceiling, in dBFS, and the release, in milliseconds, are
+/// [`Param`]s. a [`Limiter::default`] is on the master bus of the
+/// engines created with [`AudioEngine::new`](crate::AudioEngine::new)
+pub struct Limiter {
+	ceiling: Param,
+	release: Param,
+	/// the channels and sample rate the buffers are sized for
+	config: Option<(u16, u32)>,
+	/// the delayed frames, interleaved
+	delay: Vec<f32>,
+	/// the next frame of `delay` to be read and written
+	pos: usize,
+	/// the number of frames processed since the last reset
+	frame: u64,
+	/// the lowest gain needed by the frames in the look-ahead window,
+	/// increasing from front to back
+	minimum: VecDeque<(u64, f32)>,
+	/// the last minimums, averaged to smooth the gain
+	average: VecDeque<f32>,
+	sum: f64,
+	gain: f32
+}
+
+impl Limiter {
+
+
+	/// the look-ahead time, in milliseconds
+	pub const LOOKAHEAD: f32 = 5.0;
+
+	/// the highest sample rate and number of channels the limiter can
+	/// process without the audio thread allocating
+	const MAX_SAMPLE_RATE: u32 = 192000;
+	const MAX_CHANNELS: usize = 8;
+
+
+	pub fn new (ceiling: f32, release: f32) -> Self {
+		let frames = Self::lookahead_frames(Self::MAX_SAMPLE_RATE);
+		Self {
+			ceiling: Param::new(ceiling),
+			release: Param::new(release),
+			config: None,
+			delay: Vec::with_capacity(frames * Self::MAX_CHANNELS),
+			pos: 0,
+			frame: 0,
+			minimum: VecDeque::with_capacity(frames + 1),
+			average: VecDeque::with_capacity(frames + 1),
+			sum: 0.0,
+			gain: 1.0
+		}
+	}
+
+
+	/// the maximum level of the output, in dBFS
+	pub fn ceiling (&self) -> Param {
+		self.ceiling.clone()
+	}
+
+
+	/// the time the gain takes to recover after a peak, in milliseconds
+	pub fn release (&self) -> Param {
+		self.release.clone()
+	}
+
+
+	fn lookahead_frames (sample_rate: u32) -> usize {
+		(Self::LOOKAHEAD * 0.001 * sample_rate as f32).round() as usize
+	}
+
+
+	/// resize the buffers if the config changed
+	fn configure (&mut self, channels: u16, sample_rate: u32) {
+		if self.config != Some((channels, sample_rate)) {
+			self.config = Some((channels, sample_rate));
+			let frames = Self::lookahead_frames(sample_rate).max(1);
+			self.delay.clear();
+			self.delay.resize(frames * channels as usize, 0.0);
+			self.reset();
+		}
+	}
+
+
+}
+
+impl Default for Limiter {
+	/// a ceiling of -1dBFS and a release of 50ms
+	fn default () -> Self {
+		Self::new(-1.0, 50.0)
+	}
+}
+
+impl Effect for Limiter {
+
+	fn process (&mut self, buffer: &mut [f32], channels: u16, sample_rate: u32) {
+		self.configure(channels, sample_rate);
+
+		let ceiling = db_to_gain(self.ceiling.get());
+		let release = smoothing(self.release.get(), sample_rate);
+		let channels = channels as usize;
+		let frames = self.delay.len() / channels;
+		// the window of the minimum and of the average must both cover
+		// the delay, so every gain averaged for a frame is at most the
+		// gain needed by that frame
+		let window = frames + 1;
+
+		for frame in buffer.chunks_exact_mut(channels) {
+			let peak = frame.iter().fold(0.0f32, |peak, x| peak.max(x.abs()));
+			let needed = if peak > ceiling { ceiling / peak } else { 1.0 };
+
+			while self.minimum.back().is_some_and(|&(_, x)| x >= needed) {
+				self.minimum.pop_back();
+			}
+			self.minimum.push_back((self.frame, needed));
+			while self.minimum.front().is_some_and(|&(n, _)| n + window as u64 <= self.frame) {
+				self.minimum.pop_front();
+			}
+			self.frame += 1;
+
+			let minimum = self.minimum.front().map_or(1.0, |&(_, x)| x);
+			self.average.push_back(minimum);
+			self.sum += minimum as f64;
+			if self.average.len() > window {
+				self.sum -= self.average.pop_front().unwrap_or(1.0) as f64;
+			}
+			// frames before the first one count as a gain of 1
+			let missing = window - self.average.len();
+			let target = ((self.sum + missing as f64) / window as f64) as f32;
+
+			self.gain = if target < self.gain {
+				target
+			} else {
+				target + (self.gain - target) * release
+			};
+
+			let delayed = &mut self.delay[self.pos * channels..][..channels];
+			for (x, d) in frame.iter_mut().zip(delayed.iter_mut()) {
+				let input = *x;
+				*x = *d * self.gain;
+				*d = input;
+			}
+			self.pos = (self.pos + 1) % frames;
+		}
+	}
+
+	fn reset (&mut self) {
+		self.delay.fill(0.0);
+		self.pos = 0;
+		self.frame = 0;
+		self.minimum.clear();
+		self.average.clear();
+		self.sum = 0.0;
+		self.gain = 1.0;
+	}
+
+}
+
+
+
+/// a feed-forward compressor
+///
+/// when the level of the input goes above the threshold, the part
+/// above it is divided by the ratio. the level follows the peaks of
+/// all channels, rising with the attack time and falling with the
+/// release time. the makeup gain is applied after the compression
+///
+/// all settings are [`Param`]s. levels are in dBFS and times are in
+/// milliseconds
+pub struct Compressor {
+	threshold: Param,
+	ratio: Param,
+	attack: Param,
+	release: Param,
+	makeup: Param,
+	/// the detected level, linear
+	envelope: f32
+}
+
+impl Compressor {
+
+
+	pub fn new (threshold: f32, ratio: f32, attack: f32, release: f32) -> Self {
+		Self {
+			threshold: Param::new(threshold),
+			ratio: Param::new(ratio),
+			attack: Param::new(attack),
+			release: Param::new(release),
+			makeup: Param::new(0.0),
+			envelope: 0.0
+		}
+	}
+
+
+	/// the level above which the input is compressed, in dBFS
+	pub fn threshold (&self) -> Param {
+		self.threshold.clone()
+	}
+
+
+	/// how many dB the input must rise above the threshold for the
+	/// output to rise by 1dB
+	pub fn ratio (&self) -> Param {
+		self.ratio.clone()
+	}
+
+
+	/// the time the level takes to follow a louder input, in milliseconds
+	pub fn attack (&self) -> Param {
+		self.attack.clone()
+	}
+
+
+	/// the time the level takes to follow a quieter input, in milliseconds
+	pub fn release (&self) -> Param {
+		self.release.clone()
+	}
+
+
+	/// the gain applied after the compression, in dB, 0 by default
+	pub fn makeup (&self) -> Param {
+		self.makeup.clone()
+	}
+
+
+}
+
+impl Effect for Compressor {
+
+	fn process (&mut self, buffer: &mut [f32], channels: u16, sample_rate: u32) {
+		let threshold = self.threshold.get();
+		let slope = 1.0 - 1.0 / self.ratio.get().max(1.0);
+		let attack = smoothing(self.attack.get(), sample_rate);
+		let release = smoothing(self.release.get(), sample_rate);
+		let makeup = db_to_gain(self.makeup.get());
+
+		for frame in buffer.chunks_exact_mut(channels as usize) {
+			let peak = frame.iter().fold(0.0f32, |peak, x| peak.max(x.abs()));
+			let coefficient = if peak > self.envelope { attack } else { release };
+			self.envelope = peak + (self.envelope - peak) * coefficient;
+
+			let level = 20.0 * self.envelope.max(1e-6).log10();
+			let reduction = (level - threshold).max(0.0) * slope;
+			let gain = db_to_gain(-reduction) * makeup;
+			for x in frame.iter_mut() {
+				*x *= gain;
+			}
+		}
+	}
+
+	fn reset (&mut self) {
+		self.envelope = 0.0;
+	}
+
+}
@@ -0,0 +1,110 @@
+
+
+
+use std::sync::{
+	Arc,
+	atomic::{ AtomicU32, Ordering }
+};
+
+
+
+/// a processor applied to the samples of a sound
+///
+/// effects are run by the audio thread on blocks of interleaved
+/// samples, after the sound was converted to the output config. a
+/// sound or a bus can have a chain of effects, applied in the order
+/// they were added with [`Sound::add_effect`](crate::Sound::add_effect)
+/// or [`Bus::add_effect`](crate::Bus::add_effect)
+pub trait Effect: Send {
+
+	/// process the samples in `buffer` in place
+	///
+	/// `buffer` has `channels` interleaved channels at `sample_rate`,
+	/// and its length is a multiple of `channels`. it is called with
+	/// consecutive blocks of the sound while it plays
+	fn process (&mut self, buffer: &mut [f32], channels: u16, sample_rate: u32);
+
+	/// clear any internal state, like delay lines or filter memory
+	///
+	/// called when the sound is reset or stopped. the effects of a
+	/// [`Bus`](crate::Bus) are never reset
+	fn reset (&mut self) {}
+
+}
+
+impl<T: Effect + ?Sized> Effect for Box<T> {
+
+	fn process (&mut self, buffer: &mut [f32], channels: u16, sample_rate: u32) {
+		(**self).process(buffer, channels, sample_rate)
+	}
+
+	fn reset (&mut self) {
+		(**self).reset()
+	}
+
+}
+
+
+
+/// a value that can be shared between the game thread and an effect
+///
+/// cloning a `Param` gives another handle to the same value. an
+/// effect reads it while processing, and the game can change it at
+/// any time without replacing the effect
+#[derive(Debug, Clone)]
+pub struct Param(Arc<AtomicU32>);
+
+impl Param {
+
+
+	pub fn new (value: f32) -> Self {
+		Self(Arc::new(AtomicU32::new(value.to_bits())))
+	}
+
+
+	/// the current value
+	pub fn get (&self) -> f32 {
+		f32::from_bits(self.0.load(Ordering::Relaxed))
+	}
+
+
+	/// change the value, seen by the effect on its next block
+	pub fn set (&self, value: f32) {
+		self.0.store(value.to_bits(), Ordering::Relaxed);
+	}
+
+
+}
+
+
+
+/// an effect that maps each sample independently
+///
+/// ```
+/// # use audio_engine::{ FnEffect, Param };
+/// let gain = Param::new(0.5);
+/// let effect = {
+///     let gain = gain.clone();
+///     FnEffect::new(move |x| x * gain.get())
+/// };
+/// gain.set(0.25);
+/// ```
+pub struct FnEffect <F: FnMut(f32) -> f32 + Send> (F);
+
+impl <F: FnMut(f32) -> f32 + Send> FnEffect<F> {
+
+	pub fn new (f: F) -> Self {
+		Self(f)
+	}
+
+}
+
+impl <F: FnMut(f32) -> f32 + Send> Effect for FnEffect<F> {
+
+	fn process (&mut self, buffer: &mut [f32], _: u16, _: u32) {
+		for x in buffer.iter_mut() {
+			*x = (self.0)(*x);
+		}
+	}
+
+}
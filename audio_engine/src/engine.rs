@@ -7,11 +7,16 @@ use cpal::{
 	traits::{ DeviceTrait, HostTrait, StreamTrait }
 };
 
-use std::sync::{ Arc, Mutex };
+use std::time::Duration;
+use std::sync::{ Arc, Mutex, mpsc::Receiver };
 
+use crate::bus::{ Bus, BusInner };
+use crate::dynamics::Limiter;
+use crate::event::Event;
+use crate::spatial::Listener;
 use crate::mixer;
-use crate::mixer::{ Mixer, Sound, SoundSource };
-use crate::converter::{ ChannelConverter, SampleRateConverter };
+use crate::mixer::{ Command, CommandSender, Mixer, MixerState, Sound, SoundSource, StealPolicy };
+use crate::converter::{ ChannelMatrix, Conversion, ResampleQuality };
 
 
 
@@ -21,6 +26,7 @@ mod backend {
 
 
 	use super::create_device;
+	use crate::event::Event;
 	use crate::mixer::Mixer;
 	use std::sync::{ Arc, Mutex };
 
@@ -57,6 +63,7 @@ mod backend {
 				match event {
 					StreamEvent::RecreateStream => {
 						log::debug!("recreating audio device");
+						let recreated = self.stream.is_some();
 
 						// https://github.com/Rodrigodd/audio-engine/blob/3d0da3711b5cc78e7192d616ebb1d4069920707d/src/engine.rs#L47
 						// Droping the stream is unsound in android, see:
@@ -76,6 +83,14 @@ mod backend {
 							}
 						};
 						self.stream = Some(stream);
+
+						if recreated {
+							let mixer = self.mixer.lock().unwrap();
+							mixer.send_event(Event::DeviceChanged {
+								channels: mixer.channels,
+								sample_rate: mixer.sample_rate.0
+							});
+						}
 					},
 					StreamEvent::Drop => return
 				}
@@ -142,11 +157,25 @@ mod backend {
 
 /// The main struct of the crate
 ///
-/// This holds all existing Wav Sources and `cpal::platform::Stream`
+/// This holds the mixer with all existing sounds and buses, and the
+/// `cpal::platform::Stream` it plays on, if it isn't offline
+///
+/// the mixer is only locked by the audio thread, and by
+/// [`render`](AudioEngine::render) in offline mode. everything else
+/// talks to it through a command channel
 pub struct AudioEngine {
 
 	mixer: Arc<Mutex<Mixer>>,
-	_backend: Backend
+	sender: CommandSender,
+	state: Arc<MixerState>,
+	master: Bus,
+	/// how new sounds are converted to the output config, a copy is
+	/// sent to the mixer when it changes
+	conversion: Mutex<Conversion>,
+	/// `None` after the events were given to a callback
+	events: Mutex<Option<Receiver<Event>>>,
+	/// `None` when the engine was created with [`AudioEngine::new_offline`]
+	backend: Option<Backend>
 
 }
 
@@ -157,20 +186,141 @@ impl AudioEngine {
 	///
 	/// `cpal` will spawn a new thread where the sound samples will
 	/// be sampled, mixed and outputed to the output stream
+	///
+	/// the master bus starts with a [`Limiter::default`], so loud
+	/// mixes are not clipped by the device. an
+	/// [offline](AudioEngine::new_offline) engine doesn't have it
 	pub fn new () -> Result<Self, &'static str> {
 		let mixer = Arc::new(Mutex::new(Mixer::new(2, mixer::SampleRate(48000)))); // 48k sample rate
+		let (sender, state, master, events) = {
+			let mut mixer = mixer.lock().unwrap();
+			(mixer.sender(), mixer.state(), mixer.master(), mixer.take_events())
+		};
 		let backend = Backend::start(mixer.clone())?;
+		let master = Bus::new(sender.clone(), master, "master");
+		master.add_effect(Limiter::default());
 
 		Ok(Self {
 			mixer,
-			_backend: backend
+			master,
+			sender,
+			state,
+			conversion: Mutex::new(Conversion::default()),
+			events: Mutex::new(events),
+			backend: Some(backend)
+		})
+	}
+
+
+	/// create a new Audio Engine without an output device
+	///
+	/// no thread is spawned, samples are only mixed when
+	/// [`render`](AudioEngine::render) is called, so the output is
+	/// deterministic and works on machines without any audio device.
+	///
+	/// unlike [`new`](AudioEngine::new), the master bus has no effects,
+	/// so the output is exactly the mix of the sounds. add the same
+	/// limiter to render what a device would play:
+	///
+	/// ```
+	/// # use audio_engine::{ AudioEngine, Limiter };
+	/// let engine = AudioEngine::new_offline(2, 48000);
+	/// engine.master().add_effect(Limiter::default());
+	/// ```
+	///
+	/// the mixer only applies commands when rendering, and keeps up to
+	/// 1024 of them in between, later ones are dropped
+	pub fn new_offline (channels: u16, sample_rate: u32) -> Self {
+		let mut mixer = Mixer::new(channels, mixer::SampleRate(sample_rate));
+		Self {
+			master: Bus::new(mixer.sender(), mixer.master(), "master"),
+			sender: mixer.sender(),
+			state: mixer.state(),
+			events: Mutex::new(mixer.take_events()),
+			mixer: Arc::new(Mutex::new(mixer)),
+			conversion: Mutex::new(Conversion::default()),
+			backend: None
+		}
+	}
+
+
+	/// return true if the engine was created with
+	/// [`new_offline`](AudioEngine::new_offline)
+	pub fn is_offline (&self) -> bool {
+		self.backend.is_none()
+	}
+
+
+	/// mix the next `buffer.len() / channels` frames into `buffer`
+	///
+	/// samples are interleaved and not clamped. return a `Err` if the engine is
+	/// outputing to a device, or if the length of `buffer` is not a
+	/// multiple of the number of channels
+	pub fn render (&self, buffer: &mut [f32]) -> Result<(), &'static str> {
+		if !self.is_offline() {
+			return Err("only an offline engine can be rendered");
+		}
+		let mut mixer = self.mixer.lock().unwrap();
+		if !buffer.len().is_multiple_of(mixer.channels as usize) {
+			return Err("buffer length is not a multiple of the number of channels");
+		}
+		mixer.write_samples(buffer);
+		Ok(())
+	}
+
+
+	/// mix the next `frames` frames, see [`render`](AudioEngine::render)
+	pub fn render_frames (&self, frames: usize) -> Result<Vec<f32>, &'static str> {
+		let mut buffer = vec![0.0; frames * self.channels() as usize];
+		self.render(&mut buffer)?;
+		Ok(buffer)
+	}
+
+
+	/// mix the next `frames` frames and write them to a 32bit float
+	/// wav file at `path`, see [`render`](AudioEngine::render)
+	pub fn render_to_wav <P: AsRef<std::path::Path>> (&self, path: P, frames: usize) -> Result<(), &'static str> {
+		const WRITE_FRAMES: usize = 4096;
+
+		if !self.is_offline() {
+			return Err("only an offline engine can be rendered");
+		}
+
+		let spec = hound::WavSpec {
+			channels: self.channels(),
+			sample_rate: self.sample_rate(),
+			bits_per_sample: 32,
+			sample_format: hound::SampleFormat::Float
+		};
+		let mut writer = hound::WavWriter::create(path, spec).map_err(|err| {
+			log::error!("failed to create wav file: {}", err);
+			"failed to create wav file"
+		})?;
+
+		let mut buffer = vec![0.0; WRITE_FRAMES * spec.channels as usize];
+		let mut remaining = frames;
+		while remaining > 0 {
+			let len = remaining.min(WRITE_FRAMES) * spec.channels as usize;
+			self.render(&mut buffer[..len])?;
+			for &sample in &buffer[..len] {
+				writer.write_sample(sample).map_err(|err| {
+					log::error!("failed to write wav file: {}", err);
+					"failed to write wav file"
+				})?;
+			}
+			remaining -= len / spec.channels as usize;
+		}
+
+		writer.finalize().map_err(|err| {
+			log::error!("failed to write wav file: {}", err);
+			"failed to write wav file"
 		})
 	}
 
 
 	/// the sample rate that is currently being outputed to the device
 	pub fn sample_rate(&self) -> u32 {
-		self.mixer.lock().unwrap().sample_rate()
+		self.state.sample_rate()
 	}
 
 
@@ -178,58 +328,204 @@ impl AudioEngine {
 	///
 	/// may change when device changes
 	pub fn channels (&self) -> u16 {
-		self.mixer.lock().unwrap().channels()
+		self.state.channels()
+	}
+
+
+	/// the number of frames mixed since the engine was created
+	///
+	/// when the sample rate changes, the clock is converted to the new
+	/// rate, so it keeps the same [time](AudioEngine::time)
+	pub fn clock (&self) -> u64 {
+		self.state.clock()
+	}
+
+
+	/// the time mixed since the engine was created, the reference of
+	/// the times given to [`Sound::at`]
+	///
+	/// this advances by one buffer of the device at a time, so add some
+	/// delay to it to schedule a sound precisely
+	pub fn time (&self) -> Duration {
+		Duration::from_secs_f64(self.clock() as f64 / self.sample_rate() as f64)
 	}
 
 
-	/// create a new sound
+	/// use `matrix` instead of the standard mix when converting a
+	/// sound from `matrix.inputs()` to `matrix.outputs()` channels
 	///
-	/// Return a `Err` if the number of channels doesn't match the
-	/// output number of channels. If the output number of channels
-	/// of `source` is 1, `source` will be automatic wrapped in a
-	/// [`ChannelConverter`]
+	/// this applies to sounds created after this call, and to all
+	/// sounds converted when the output device changes. mono sounds
+	/// converted with a user matrix are not panned
+	pub fn set_channel_matrix (&self, matrix: ChannelMatrix) {
+		let mut conversion = self.conversion.lock().unwrap();
+		conversion.set_matrix(matrix);
+		self.sender.send(Command::SetConversion(conversion.clone()));
+	}
+
+
+	/// set the interpolation used to resample sounds to the output
+	/// sample rate
+	///
+	/// this applies to sounds created after this call, and to all
+	/// sounds converted when the output device changes. a single
+	/// sound can use a different quality by wrapping it in a
+	/// [`SampleRateConverter`](crate::SampleRateConverter) before
+	/// calling [`new_sound`](AudioEngine::new_sound). the default
+	/// is [`ResampleQuality::Linear`]
+	pub fn set_resample_quality (&self, quality: ResampleQuality) {
+		let mut conversion = self.conversion.lock().unwrap();
+		conversion.quality = quality;
+		self.sender.send(Command::SetConversion(conversion.clone()));
+	}
+
+
+	/// fade `from` out and stop it, while `to` fades in, both over
+	/// `duration`
+	///
+	/// both fades start on the same sample, see
+	/// [`Sound::stop_with_fade`] and [`Sound::fade_in`]
+	pub fn crossfade (&self, from: &mut Sound, to: &mut Sound, duration: Duration) {
+		self.sender.send(Command::Crossfade(from.id(), to.id(), duration));
+	}
+
+
+	/// take the next event of the mixer, if there is one
+	///
+	/// the mixer keeps up to 256 events until they are taken, later
+	/// ones are dropped. this returns `None` after
+	/// [`set_event_callback`](AudioEngine::set_event_callback)
+	///
+	/// ```no_run
+	/// # let engine = audio_engine::AudioEngine::new().unwrap();
+	/// // once per frame of the game
+	/// while let Some(event) = engine.poll_event() {
+	///     println!("{:?}", event);
+	/// }
+	/// ```
+	pub fn poll_event (&self) -> Option<Event> {
+		// called on every frame of the game, even if it sends nothing
+		self.sender.collect();
+		self.events.lock().unwrap().as_ref()?.try_recv().ok()
+	}
+
+
+	/// call `callback` with every event of the mixer, on a new thread
+	///
+	/// the callback replaces [`poll_event`](AudioEngine::poll_event),
+	/// and can only be set once. the thread ends with the engine
+	pub fn set_event_callback (&self, mut callback: impl FnMut(Event) + Send + 'static) -> Result<(), &'static str> {
+		let events = self.events.lock().unwrap().take().ok_or("the event callback is already set")?;
+		std::thread::spawn(move || {
+			for event in events {
+				callback(event);
+			}
+		});
+		Ok(())
+	}
+
+
+	/// move the point positional sounds are heard from
+	pub fn set_listener (&self, listener: Listener) {
+		self.sender.send(Command::SetListener(listener));
+	}
+
+
+	/// limit how many sounds can play at once, or remove the limit with
+	/// `None`, which is the default
+	///
+	/// when a sound starts to play over this limit, or over the
+	/// [instance limit](crate::SoundSource::instance_limit) of its
+	/// source, another sound is chosen by the
+	/// [steal policy](AudioEngine::set_steal_policy) and faded out over
+	/// 20ms, with an [`Event::Stolen`]. if not enough sounds that could
+	/// be stopped have a low enough [priority](Sound::set_priority), no
+	/// sound is stopped and the new sound doesn't play instead, with an
+	/// [`Event::Rejected`]. sounds fading out don't count in the limits
+	pub fn set_max_voices (&self, max: Option<usize>) {
+		self.sender.send(Command::SetMaxVoices(max));
+	}
+
+
+	/// choose which sound is stopped for a new one over a voice limit,
+	/// [`StealPolicy::Oldest`] by default
+	pub fn set_steal_policy (&self, policy: StealPolicy) {
+		self.sender.send(Command::SetStealPolicy(policy));
+	}
+
+
+	/// the bus all sounds and buses are eventually mixed into
+	pub fn master (&self) -> &Bus {
+		&self.master
+	}
+
+
+	/// create a new bus mixed into `parent`, or into the
+	/// [master](AudioEngine::master) bus if it is `None`
+	pub fn new_bus (&self, name: &str, parent: Option<&Bus>) -> Bus {
+		let inner = BusInner::new(mixer::next_id(), self.state.channels());
+		let bus = Bus::new(self.sender.clone(), inner.id, name);
+		let parent = parent.unwrap_or(&self.master).id();
+		self.sender.send(Command::AddBus(Box::new(inner), parent));
+		bus
+	}
+
+
+	/// create a new sound, mixed into `bus` or into the
+	/// [master](AudioEngine::master) bus if it is `None`
+	///
+	/// Return a `Err` if `source` has no channels, or if the mixer has
+	/// more commands queued than it can hold. If the number of
+	/// channels of `source` doesn't match the output number of
+	/// channels, `source` will be automatic wrapped in a
+	/// [`ChannelConverter`](crate::ChannelConverter), using the matrix
+	/// set with [`set_channel_matrix`](AudioEngine::set_channel_matrix)
+	/// or the [standard](ChannelMatrix::standard) one. mono and
+	/// stereo sources are first panned to stereo, see
+	/// [`Sound::set_pan`], so on a surround output they play from the
+	/// front speakers
 	///
 	/// if the `sample_rate` of `source` mismatch the output
 	/// `sample_rate`, `source` will be wrapped in a
-	/// [`SampleRateConverter`]
+	/// [`SampleRateConverter`](crate::SampleRateConverter)
 	pub fn new_sound <T: SoundSource + Send + 'static> (
 		&self,
 		source: T,
-		effect: impl FnMut(f32) -> f32 + 'static + std::marker::Send
+		bus: Option<&Bus>
 	) -> Result<Sound, &'static str> {
-		let mut mixer = self.mixer.lock().unwrap();
-
-		let sound: Box<dyn SoundSource + Send> = if source.sample_rate() != mixer.sample_rate.0 {
-			if source.channels() == mixer.channels {
-				Box::new(SampleRateConverter::new(source, mixer.sample_rate.0))
-			} else if mixer.channels == 1 || source.channels() == 1 {
-				Box::new(ChannelConverter::new(
-					SampleRateConverter::new(source, mixer.sample_rate.0),
-					mixer.channels
-				))
-			} else {
-				return Err("Number of channels do not match the output, and neither are 1");
-			}
-		} else if source.channels() == mixer.channels {
-			Box::new(source)
-		} else if mixer.channels == 1 || source.channels() == 1 {
-			Box::new(ChannelConverter::new(source, mixer.channels))
-		} else {
-			return Err("Number of channels do not match the output, and is not 1");
-		};
+		if source.channels() == 0 {
+			return Err("source has no channels");
+		}
 
-		let id = mixer.add_sound(sound, effect);
-		drop(mixer);
+		// asked before `source` is wrapped, so the wrappers don't forward it
+		let instances = source.instance_limit();
+		let (sound, mut inner) = Sound::new(self.sender.clone(), Box::new(source), instances);
+		inner.conform(
+			self.state.channels(),
+			self.state.sample_rate(),
+			&self.conversion.lock().unwrap()
+		);
+		let bus = bus.unwrap_or(&self.master).id();
+		if !self.sender.send(Command::Add(inner, bus)) {
+			return Err("the command queue of the mixer is full");
+		}
 
-		Ok(Sound {
-			mixer: self.mixer.clone(),
-			id
-		})
+		Ok(sound)
 	}
 
 
 }
 
+impl Drop for AudioEngine {
+	fn drop (&mut self) {
+		// on android the stream is never dropped, and neither is the
+		// mixer it holds, so the event callback thread is ended here
+		if let Ok(mut mixer) = self.mixer.lock() {
+			mixer.close_events();
+		}
+	}
+}
+
 
 
 fn create_device (
@@ -330,18 +626,30 @@ fn stream <T: cpal::Sample, E: FnMut(StreamError) + Send + 'static> (
 ) -> Result<cpal::Stream, cpal::BuildStreamError> {
 
 	let mixer = mixer.clone();
-	let mut input_buffer = Vec::new();
+	// the device buffer size is unknown, so mix in blocks of a buffer
+	// allocated here instead of in the callback
+	let mut input_buffer = vec![0.0; mixer::BLOCK_FRAMES * config.channels as usize];
 	device.build_output_stream(
 		config,
 		move |output_buffer: &mut [T], _| {
-			input_buffer.clear();
-			input_buffer.resize(output_buffer.len(), 0);
-			mixer.lock().unwrap().write_samples(&mut input_buffer);
-			// write sample to output buffer
-			output_buffer
-				.iter_mut()
-				.zip(input_buffer.iter())
-				.for_each(|(a, b)| *a = T::from(b));
+			// the mixer is only locked elsewhere while the device is
+			// being recreated, never wait for it in the callback
+			let mut mixer = mixer.try_lock();
+			for output in output_buffer.chunks_mut(input_buffer.len()) {
+				let input = &mut input_buffer[..output.len()];
+				match mixer {
+					Ok(ref mut mixer) => {
+						mixer.write_samples(input);
+					},
+					Err(_) => input.fill(0.0)
+				}
+				// convert the mix to the device format, this is the only
+				// place where the samples are clamped
+				output
+					.iter_mut()
+					.zip(input.iter())
+					.for_each(|(a, b)| *a = T::from(&b.clamp(-1.0, 1.0)));
+			}
 		},
 		error_callback
 	)
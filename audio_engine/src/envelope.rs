@@ -0,0 +1,189 @@
+
+
+
+use crate::error::SourceError;
+use crate::mixer::SoundSource;
+
+use std::time::Duration;
+
+
+
+/// an attack, decay, sustain and release envelope
+///
+/// the gain rises from 0 to 1 over `attack`, falls to `sustain` over
+/// `decay`, and stays there while the note is held. when the note is
+/// released, it falls from where it is to 0 over `release`, even if the
+/// attack or the decay were not done
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Adsr {
+	pub attack: Duration,
+	pub decay: Duration,
+	/// the gain while the note is held, after the decay
+	pub sustain: f32,
+	pub release: Duration
+}
+
+impl Default for Adsr {
+	/// no envelope, a gain of 1 until the note is released
+	fn default () -> Self {
+		Self {
+			attack: Duration::ZERO,
+			decay: Duration::ZERO,
+			sustain: 1.0,
+			release: Duration::ZERO
+		}
+	}
+}
+
+impl Adsr {
+
+
+	pub fn new (attack: Duration, decay: Duration, sustain: f32, release: Duration) -> Self {
+		Self { attack, decay, sustain, release }
+	}
+
+
+	/// the gain `time` seconds after the start of a note released after
+	/// `hold` seconds
+	pub fn gain (&self, time: f64, hold: f64) -> f32 {
+		if time < hold {
+			return self.held(time);
+		}
+		let release = self.release.as_secs_f64();
+		let released = time - hold;
+		if released >= release {
+			0.0
+		} else {
+			self.held(hold) * (1.0 - released / release) as f32
+		}
+	}
+
+
+	/// the length of a note released after `hold`, up to the end of
+	/// the release
+	pub fn duration (&self, hold: Duration) -> Duration {
+		hold + self.release
+	}
+
+
+	/// the gain `time` seconds after the start of a note still held
+	fn held (&self, time: f64) -> f32 {
+		let attack = self.attack.as_secs_f64();
+		let decay = self.decay.as_secs_f64();
+		if time < attack {
+			(time / attack) as f32
+		} else if time < attack + decay {
+			1.0 - (1.0 - self.sustain) * ((time - attack) / decay) as f32
+		} else {
+			self.sustain
+		}
+	}
+
+
+}
+
+
+
+/// a source played through an [`Adsr`] envelope, as a note held for
+/// a given time
+///
+/// the source ends when the release of the envelope is done, or before
+/// if the inner source ends
+pub struct Envelope <T: SoundSource> {
+	inner: T,
+	adsr: Adsr,
+	/// seconds before the release
+	hold: f64,
+	/// the frames from the start to the end of the release
+	len: u64,
+	/// the next frame to be written
+	position: u64
+}
+
+impl <T: SoundSource> Envelope<T> {
+
+
+	pub fn new (inner: T, adsr: Adsr, hold: Duration) -> Self {
+		let len = (adsr.duration(hold).as_secs_f64() * inner.sample_rate() as f64).round() as u64;
+		Self {
+			inner,
+			adsr,
+			hold: hold.as_secs_f64(),
+			len,
+			position: 0
+		}
+	}
+
+
+	pub fn adsr (&self) -> Adsr {
+		self.adsr
+	}
+
+
+	pub fn into_inner (self) -> T {
+		self.inner
+	}
+
+
+}
+
+impl <T: SoundSource> SoundSource for Envelope<T> {
+
+
+	fn channels (&self) -> u16 {
+		self.inner.channels()
+	}
+
+
+	fn sample_rate (&self) -> u32 {
+		self.inner.sample_rate()
+	}
+
+
+	fn reset (&mut self) {
+		self.inner.reset();
+		self.position = 0;
+	}
+
+
+	fn write_samples (&mut self, buffer: &mut [f32]) -> usize {
+
+		let channels = self.inner.channels() as usize;
+		let frames = (self.len - self.position).min((buffer.len() / channels) as u64) as usize;
+		let len = self.inner.write_samples(&mut buffer[..frames * channels]);
+
+		let sample_rate = self.inner.sample_rate() as f64;
+		for (i, frame) in buffer[..len].chunks_exact_mut(channels).enumerate() {
+			let time = (self.position + i as u64) as f64 / sample_rate;
+			let gain = self.adsr.gain(time, self.hold);
+			frame.iter_mut().for_each(|x| *x *= gain);
+		}
+		self.position += (len / channels) as u64;
+		len
+
+	}
+
+
+	fn seek (&mut self, frame: u64) -> Result<(), SourceError> {
+		self.inner.seek(frame)?;
+		self.position = frame.min(self.len);
+		Ok(())
+	}
+
+
+	fn len_frames (&self) -> Option<u64> {
+		Some(self.inner.len_frames().map_or(self.len, |len| len.min(self.len)))
+	}
+
+
+	fn position (&self) -> Option<u64> {
+		Some(self.position)
+	}
+
+
+	fn take_error (&mut self) -> Option<SourceError> {
+		self.inner.take_error()
+	}
+
+
+}
@@ -0,0 +1,85 @@
+
+
+
+use std::fmt;
+
+
+
+/// an error of a [`SoundSource`](crate::SoundSource)
+///
+/// a source reports the error that ended it with
+/// [`take_error`](crate::SoundSource::take_error). the mixer then stops
+/// the sound, even if it loops, sends an
+/// [`Event::DecoderError`](crate::Event::DecoderError) and keeps the
+/// error in [`Sound::error`](crate::Sound::error)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourceError {
+	/// reading the data failed
+	Io,
+	/// the data is not valid for its format
+	Malformed,
+	/// the format, or what was asked to the source, is not supported
+	Unsupported,
+	/// any other error
+	Other
+}
+
+impl SourceError {
+
+
+	/// a non zero code, so the error fits in an atomic
+	pub(crate) fn code (self) -> u8 {
+		match self {
+			SourceError::Io => 1,
+			SourceError::Malformed => 2,
+			SourceError::Unsupported => 3,
+			SourceError::Other => 4
+		}
+	}
+
+
+	/// the error of a `code`, or `None` for 0
+	pub(crate) fn from_code (code: u8) -> Option<Self> {
+		match code {
+			0 => None,
+			1 => Some(SourceError::Io),
+			2 => Some(SourceError::Malformed),
+			3 => Some(SourceError::Unsupported),
+			_ => Some(SourceError::Other)
+		}
+	}
+
+
+}
+
+impl fmt::Display for SourceError {
+	fn fmt (&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str(match self {
+			SourceError::Io => "failed to read the sound data",
+			SourceError::Malformed => "the sound data is malformed",
+			SourceError::Unsupported => "not supported by the sound source",
+			SourceError::Other => "the sound source failed"
+		})
+	}
+}
+
+impl std::error::Error for SourceError {}
+
+impl From<hound::Error> for SourceError {
+	fn from (err: hound::Error) -> Self {
+		match err {
+			hound::Error::IoError(_) => SourceError::Io,
+			hound::Error::Unsupported => SourceError::Unsupported,
+			_ => SourceError::Malformed
+		}
+	}
+}
+
+impl From<lewton::VorbisError> for SourceError {
+	fn from (err: lewton::VorbisError) -> Self {
+		match err {
+			lewton::VorbisError::OggError(lewton::OggReadError::ReadError(_)) => SourceError::Io,
+			_ => SourceError::Malformed
+		}
+	}
+}
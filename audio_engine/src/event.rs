@@ -0,0 +1,36 @@
+
+
+
+use crate::error::SourceError;
+use crate::mixer::SoundId;
+
+use std::time::Duration;
+
+
+
+/// something that happened in the mixer, see
+/// [`AudioEngine::poll_event`](crate::AudioEngine::poll_event)
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+	/// the sound reached its end and stopped
+	Finished(SoundId),
+	/// the sound reached the end of its loop region and went back to
+	/// its start, at most once per mixed block
+	Looped(SoundId),
+	/// the sound played the frame of a [marker](crate::Sound::add_marker)
+	Marker(SoundId, Duration),
+	/// the sound was faded out to play another one over a
+	/// [voice limit](crate::AudioEngine::set_max_voices)
+	Stolen(SoundId),
+	/// the sound didn't start because it was over a
+	/// [voice limit](crate::AudioEngine::set_max_voices), and not enough
+	/// playing sounds had a low enough priority to be stolen for it
+	Rejected(SoundId),
+	/// the source of the sound failed, and the sound stopped
+	DecoderError(SoundId, SourceError),
+	/// the output device was recreated, with a new config
+	DeviceChanged {
+		channels: u16,
+		sample_rate: u32
+	}
+}
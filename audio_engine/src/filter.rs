@@ -0,0 +1,256 @@
+
+
+
+use crate::effect::{ Effect, Param };
+
+use std::f64::consts::{ FRAC_1_SQRT_2, TAU };
+
+
+
+/// the response of a [`Biquad`] filter
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterKind {
+	/// keep the frequencies below the cutoff
+	LowPass,
+	/// keep the frequencies above the cutoff
+	HighPass,
+	/// keep the frequencies around the cutoff, with a peak gain of 0dB
+	BandPass,
+	/// remove the frequencies around the cutoff
+	Notch,
+	/// boost or cut the frequencies below the cutoff by the gain
+	LowShelf,
+	/// boost or cut the frequencies above the cutoff by the gain
+	HighShelf
+}
+
+
+
+/// the normalized coefficients of the filter, with `a0` equal to 1
+#[derive(Debug, Clone, Copy, Default)]
+struct Coefficients {
+	b0: f32,
+	b1: f32,
+	b2: f32,
+	a1: f32,
+	a2: f32
+}
+
+impl Coefficients {
+
+
+	/// the coefficients from the audio eq cookbook by robert bristow-johnson
+	fn new (kind: FilterKind, cutoff: f32, q: f32, gain: f32, sample_rate: u32) -> Self {
+		let nyquist = sample_rate as f64 / 2.0;
+		let cutoff = (cutoff as f64).clamp(Biquad::MIN_CUTOFF, nyquist * 0.98);
+		let q = (q as f64).max(Biquad::MIN_Q);
+
+		let w0 = TAU * cutoff / sample_rate as f64;
+		let (sin, cos) = w0.sin_cos();
+		let alpha = sin / (2.0 * q);
+		let a = 10f64.powf(gain as f64 / 40.0);
+		let shelf = 2.0 * a.sqrt() * alpha;
+
+		let (b0, b1, b2, a0, a1, a2) = match kind {
+			FilterKind::LowPass => (
+				(1.0 - cos) / 2.0, 1.0 - cos, (1.0 - cos) / 2.0,
+				1.0 + alpha, -2.0 * cos, 1.0 - alpha
+			),
+			FilterKind::HighPass => (
+				(1.0 + cos) / 2.0, -(1.0 + cos), (1.0 + cos) / 2.0,
+				1.0 + alpha, -2.0 * cos, 1.0 - alpha
+			),
+			FilterKind::BandPass => (
+				alpha, 0.0, -alpha,
+				1.0 + alpha, -2.0 * cos, 1.0 - alpha
+			),
+			FilterKind::Notch => (
+				1.0, -2.0 * cos, 1.0,
+				1.0 + alpha, -2.0 * cos, 1.0 - alpha
+			),
+			FilterKind::LowShelf => (
+				a * ((a + 1.0) - (a - 1.0) * cos + shelf),
+				2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+				a * ((a + 1.0) - (a - 1.0) * cos - shelf),
+				(a + 1.0) + (a - 1.0) * cos + shelf,
+				-2.0 * ((a - 1.0) + (a + 1.0) * cos),
+				(a + 1.0) + (a - 1.0) * cos - shelf
+			),
+			FilterKind::HighShelf => (
+				a * ((a + 1.0) + (a - 1.0) * cos + shelf),
+				-2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+				a * ((a + 1.0) + (a - 1.0) * cos - shelf),
+				(a + 1.0) - (a - 1.0) * cos + shelf,
+				2.0 * ((a - 1.0) - (a + 1.0) * cos),
+				(a + 1.0) - (a - 1.0) * cos - shelf
+			)
+		};
+
+		Self {
+			b0: (b0 / a0) as f32,
+			b1: (b1 / a0) as f32,
+			b2: (b2 / a0) as f32,
+			a1: (a1 / a0) as f32,
+			a2: (a2 / a0) as f32
+		}
+	}
+
+
+}
+
+
+
+/// a second order iir filter effect
+///
+/// the cutoff frequency, in Hz, the quality factor and the gain, in
+/// dB, are [`Param`]s, so they can be changed while the sound plays.
+/// the coefficients are updated at the start of each block processed
+/// by the mixer. the gain is only used by the shelving filters
+///
+/// ```
+/// # use audio_engine::Biquad;
+/// let filter = Biquad::low_pass(20000.0, 0.7);
+/// let cutoff = filter.cutoff();
+/// // sound.add_effect(filter);
+/// // muffle the sound
+/// cutoff.set(500.0);
+/// ```
+pub struct Biquad {
+	kind: FilterKind,
+	cutoff: Param,
+	q: Param,
+	gain: Param,
+	/// the parameters the coefficients were computed for
+	current: Option<(f32, f32, f32, u32)>,
+	coefficients: Coefficients,
+	/// the two state variables of each channel
+	state: Vec<[f32; 2]>
+}
+
+impl Biquad {
+
+
+	/// the lowest cutoff frequency, in Hz
+	pub const MIN_CUTOFF: f64 = 10.0;
+
+	/// the lowest quality factor
+	pub const MIN_Q: f64 = 0.01;
+
+	/// the number of channels the filter can process without the audio
+	/// thread allocating
+	const STATE_CAPACITY: usize = 8;
+
+
+	/// a filter with a gain of 0dB
+	pub fn new (kind: FilterKind, cutoff: f32, q: f32) -> Self {
+		Self::with_gain(kind, cutoff, q, 0.0)
+	}
+
+
+	/// a filter with a given gain, in dB
+	pub fn with_gain (kind: FilterKind, cutoff: f32, q: f32, gain: f32) -> Self {
+		Self {
+			kind,
+			cutoff: Param::new(cutoff),
+			q: Param::new(q),
+			gain: Param::new(gain),
+			current: None,
+			coefficients: Coefficients::default(),
+			state: Vec::with_capacity(Self::STATE_CAPACITY)
+		}
+	}
+
+
+	pub fn low_pass (cutoff: f32, q: f32) -> Self {
+		Self::new(FilterKind::LowPass, cutoff, q)
+	}
+
+
+	pub fn high_pass (cutoff: f32, q: f32) -> Self {
+		Self::new(FilterKind::HighPass, cutoff, q)
+	}
+
+
+	pub fn band_pass (cutoff: f32, q: f32) -> Self {
+		Self::new(FilterKind::BandPass, cutoff, q)
+	}
+
+
+	pub fn notch (cutoff: f32, q: f32) -> Self {
+		Self::new(FilterKind::Notch, cutoff, q)
+	}
+
+
+	/// a low shelf with the maximum slope that doesn't overshoot
+	pub fn low_shelf (cutoff: f32, gain: f32) -> Self {
+		Self::with_gain(FilterKind::LowShelf, cutoff, FRAC_1_SQRT_2 as f32, gain)
+	}
+
+
+	/// a high shelf with the maximum slope that doesn't overshoot
+	pub fn high_shelf (cutoff: f32, gain: f32) -> Self {
+		Self::with_gain(FilterKind::HighShelf, cutoff, FRAC_1_SQRT_2 as f32, gain)
+	}
+
+
+	pub fn kind (&self) -> FilterKind {
+		self.kind
+	}
+
+
+	/// the cutoff or center frequency, in Hz
+	pub fn cutoff (&self) -> Param {
+		self.cutoff.clone()
+	}
+
+
+	/// the quality factor, higher values give a narrower band or a
+	/// resonant peak at the cutoff
+	pub fn q (&self) -> Param {
+		self.q.clone()
+	}
+
+
+	/// the gain of the shelving filters, in dB
+	pub fn gain (&self) -> Param {
+		self.gain.clone()
+	}
+
+
+	/// recompute the coefficients if a parameter changed
+	fn update (&mut self, sample_rate: u32) {
+		let params = (self.cutoff.get(), self.q.get(), self.gain.get(), sample_rate);
+		if self.current != Some(params) {
+			self.current = Some(params);
+			self.coefficients = Coefficients::new(self.kind, params.0, params.1, params.2, sample_rate);
+		}
+	}
+
+
+}
+
+impl Effect for Biquad {
+
+	fn process (&mut self, buffer: &mut [f32], channels: u16, sample_rate: u32) {
+		self.update(sample_rate);
+		if self.state.len() != channels as usize {
+			self.state.resize(channels as usize, [0.0; 2]);
+		}
+
+		// transposed direct form II
+		let Coefficients { b0, b1, b2, a1, a2 } = self.coefficients;
+		for frame in buffer.chunks_exact_mut(channels as usize) {
+			for (x, s) in frame.iter_mut().zip(self.state.iter_mut()) {
+				let y = b0 * *x + s[0];
+				s[0] = b1 * *x - a1 * y + s[1];
+				s[1] = b2 * *x - a2 * y;
+				*x = y;
+			}
+		}
+	}
+
+	fn reset (&mut self) {
+		self.state.fill([0.0; 2]);
+	}
+
+}
@@ -4,12 +4,57 @@
 mod wav;
 pub use wav::WavDecoder;
 
+mod ogg;
+pub use ogg::OggDecoder;
+
+mod buffer;
+pub use buffer::{ BufferSource, SoundBuffer };
+
+mod oscillator;
+pub use oscillator::{ GENERATOR_SAMPLE_RATE, Noise, NoiseKind, Oscillator, Silence, Waveform };
+
+mod envelope;
+pub use envelope::{ Adsr, Envelope };
+
+mod sfx;
+pub use sfx::{ Sfx, SfxParams, SfxWave };
+
+mod tracker;
+pub use tracker::{ ModuleDecoder, ModuleFormat, ModuleHandle, ModulePosition };
+
 mod engine;
 pub use engine::AudioEngine;
 
 mod converter;
+pub use converter::{ ChannelConverter, ChannelMatrix, ResampleQuality, SampleRateConverter };
+
+mod effect;
+pub use effect::{ Effect, FnEffect, Param };
+
+mod filter;
+pub use filter::{ Biquad, FilterKind };
+
+mod dynamics;
+pub use dynamics::{ Compressor, Limiter };
 
 mod mixer;
+pub use mixer::{ Schedule, Sound, SoundId, SoundSource, StealPolicy };
+
+mod event;
+pub use event::Event;
+
+mod error;
+pub use error::SourceError;
+
+mod bus;
+pub use bus::Bus;
+
+mod pan;
+
+mod looping;
+
+mod spatial;
+pub use spatial::{ Attenuation, Listener, Spatial };
 
 pub use cpal;
 
@@ -0,0 +1,198 @@
+
+
+
+use crate::error::SourceError;
+use crate::mixer::SoundSource;
+
+use std::sync::Arc;
+use std::sync::atomic::{ AtomicBool, AtomicU64, Ordering };
+use std::time::Duration;
+
+
+
+/// if a sound loops and the part of it that loops, shared by the mixer
+/// and the `Looper` of the sound
+pub(crate) struct LoopRegion {
+	/// the sample rate of the source, which the frames are in
+	sample_rate: u32,
+	looping: AtomicBool,
+	start: AtomicU64,
+	/// `u64::MAX` for the end of the sound
+	end: AtomicU64,
+	/// if the sound went back to the start since the last `take_wrapped`
+	wrapped: AtomicBool
+}
+
+impl LoopRegion {
+
+
+	/// a region that is the whole sound, not looping
+	pub fn new (sample_rate: u32) -> Self {
+		Self {
+			sample_rate,
+			looping: AtomicBool::new(false),
+			start: AtomicU64::new(0),
+			end: AtomicU64::new(u64::MAX),
+			wrapped: AtomicBool::new(false)
+		}
+	}
+
+
+	pub fn looping (&self) -> bool {
+		self.looping.load(Ordering::Relaxed)
+	}
+
+
+	pub fn set_looping (&self, looping: bool) {
+		self.looping.store(looping, Ordering::Relaxed);
+	}
+
+
+	/// the first frame of the loop, and the frame after its last one
+	pub fn get (&self) -> (u64, u64) {
+		(self.start.load(Ordering::Relaxed), self.end.load(Ordering::Relaxed))
+	}
+
+
+	/// the times of the start and of the end of the loop in the source
+	pub fn times (&self) -> (Duration, Option<Duration>) {
+		let (start, end) = self.get();
+		let time = |frame: u64| Duration::from_secs_f64(frame as f64 / self.sample_rate as f64);
+		(time(start), (end != u64::MAX).then(|| time(end)))
+	}
+
+
+	/// return true if the sound looped since the last call
+	pub fn take_wrapped (&self) -> bool {
+		self.wrapped.swap(false, Ordering::Relaxed)
+	}
+
+
+	/// loop from `start` to `end`, or to the end of the sound with `None`
+	pub fn set (&self, start: u64, end: Option<u64>) {
+		self.start.store(start, Ordering::Relaxed);
+		self.end.store(end.unwrap_or(u64::MAX), Ordering::Relaxed);
+	}
+
+
+}
+
+
+
+/// play the loop region of a source, jumping from its end back to its
+/// start
+///
+/// this wraps the source before any conversion, so the converters see
+/// a continuous stream and the loop has no gap or click. the frames are
+/// in the sample rate of the source
+pub(crate) struct Looper <T: SoundSource> {
+	inner: T,
+	region: Arc<LoopRegion>,
+	/// the next frame of `inner` to be read
+	position: u64,
+	/// the error of `inner` that stopped the sound, until it is taken
+	error: Option<SourceError>
+}
+
+impl <T: SoundSource> Looper<T> {
+
+
+	pub fn new (inner: T, region: Arc<LoopRegion>) -> Self {
+		Self {
+			inner,
+			region,
+			position: 0,
+			error: None
+		}
+	}
+
+
+	/// continue from `start`, or from the beginning if `inner` can't seek
+	fn wrap (&mut self, start: u64) {
+		self.region.wrapped.store(true, Ordering::Relaxed);
+		if start == 0 || self.inner.seek(start).is_err() {
+			self.inner.reset();
+			self.position = 0;
+		} else {
+			self.position = self.inner.position().unwrap_or(start);
+		}
+	}
+
+
+}
+
+impl <T: SoundSource> SoundSource for Looper<T> {
+
+	fn channels (&self) -> u16 {
+		self.inner.channels()
+	}
+
+	fn sample_rate (&self) -> u32 {
+		self.inner.sample_rate()
+	}
+
+	fn reset (&mut self) {
+		self.inner.reset();
+		self.position = 0;
+	}
+
+	fn write_samples (&mut self, buffer: &mut [f32]) -> usize {
+		let channels = self.inner.channels() as usize;
+		let mut written = 0;
+		// where the last wrap happened, to stop on a loop with no frames
+		let mut wrapped_at = None;
+		while written < buffer.len() {
+			let looping = self.region.looping();
+			let (start, end) = self.region.get();
+
+			let mut len = buffer.len() - written;
+			if looping && self.position < end {
+				len = (end - self.position).min((len / channels) as u64) as usize * channels;
+			}
+			let n = self.inner.write_samples(&mut buffer[written..written + len]);
+			written += n;
+			self.position += (n / channels) as u64;
+
+			let at_end = n < len || (looping && self.position == end);
+			if !at_end {
+				continue;
+			}
+			if !looping || wrapped_at == Some(written) {
+				break;
+			}
+			// a failed source is not looped, so its error doesn't repeat
+			if n < len {
+				self.error = self.inner.take_error();
+				if self.error.is_some() {
+					break;
+				}
+			}
+			wrapped_at = Some(written);
+			self.wrap(start);
+		}
+		written
+	}
+
+	fn seek (&mut self, frame: u64) -> Result<(), SourceError> {
+		self.inner.seek(frame)?;
+		self.position = self.inner.position().unwrap_or(frame);
+		Ok(())
+	}
+
+	fn len_frames (&self) -> Option<u64> {
+		self.inner.len_frames()
+	}
+
+	fn position (&self) -> Option<u64> {
+		Some(self.position)
+	}
+
+	fn loop_region (&self) -> Option<(u64, Option<u64>)> {
+		self.inner.loop_region()
+	}
+
+	fn take_error (&mut self) -> Option<SourceError> {
+		self.error.take().or_else(|| self.inner.take_error())
+	}
+
+}
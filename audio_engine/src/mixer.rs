@@ -1,51 +1,305 @@
 
 
 
+use crate::bus::{ BusId, BusInner };
 use crate::converter;
-
+use crate::converter::Conversion;
+use crate::effect::{ Effect, Param };
+use crate::error::SourceError;
+use crate::event::Event;
+use crate::pan::Panning;
+use crate::looping::{ LoopRegion, Looper };
+use crate::spatial::{ Emitter, Listener, Spatial };
+
+use std::time::Duration;
 use std::sync::{
-	Arc,
-	Mutex,
-	atomic::{ AtomicU64, Ordering }
+	Arc, Mutex,
+	mpsc::{ sync_channel, Receiver, SyncSender, TrySendError },
+	atomic::{ AtomicBool, AtomicU8, AtomicU16, AtomicU32, AtomicU64, Ordering }
 };
 
 
 
-type SoundId = u64;
+/// identifies a [`Sound`] in the [events](crate::Event) of the engine
+pub type SoundId = u64;
 
 
 
-fn next_id() -> SoundId {
+/// a new id for a sound or a bus
+pub(crate) fn next_id() -> u64 {
 	static GLOBAL_COUNT: AtomicU64 = AtomicU64::new(0);
 	GLOBAL_COUNT.fetch_add(1, Ordering::Relaxed)
 }
 
 
 
+/// the maximum number of frames mixed at once
+///
+/// scratch buffers are allocated with this size up front, so the
+/// audio thread never allocates. larger buffers are mixed in blocks
+pub(crate) const BLOCK_FRAMES: usize = 512;
+
+
+
 /// the number of samples processed per second for a single channel of audio
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct SampleRate(pub u32);
 
 
 
+/// which sound is stopped when a new one would play over a voice
+/// limit, see [`AudioEngine::set_max_voices`](crate::AudioEngine::set_max_voices)
+///
+/// only sounds with a [priority](Sound::set_priority) lower than or
+/// equal to the one of the new sound can be stopped
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StealPolicy {
+	/// the sound that started to play first
+	#[default]
+	Oldest,
+	/// the sound with the lowest volume, including its fades and the
+	/// attenuation of a positional sound
+	Quietest,
+	/// the sound with the lowest priority, and the oldest of them
+	LowestPriority
+}
+
+
+
+/// a message to the mixer, sent from `Sound` or `AudioEngine`
+///
+/// commands are applied by the audio thread at the start of the next
+/// buffer, so the game thread never has to wait for the mixer
+pub(crate) enum Command {
+	Add(Box<SoundInner>, BusId),
+	Play(SoundId),
+	Pause(SoundId),
+	Stop(SoundId),
+	Reset(SoundId),
+	Seek(SoundId, Duration),
+	SetVolume(SoundId, f32),
+	FadeTo(SoundId, f32, Duration),
+	FadeIn(SoundId, Duration),
+	FadeOut(SoundId, Duration),
+	Crossfade(SoundId, SoundId, Duration),
+	SetLoop(SoundId, bool),
+	SetSpeed(SoundId, f32),
+	SetPriority(SoundId, i32),
+	SetLoopRegion(SoundId, u64, Option<u64>),
+	AddMarker(SoundId, Duration),
+	ClearMarkers(SoundId),
+	AddEffect(SoundId, Box<dyn Effect>),
+	ClearEffects(SoundId),
+	Drop(SoundId),
+	SetConversion(Conversion),
+	SetSpatial(SoundId, Option<Spatial>),
+	SetLocation(SoundId, [f32; 2]),
+	SetVelocity(SoundId, [f32; 2]),
+	SetListener(Listener),
+	SetMaxVoices(Option<usize>),
+	SetStealPolicy(StealPolicy),
+	/// apply the command when the clock of the mixer reaches the time
+	At(Duration, Box<Command>),
+	/// what is left in the box of a scheduled command once the mixer
+	/// took the command out of it
+	Applied,
+	AddBus(Box<BusInner>, BusId),
+	SetBusVolume(BusId, f32),
+	SetBusMuted(BusId, bool),
+	SetBusPaused(BusId, bool),
+	AddBusEffect(BusId, Box<dyn Effect>),
+	ClearBusEffects(BusId),
+	DropBus(BusId)
+}
+
+
+
+/// something the mixer stopped using, sent back to the game thread to
+/// be dropped there, so the audio thread never frees memory
+///
+/// the values are never read, only dropped
+#[allow(dead_code)]
+pub(crate) enum Garbage {
+	Sound(Box<SoundInner>),
+	Bus(Box<BusInner>),
+	Effect(Box<dyn Effect>),
+	Conversion(Conversion),
+	Command(Box<Command>)
+}
+
+
+
+/// the sending side of the commands of a mixer, cloned into every
+/// `Sound`, `Bus` and `AudioEngine`
+///
+/// the queue is allocated up front with room for
+/// [`COMMANDS_CAPACITY`](Mixer::COMMANDS_CAPACITY) commands, so neither
+/// sending nor receiving a command allocates. a command sent while the
+/// queue is full is dropped, and an error is logged
+///
+/// the garbage of the mixer is dropped by the next command sent, or by
+/// the next [`AudioEngine::poll_event`](crate::AudioEngine::poll_event)
+#[derive(Clone)]
+pub(crate) struct CommandSender {
+	sender: SyncSender<Command>,
+	garbage: Arc<Mutex<Receiver<Garbage>>>
+}
+
+impl CommandSender {
+
+	/// queue `command`, and return false if it was dropped
+	pub fn send (&self, command: Command) -> bool {
+		self.collect();
+		match self.sender.try_send(command) {
+			Ok(()) => true,
+			Err(TrySendError::Full(_)) => {
+				log::error!("the command queue of the mixer is full, a command was dropped");
+				false
+			},
+			// the receiver only goes away with the engine, in which case
+			// there is nothing left to control
+			Err(TrySendError::Disconnected(_)) => false
+		}
+	}
+
+
+	/// drop the garbage sent back by the mixer
+	pub fn collect (&self) {
+		// another thread is already dropping it
+		if let Ok(garbage) = self.garbage.try_lock() {
+			garbage.try_iter().for_each(drop);
+		}
+	}
+
+}
+
+
+
+/// the state of a sound, written by the mixer and read by `Sound`
+#[derive(Default)]
+struct SoundState {
+	playing: AtomicBool,
+	position: AtomicU64,
+	/// the code of the `SourceError` that stopped the sound, or 0
+	error: AtomicU8
+}
+
+
+
+/// the output config of the mixer, readable without locking it
+pub(crate) struct MixerState {
+	channels: AtomicU16,
+	sample_rate: AtomicU32,
+	clock: AtomicU64
+}
+
+impl MixerState {
+
+	/// the number of frames mixed, at the current sample rate
+	pub fn clock (&self) -> u64 {
+		self.clock.load(Ordering::Relaxed)
+	}
+
+	pub fn channels (&self) -> u16 {
+		self.channels.load(Ordering::Relaxed)
+	}
+
+	pub fn sample_rate (&self) -> u32 {
+		self.sample_rate.load(Ordering::Relaxed)
+	}
+
+}
+
+
+
 /// represents a sound in the audio engine. if this is dropped,
 /// the sound will continue to play until it ends.
 pub struct Sound {
 
-	pub mixer: Arc<Mutex<Mixer>>,
-	pub id: SoundId
+	sender: CommandSender,
+	state: Arc<SoundState>,
+	panning: Panning,
+	duration: Option<Duration>,
+	id: SoundId
 
 }
 
 impl Sound {
 
 
+	/// create a sound to be sent to the mixer with `Command::Add`, with
+	/// the instance limit of the source before it was boxed
+	pub(crate) fn new (
+		sender: CommandSender,
+		data: Box<dyn SoundSource + Send>,
+		instances: Option<(u64, usize)>
+	) -> (Self, Box<SoundInner>) {
+		let duration = data.len_frames().map(|len| {
+			Duration::from_secs_f64(len as f64 / data.sample_rate() as f64)
+		});
+		let inner = Box::new(SoundInner::new(data, instances));
+		let sound = Self {
+			sender,
+			state: inner.state.clone(),
+			panning: inner.panning.clone(),
+			duration,
+			id: inner.id
+		};
+		(sound, inner)
+	}
+
+
+	/// the id of the sound in the [events](crate::Event) of the engine
+	pub fn id (&self) -> SoundId {
+		self.id
+	}
+
+
+	fn send (&self, command: Command) {
+		self.sender.send(command);
+	}
+
+
 	/// starts or continue to play the sound
 	///
-	/// if the sound was paused ot stopped, it will start playing
+	/// if the sound was paused or stopped, it will start playing
 	/// again. otherwise, does nothing
 	pub fn play (&mut self) {
-		self.mixer.lock().unwrap().play(self.id);
+		self.send(Command::Play(self.id));
+	}
+
+
+	/// start to play the sound when the [clock](crate::AudioEngine::time)
+	/// of the engine reaches `time`, see [`at`](Sound::at)
+	pub fn play_at (&mut self, time: Duration) {
+		self.at(time).play();
+	}
+
+
+	/// stop the sound when the [clock](crate::AudioEngine::time) of
+	/// the engine reaches `time`, see [`at`](Sound::at)
+	pub fn stop_at (&mut self, time: Duration) {
+		self.at(time).stop();
+	}
+
+
+	/// change the sound when the [clock](crate::AudioEngine::time) of
+	/// the engine reaches `time`
+	///
+	/// the changes are applied on the exact sample of `time`, or on
+	/// the next buffer if `time` already passed
+	///
+	/// ```no_run
+	/// # use audio_engine::{ AudioEngine, WavDecoder };
+	/// # use std::time::Duration;
+	/// # let engine = AudioEngine::new().unwrap();
+	/// # let mut sound = engine.new_sound(WavDecoder::new(std::fs::File::open("a.wav").unwrap()).unwrap(), None).unwrap();
+	/// let beat = engine.time() + Duration::from_millis(500);
+	/// sound.at(beat).set_volume(0.5);
+	/// sound.at(beat).play();
+	/// ```
+	pub fn at (&self, time: Duration) -> Schedule<'_> {
+		Schedule { sound: self, time }
 	}
 
 
@@ -55,7 +309,7 @@ impl Sound {
 	/// this sound will continue from where it was before pause.
 	/// if the sound is not playing, doesn nothing.
 	pub fn pause (&mut self) {
-		self.mixer.lock().unwrap().pause(self.id);
+		self.send(Command::Pause(self.id));
 	}
 
 
@@ -65,7 +319,7 @@ impl Sound {
 	/// when play is called, this sound will start from beggining.
 	/// even if the sound is not playing, it will reset the sound.
 	pub fn stop (&mut self) {
-		self.mixer.lock().unwrap().stop(self.id);
+		self.send(Command::Stop(self.id));
 	}
 
 
@@ -73,25 +327,212 @@ impl Sound {
 	///
 	/// the behaviour is the same being the sound playing or not
 	pub fn reset (&mut self) {
-		self.mixer.lock().unwrap().reset(self.id);
+		self.send(Command::Reset(self.id));
 	}
 
 
 	/// set the volume of the sound
+	///
+	/// the volume changes right away, use [`fade_to`](Sound::fade_to)
+	/// to avoid clicks
 	pub fn set_volume(&mut self, volume: f32) {
-		self.mixer.lock().unwrap().set_volume(self.id, volume);
+		self.send(Command::SetVolume(self.id, volume));
+	}
+
+
+	/// change the volume of the sound linearly over `duration`
+	///
+	/// the volume is interpolated for every sample while the sound
+	/// plays, and doesn't change while it is paused
+	pub fn fade_to (&mut self, volume: f32, duration: Duration) {
+		self.send(Command::FadeTo(self.id, volume, duration));
+	}
+
+
+	/// play the sound, starting silent and reaching its volume after
+	/// `duration`
+	///
+	/// if the sound is already playing, it fades back from where it
+	/// is, canceling a [`stop_with_fade`](Sound::stop_with_fade)
+	pub fn fade_in (&mut self, duration: Duration) {
+		self.send(Command::FadeIn(self.id, duration));
+	}
+
+
+	/// fade the sound out over `duration`, then [`stop`](Sound::stop) it
+	///
+	/// the volume set with [`set_volume`](Sound::set_volume) is kept
+	/// for the next time the sound plays
+	pub fn stop_with_fade (&mut self, duration: Duration) {
+		self.send(Command::FadeOut(self.id, duration));
+	}
+
+
+	/// set the position of the sound between the left, at `-1.0`,
+	/// and the right speakers, at `1.0`
+	///
+	/// mono sounds use a constant power pan law: each side is at -3dB
+	/// when centered, and the side the sound is panned to is at the
+	/// level of the source. stereo sounds have the opposite side moved
+	/// into the panned one. the change is
+	/// smoothed over the next block, so it can be called on every
+	/// frame of the game. this has no effect on sounds with more than
+	/// two channels, or on a mono output
+	pub fn set_pan (&mut self, pan: f32) {
+		self.panning.pan.set(pan);
+	}
+
+
+	/// set the stereo width of a stereo sound
+	///
+	/// `0.0` plays both channels in the center, `1.0` leaves them as
+	/// they are and larger values exaggerate their difference
+	pub fn set_width (&mut self, width: f32) {
+		self.panning.width.set(width);
 	}
 
 
-	/// set if the sound will repeat every time it reaches the end
+	/// make the sound positional, or not positional with `None`
+	///
+	/// the volume, the pan and, if the doppler effect is enabled, the
+	/// speed of a positional sound are derived from its location and
+	/// velocity relative to the [listener](crate::AudioEngine::set_listener).
+	/// its volume is still multiplied by [`set_volume`](Sound::set_volume),
+	/// but [`set_pan`](Sound::set_pan) is overriden
+	pub fn set_spatial (&mut self, spatial: Option<Spatial>) {
+		self.send(Command::SetSpatial(self.id, spatial));
+	}
+
+
+	/// set the location of a positional sound in the world
+	pub fn set_location (&mut self, location: [f32; 2]) {
+		self.send(Command::SetLocation(self.id, location));
+	}
+
+
+	/// set the velocity of a positional sound, in units per second,
+	/// used by the doppler effect
+	pub fn set_velocity (&mut self, velocity: [f32; 2]) {
+		self.send(Command::SetVelocity(self.id, velocity));
+	}
+
+
+	/// set the playback speed of the sound, where `2.0` plays it twice
+	/// as fast and an octave higher, and `0.5` twice as slow and an
+	/// octave lower
+	///
+	/// while the sound plays, the speed changes gradually over the next
+	/// block, without clicks. positional sounds play at this speed times
+	/// their doppler speed
+	pub fn set_speed (&mut self, speed: f32) {
+		self.send(Command::SetSpeed(self.id, speed));
+	}
+
+
+	/// set the priority of the sound, `0` by default
+	///
+	/// when a sound would play over a
+	/// [voice limit](crate::AudioEngine::set_max_voices), only sounds
+	/// with the same or a lower priority can be stopped for it. if there
+	/// are none, the new sound doesn't play
+	pub fn set_priority (&mut self, priority: i32) {
+		self.send(Command::SetPriority(self.id, priority));
+	}
+
+
+	/// set if the sound will repeat every time it reaches the end of
+	/// its [loop region](Sound::set_loop_region)
 	pub fn set_loop (&mut self, looping: bool) {
-		self.mixer.lock().unwrap().set_loop(self.id, looping);
+		self.send(Command::SetLoop(self.id, looping));
+	}
+
+
+	/// set the part of the sound that repeats when it loops, from the
+	/// frame `start` to the frame before `end`, or to the end of the
+	/// sound with `None`. the frames are at the sample rate of the
+	/// source
+	///
+	/// the sound plays from the beginning, then repeats the region
+	/// without any gap, so an intro is only heard once. by default,
+	/// the region is the one of the [source](SoundSource::loop_region),
+	/// or the whole sound
+	///
+	/// looping back to a `start` other than 0 needs a source that can
+	/// [seek](SoundSource::seek), otherwise the sound loops from the
+	/// beginning
+	pub fn set_loop_region (&mut self, start: u64, end: Option<u64>) {
+		self.send(Command::SetLoopRegion(self.id, start, end));
 	}
 
 
-	/// update sound effect
-	pub fn effect (&mut self, effect: impl FnMut(f32) -> f32 + 'static + std::marker::Send) {
-		self.mixer.lock().unwrap().update_effect(self.id, effect);
+	/// send an [`Event::Marker`](crate::Event::Marker) every time the
+	/// sound plays the frame at `time` from the start of the source
+	pub fn add_marker (&mut self, time: Duration) {
+		self.send(Command::AddMarker(self.id, time));
+	}
+
+
+	/// remove all markers of the sound
+	pub fn clear_markers (&mut self) {
+		self.send(Command::ClearMarkers(self.id));
+	}
+
+
+	/// add an effect to the end of the effect chain of the sound
+	///
+	/// to change the parameters of the effect later, keep a clone of
+	/// the [`Param`](crate::Param)s it was created with
+	pub fn add_effect (&mut self, effect: impl Effect + 'static) {
+		self.send(Command::AddEffect(self.id, Box::new(effect)));
+	}
+
+
+	/// remove all effects of the sound
+	pub fn clear_effects (&mut self) {
+		self.send(Command::ClearEffects(self.id));
+	}
+
+
+	/// return true if the sound was playing at the end of the last
+	/// mixed buffer
+	///
+	/// commands are applied by the audio thread, so this only reflect
+	/// a call to `play` or `pause` after the next buffer is mixed
+	pub fn is_playing (&self) -> bool {
+		self.state.playing.load(Ordering::Relaxed)
+	}
+
+
+	/// the number of frames played since the start of the sound, at
+	/// the sample rate of the engine, as of the last mixed buffer
+	///
+	/// if the source knows its [position](SoundSource::position), this
+	/// is the position in the source, which doesn't follow the frames
+	/// mixed when the speed changes or after a [`seek`](Sound::seek)
+	pub fn position (&self) -> u64 {
+		self.state.position.load(Ordering::Relaxed)
+	}
+
+
+	/// the error that stopped the sound, if its source failed since the
+	/// sound last started to play
+	pub fn error (&self) -> Option<SourceError> {
+		SourceError::from_code(self.state.error.load(Ordering::Relaxed))
+	}
+
+
+	/// the length of the source, if it is [known](SoundSource::len_frames)
+	pub fn duration (&self) -> Option<Duration> {
+		self.duration
+	}
+
+
+	/// continue the sound from `time`, from the start of the source
+	///
+	/// this does nothing if the source can't [seek](SoundSource::seek).
+	/// seeking after the end ends the sound
+	pub fn seek (&mut self, time: Duration) {
+		self.send(Command::Seek(self.id, time));
 	}
 
 
@@ -99,8 +540,84 @@ impl Sound {
 
 impl Drop for Sound {
 	fn drop (&mut self) {
-		self.mixer.lock().unwrap().drop_sound(self.id);
+		self.send(Command::Drop(self.id));
+	}
+}
+
+
+
+/// changes to a [`Sound`] scheduled at a time, created by [`Sound::at`]
+///
+/// each method does the same as the method of `Sound` with the same
+/// name, when the clock of the engine reaches the time
+pub struct Schedule <'a> {
+	sound: &'a Sound,
+	time: Duration
+}
+
+impl Schedule<'_> {
+
+
+	fn send (&self, command: Command) {
+		self.sound.send(Command::At(self.time, Box::new(command)));
+	}
+
+
+	pub fn play (&self) {
+		self.send(Command::Play(self.sound.id));
+	}
+
+
+	pub fn pause (&self) {
+		self.send(Command::Pause(self.sound.id));
+	}
+
+
+	pub fn stop (&self) {
+		self.send(Command::Stop(self.sound.id));
+	}
+
+
+	pub fn reset (&self) {
+		self.send(Command::Reset(self.sound.id));
+	}
+
+
+	pub fn seek (&self, time: Duration) {
+		self.send(Command::Seek(self.sound.id, time));
+	}
+
+
+	pub fn set_volume (&self, volume: f32) {
+		self.send(Command::SetVolume(self.sound.id, volume));
+	}
+
+
+	pub fn fade_to (&self, volume: f32, duration: Duration) {
+		self.send(Command::FadeTo(self.sound.id, volume, duration));
+	}
+
+
+	pub fn fade_in (&self, duration: Duration) {
+		self.send(Command::FadeIn(self.sound.id, duration));
+	}
+
+
+	pub fn stop_with_fade (&self, duration: Duration) {
+		self.send(Command::FadeOut(self.sound.id, duration));
+	}
+
+
+	pub fn set_speed (&self, speed: f32) {
+		self.send(Command::SetSpeed(self.sound.id, speed));
 	}
+
+
+	pub fn set_loop (&self, looping: bool) {
+		self.send(Command::SetLoop(self.sound.id, looping));
+	}
+
+
 }
 
 
@@ -125,9 +642,63 @@ pub trait SoundSource {
 	/// less than the length of `buffer`, this indicates that the
 	/// sound has ended.
 	///
-	/// the `buffer` length and the returned length should always be
-	/// a multiple of [`self.channels()`](SoundSource::channels).
-	fn write_samples (&mut self, buffer: &mut [i16]) -> usize;
+	/// samples are in the range `-1.0..=1.0`. the `buffer` length and
+	/// the returned length should always be a multiple of
+	/// [`self.channels()`](SoundSource::channels).
+	fn write_samples (&mut self, buffer: &mut [f32]) -> usize;
+
+	/// continue the sound from `frame`, at the sample rate of the
+	/// source
+	///
+	/// a frame after the end should end the sound. by default, the
+	/// source can't seek and this returns [`SourceError::Unsupported`]
+	fn seek (&mut self, frame: u64) -> Result<(), SourceError> {
+		let _ = frame;
+		Err(SourceError::Unsupported)
+	}
+
+	/// return the length of the sound in frames, if it is known
+	fn len_frames (&self) -> Option<u64> {
+		None
+	}
+
+	/// return the frame the next call to `write_samples` starts from,
+	/// if it is known
+	fn position (&self) -> Option<u64> {
+		None
+	}
+
+	/// return the first frame of the part of the sound that loops and
+	/// the frame after its last one, or `None` for the end of the
+	/// sound, if the source defines them
+	///
+	/// these are used as the [loop region](Sound::set_loop_region) of
+	/// the sounds created from the source
+	fn loop_region (&self) -> Option<(u64, Option<u64>)> {
+		None
+	}
+
+	/// return the error that made the last call to `write_samples` end
+	/// the sound early, if there was one, and forget it
+	///
+	/// the mixer calls this after every `write_samples`, and stops the
+	/// sound if there was an error, see [`SourceError`]
+	fn take_error (&mut self) -> Option<SourceError> {
+		None
+	}
+
+	/// return an id of the asset the source plays, shared by all
+	/// sources of the same asset, and how many sounds of it can play at
+	/// once, if the source limits them
+	///
+	/// this is only asked once, of the source given to
+	/// [`AudioEngine::new_sound`](crate::AudioEngine::new_sound), so
+	/// sources that wrap another one don't report its limit. see
+	/// [`AudioEngine::set_max_voices`](crate::AudioEngine::set_max_voices)
+	/// for how sounds over the limit are stopped
+	fn instance_limit (&self) -> Option<(u64, usize)> {
+		None
+	}
 
 }
 
@@ -145,46 +716,266 @@ impl<T: SoundSource + ?Sized> SoundSource for Box<T> {
 		(**self).reset()
 	}
 
-	fn write_samples (&mut self, buffer: &mut [i16]) -> usize {
+	fn write_samples (&mut self, buffer: &mut [f32]) -> usize {
 		(**self).write_samples(buffer)
 	}
 
+	fn seek (&mut self, frame: u64) -> Result<(), SourceError> {
+		(**self).seek(frame)
+	}
+
+	fn len_frames (&self) -> Option<u64> {
+		(**self).len_frames()
+	}
+
+	fn position (&self) -> Option<u64> {
+		(**self).position()
+	}
+
+	fn loop_region (&self) -> Option<(u64, Option<u64>)> {
+		(**self).loop_region()
+	}
+
+	fn take_error (&mut self) -> Option<SourceError> {
+		(**self).take_error()
+	}
+
 }
 
 
-struct SoundInner {
+/// a value that moves linearly to a target, one frame at a time
+#[derive(Debug, Clone, Copy)]
+struct Ramp {
+	value: f32,
+	target: f32,
+	step: f32,
+	/// the number of frames left to reach `target`
+	frames: u64
+}
+
+impl Ramp {
+
+	fn new (value: f32) -> Self {
+		Self { value, target: value, step: 0.0, frames: 0 }
+	}
+
+
+	/// jump to `value`
+	fn set (&mut self, value: f32) {
+		*self = Self::new(value);
+	}
+
+
+	/// start moving to `target` in `frames` frames
+	fn to (&mut self, target: f32, frames: u64) {
+		if frames == 0 {
+			self.set(target);
+		} else {
+			self.target = target;
+			self.step = (target - self.value) / frames as f32;
+			self.frames = frames;
+		}
+	}
+
+
+	fn is_moving (&self) -> bool {
+		self.frames > 0
+	}
+
+
+	/// advance one frame and return the new value
+	fn next (&mut self) -> f32 {
+		if self.frames > 0 {
+			self.frames -= 1;
+			self.value = if self.frames == 0 { self.target } else { self.value + self.step };
+		}
+		self.value
+	}
+
+}
+
+
+
+pub(crate) struct SoundInner {
 
 	id: SoundId,
 	data: Box<dyn SoundSource + Send>,
-	volume: f32,
-	looping: bool,
+	state: Arc<SoundState>,
+	/// frames played since the start, at the mixer sample rate
+	position: u64,
+	/// the index of the bus the sound is mixed into
+	bus: usize,
+	volume: Ramp,
+	/// a gain for fading in and out, that doesn't change `volume`
+	envelope: Ramp,
+	/// if the sound stops when `envelope` is done fading out
+	stopping: bool,
+	panning: Panning,
+	/// if `data` already has a `Panner`
+	panned: bool,
+	/// the playback speed of the `SampleRateConverter` in `data`, the
+	/// product of `rate` and of the doppler speed
+	speed: Param,
+	/// the playback speed set by the user
+	rate: f32,
+	/// if `data` already has a `SampleRateConverter` playing at `speed`
+	varispeed: bool,
+	priority: i32,
+	/// the clock of the mixer when the sound last started to play
+	started: u64,
+	/// the asset of the source and how many sounds of it can play
+	instances: Option<(u64, usize)>,
+	emitter: Option<Emitter>,
+	/// the gain of the emitter in the last block, ramped toward the
+	/// new one over the next block
+	distance_gain: Option<f32>,
+	region: Arc<LoopRegion>,
+	/// the times of the source that send an `Event::Marker`
+	markers: Vec<Duration>,
 	drop: bool,
-	effect: Box<dyn FnMut(f32) -> f32 + Send>
+	effects: Vec<Box<dyn Effect>>
 
 }
 
 impl SoundInner {
 
-	fn new (data: Box<dyn SoundSource + Send>, effect: impl FnMut(f32) -> f32 + 'static + std::marker::Send) -> Self {
+	/// the number of effects a sound can have without the audio
+	/// thread allocating
+	const EFFECTS_CAPACITY: usize = 4;
+
+	/// the number of markers a sound can have without the audio thread
+	/// allocating
+	const MARKERS_CAPACITY: usize = 8;
+
+	fn new (data: Box<dyn SoundSource + Send>, instances: Option<(u64, usize)>) -> Self {
+		let region = Arc::new(LoopRegion::new(data.sample_rate()));
+		if let Some((start, end)) = data.loop_region() {
+			region.set(start, end);
+		}
 		Self {
 			id: next_id(),
-			data,
-			volume: 1.0,
-			looping: false,
+			instances,
+			data: Box::new(Looper::new(data, region.clone())),
+			state: Arc::new(SoundState::default()),
+			position: 0,
+			bus: 0,
+			volume: Ramp::new(1.0),
+			envelope: Ramp::new(1.0),
+			stopping: false,
+			panning: Panning::new(),
+			panned: false,
+			speed: Param::new(1.0),
+			rate: 1.0,
+			varispeed: false,
+			priority: 0,
+			started: 0,
+			emitter: None,
+			distance_gain: None,
+			region,
+			markers: Vec::with_capacity(Self::MARKERS_CAPACITY),
 			drop: false,
-			effect: Box::new(effect)
+			effects: Vec::with_capacity(Self::EFFECTS_CAPACITY)
 		}
 	}
 
+
+	/// wrap the source in converters if it doesn't match the given
+	/// config, and in a `Panner` the first time it can be panned. the
+	/// first time, it is always wrapped in a `SampleRateConverter` so
+	/// its speed can change
+	pub(crate) fn conform (&mut self, channels: u16, sample_rate: u32, conversion: &Conversion) {
+
+		struct Nop;
+		#[rustfmt::skip]
+		impl SoundSource for Nop {
+			fn channels (&self) -> u16 { 0 }
+			fn sample_rate (&self) -> u32 { 0 }
+			fn reset (&mut self) { }
+			fn write_samples (&mut self, _: &mut [f32]) -> usize { 0 }
+		}
+
+		let pan = !self.panned && conversion.pans(self.data.channels(), channels);
+		let varispeed = !self.varispeed;
+		// https://github.com/Rodrigodd/audio-engine/blob/3d0da3711b5cc78e7192d616ebb1d4069920707d/src/lib.rs#L200
+		// Beware !! read the link
+		if pan || varispeed || self.data.channels() != channels || self.data.sample_rate() != sample_rate {
+			let inner = std::mem::replace(&mut self.data, Box::new(Nop));
+			let panning = pan.then_some(&self.panning);
+			let speed = varispeed.then_some(&self.speed);
+			self.data = converter::conform(inner, channels, sample_rate, conversion, panning, speed);
+			self.panned |= pan;
+			self.varispeed = true;
+		}
+
+	}
+
+
+	fn reset (&mut self) {
+		self.data.reset();
+		self.position = 0;
+		self.state.position.store(0, Ordering::Relaxed);
+		for effect in self.effects.iter_mut() {
+			effect.reset();
+		}
+	}
+
+
+	/// the gain the sound is mixed with, without its effects
+	fn gain (&self) -> f32 {
+		self.volume.value * self.envelope.value * self.distance_gain.unwrap_or(1.0)
+	}
+
 }
 
 
 
 /// keep track of each Sound, and mix their output together
+///
+/// the mixer is owned by the audio thread. other threads control it
+/// by sending [`Command`]s through [`Mixer::sender`], which are
+/// applied at the start of each call to `write_samples`
+///
+/// sounds are mixed into the buffer of their bus, then each bus is
+/// mixed into its parent, up to the master bus
+///
+/// mixing doesn't allocate. buffers are only reallocated by
+/// `set_config`, and the list of sounds only grows when more than
+/// [`SOUNDS_CAPACITY`](Mixer::SOUNDS_CAPACITY) sounds exist at once.
+/// it doesn't free memory either: the sounds, buses, effects and
+/// commands it is done with are sent back as [`Garbage`], and dropped
+/// by the game thread, see [`CommandSender`]
 pub struct Mixer {
 
-	sounds: Vec<SoundInner>,
+	/// the boxes the sounds are sent in are kept, so adding one doesn't
+	/// free memory
+	#[allow(clippy::vec_box)]
+	sounds: Vec<Box<SoundInner>>,
 	playing: usize,
+	/// the master bus first, then every bus after its parent, kept in
+	/// their boxes like the sounds
+	#[allow(clippy::vec_box)]
+	buses: Vec<Box<BusInner>>,
+	/// scratch buffer with [`BLOCK_FRAMES`] frames of each sound
+	buf: Vec<f32>,
+	/// how sounds are converted when the config changes
+	conversion: Conversion,
+	listener: Listener,
+	/// the number of sounds that can play at once, not counting the
+	/// ones fading out
+	max_voices: usize,
+	steal_policy: StealPolicy,
+	/// the number of frames mixed
+	clock: u64,
+	/// commands waiting for the clock to reach their frame, the last one
+	/// first, so the next one is popped
+	schedule: Vec<(u64, Box<Command>)>,
+	commands: Receiver<Command>,
+	sender: CommandSender,
+	garbage: SyncSender<Garbage>,
+	events: SyncSender<Event>,
+	/// taken by the engine with `take_events`
+	event_receiver: Option<Receiver<Event>>,
+	state: Arc<MixerState>,
 	pub channels: u16,
 	pub sample_rate: SampleRate
 
@@ -193,71 +984,353 @@ pub struct Mixer {
 impl Mixer {
 
 
+	/// the number of sounds the mixer can hold without allocating
+	pub const SOUNDS_CAPACITY: usize = 64;
+
+	/// the number of buses the mixer can hold without allocating
+	pub const BUSES_CAPACITY: usize = 16;
+
+	/// the number of scheduled commands the mixer can hold, later ones
+	/// are dropped
+	pub const SCHEDULE_CAPACITY: usize = 64;
+
+	/// the number of commands queued until the mixer applies them,
+	/// later commands are dropped
+	pub const COMMANDS_CAPACITY: usize = 1024;
+
+	/// the number of things the mixer can send back to be dropped before
+	/// the game thread drops them, later ones are dropped by the mixer
+	pub const GARBAGE_CAPACITY: usize = 1024;
+
+	/// the number of events kept until they are taken, later events are
+	/// dropped
+	pub const EVENTS_CAPACITY: usize = 256;
+
+	/// how long a sound stopped by a voice limit takes to fade out
+	pub const STEAL_FADE: Duration = Duration::from_millis(20);
+
+
 	pub fn new (channels: u16, sample_rate: SampleRate) -> Self {
+		let (sender, commands) = sync_channel(Self::COMMANDS_CAPACITY);
+		let (garbage, garbage_receiver) = sync_channel(Self::GARBAGE_CAPACITY);
+		let (events, event_receiver) = sync_channel(Self::EVENTS_CAPACITY);
+		let mut buses = Vec::with_capacity(Self::BUSES_CAPACITY);
+		buses.push(Box::new(BusInner::new(next_id(), channels)));
 		Self {
-			sounds: vec![],
+			sounds: Vec::with_capacity(Self::SOUNDS_CAPACITY),
 			playing: 0,
+			buses,
+			buf: vec![0.0; BLOCK_FRAMES * channels as usize],
+			conversion: Conversion::default(),
+			listener: Listener::default(),
+			max_voices: usize::MAX,
+			steal_policy: StealPolicy::default(),
+			clock: 0,
+			schedule: Vec::with_capacity(Self::SCHEDULE_CAPACITY),
+			commands,
+			sender: CommandSender {
+				sender,
+				garbage: Arc::new(Mutex::new(garbage_receiver))
+			},
+			garbage,
+			events,
+			event_receiver: Some(event_receiver),
+			state: Arc::new(MixerState {
+				channels: AtomicU16::new(channels),
+				sample_rate: AtomicU32::new(sample_rate.0),
+				clock: AtomicU64::new(0)
+			}),
 			channels,
 			sample_rate
 		}
 	}
 
 
+	/// a new sender of commands to this mixer
+	pub(crate) fn sender (&self) -> CommandSender {
+		self.sender.clone()
+	}
+
+
+	/// the output config, shared with other threads
+	pub(crate) fn state (&self) -> Arc<MixerState> {
+		self.state.clone()
+	}
+
+
+	/// the id of the master bus
+	pub(crate) fn master (&self) -> BusId {
+		self.buses[0].id
+	}
+
+
+	/// the receiver of the events of this mixer, only returned once
+	pub(crate) fn take_events (&mut self) -> Option<Receiver<Event>> {
+		self.event_receiver.take()
+	}
+
+
+	/// send an event if there is room for it
+	pub(crate) fn send_event (&self, event: Event) {
+		let _ = self.events.try_send(event);
+	}
+
+
+	/// drop the sender of the events, so their receiver ends even if
+	/// the mixer outlives the engine
+	pub(crate) fn close_events (&mut self) {
+		self.events = sync_channel(0).0;
+	}
+
+
+	/// send `garbage` back to be dropped by the game thread, or drop it
+	/// here if there is no room for it
+	fn discard (&self, garbage: Garbage) {
+		let _ = self.garbage.try_send(garbage);
+	}
+
+
 	/// change the number of channels and the sample rate
 	///
 	/// this will also keep all currently playing sounds and convert
 	/// them to the new config if necessary
 	pub fn set_config (&mut self, channels: u16, sample_rate: SampleRate) {
 
-		struct Nop;
-		#[rustfmt::skip]
-		impl SoundSource for Nop {
-			fn channels (&self) -> u16 { 0 }
-			fn sample_rate (&self) -> u32 { 0 }
-			fn reset (&mut self) { }
-			fn write_samples (&mut self, _: &mut [i16]) -> usize { 0 }
-		}
-
 		let not_changed = self.channels == channels && self.sample_rate == sample_rate;
 		if not_changed {
 			return;
 		}
-		if !self.sounds.is_empty() {
-			for sound in self.sounds.iter_mut() {
-				// https://github.com/Rodrigodd/audio-engine/blob/3d0da3711b5cc78e7192d616ebb1d4069920707d/src/lib.rs#L200
-				// Beware !! read the link
-				if sound.data.channels() != channels {
-					let inner = std::mem::replace(&mut sound.data, Box::new(Nop));
-					sound.data = Box::new(converter::ChannelConverter::new(inner, channels));
-				}
-				if sound.data.sample_rate() != sample_rate.0 {
-					let inner = std::mem::replace(&mut sound.data, Box::new(Nop));
-					sound.data = Box::new(converter::SampleRateConverter::new(inner, sample_rate.0));
-				}
-			}
+
+		// keep the clock and the schedule at the same time
+		let rescale = |frame: u64| (frame as f64 * sample_rate.0 as f64 / self.sample_rate.0 as f64).round() as u64;
+		self.clock = rescale(self.clock);
+		for (frame, _) in self.schedule.iter_mut() {
+			*frame = rescale(*frame);
 		}
+		self.state.clock.store(self.clock, Ordering::Relaxed);
+
 		self.channels = channels;
 		self.sample_rate = sample_rate;
+		self.buf = vec![0.0; BLOCK_FRAMES * channels as usize];
+		for bus in self.buses.iter_mut() {
+			bus.buf = vec![0.0; BLOCK_FRAMES * channels as usize];
+		}
+		self.state.channels.store(channels, Ordering::Relaxed);
+		self.state.sample_rate.store(sample_rate.0, Ordering::Relaxed);
+
+		for i in 0..self.sounds.len() {
+			self.conform(i);
+		}
+
+	}
+
+
+	/// wrap the source of the sound at `index` in converters if it
+	/// doesn't match the mixer config
+	fn conform (&mut self, index: usize) {
+		self.sounds[index].conform(self.channels, self.sample_rate.0, &self.conversion);
+	}
+
+
+	/// apply all pending commands
+	fn process_commands (&mut self) {
+		while let Ok(command) = self.commands.try_recv() {
+			self.apply(command);
+		}
+	}
+
+
+	/// apply the scheduled commands whose frame was reached
+	fn process_schedule (&mut self) {
+		while self.schedule.last().is_some_and(|&(frame, _)| frame <= self.clock) {
+			let (_, mut command) = self.schedule.pop().unwrap();
+			let scheduled = std::mem::replace(&mut *command, Command::Applied);
+			self.discard(Garbage::Command(command));
+			self.apply(scheduled);
+		}
+	}
+
+
+	/// keep `command` until the clock reaches `time`, or drop it if the
+	/// schedule is full
+	fn schedule (&mut self, time: Duration, command: Box<Command>) {
+		if self.schedule.len() == self.schedule.capacity() {
+			log::error!("the schedule of the mixer is full, a command was dropped");
+			self.discard(Garbage::Command(command));
+			return;
+		}
+		let frame = self.frames(time);
+		// before the commands already scheduled for the same frame, so it
+		// is popped after them
+		let index = self.schedule.partition_point(|(x, _)| *x > frame);
+		self.schedule.insert(index, (frame, command));
+	}
+
+
+	fn apply (&mut self, command: Command) {
+		match command {
+			Command::Add(sound, bus) => self.add_sound(sound, bus),
+			Command::Play(id) => self.play(id),
+			Command::Pause(id) => self.pause(id),
+			Command::Stop(id) => self.stop(id),
+			Command::Reset(id) => self.reset(id),
+			Command::Seek(id, time) => self.seek(id, time),
+			Command::SetVolume(id, volume) => self.set_volume(id, volume),
+			Command::FadeTo(id, volume, duration) => self.fade_to(id, volume, duration),
+			Command::FadeIn(id, duration) => self.fade_in(id, duration),
+			Command::FadeOut(id, duration) => self.fade_out(id, duration),
+			Command::Crossfade(from, to, duration) => {
+				self.fade_out(from, duration);
+				self.fade_in(to, duration);
+			},
+			Command::SetLoop(id, looping) => self.set_loop(id, looping),
+			Command::SetSpeed(id, speed) => self.set_speed(id, speed),
+			Command::SetPriority(id, priority) => self.set_priority(id, priority),
+			Command::SetLoopRegion(id, start, end) => self.set_loop_region(id, start, end),
+			Command::AddMarker(id, time) => self.add_marker(id, time),
+			Command::ClearMarkers(id) => self.clear_markers(id),
+			Command::AddEffect(id, effect) => self.add_effect(id, effect),
+			Command::ClearEffects(id) => self.clear_effects(id),
+			Command::Drop(id) => self.drop_sound(id),
+			Command::SetConversion(conversion) => {
+				let old = std::mem::replace(&mut self.conversion, conversion);
+				self.discard(Garbage::Conversion(old));
+			},
+			Command::SetSpatial(id, spatial) => self.set_spatial(id, spatial),
+			Command::SetLocation(id, location) => self.set_location(id, location),
+			Command::SetVelocity(id, velocity) => self.set_velocity(id, velocity),
+			Command::SetListener(listener) => self.listener = listener,
+			Command::SetMaxVoices(max) => self.max_voices = max.unwrap_or(usize::MAX),
+			Command::SetStealPolicy(policy) => self.steal_policy = policy,
+			Command::AddBus(bus, parent) => self.add_bus(bus, parent),
+			Command::SetBusVolume(id, volume) => self.set_bus_volume(id, volume),
+			Command::SetBusMuted(id, muted) => self.set_bus_muted(id, muted),
+			Command::SetBusPaused(id, paused) => self.set_bus_paused(id, paused),
+			Command::AddBusEffect(id, effect) => self.add_bus_effect(id, effect),
+			Command::ClearBusEffects(id) => self.clear_bus_effects(id),
+			Command::DropBus(id) => self.drop_bus(id),
+			Command::At(time, command) => self.schedule(time, command),
+			Command::Applied => {}
+		}
+	}
+
+
+	/// the index of the sound with the given id
+	fn find (&self, id: SoundId) -> Option<usize> {
+		self.sounds.iter().rposition(|x| x.id == id)
+	}
+
 
+	/// the index of the bus with the given id, or of the master bus if
+	/// it doesn't exist anymore
+	fn find_bus (&self, id: BusId) -> usize {
+		self.buses.iter().position(|x| x.id == id).unwrap_or(0)
 	}
 
 
-	pub fn add_sound (&mut self, sound: Box<dyn SoundSource + Send>, effect: impl FnMut(f32) -> f32 + 'static + std::marker::Send) -> SoundId {
-		let sound_inner = SoundInner::new(sound, effect);
-		let id = sound_inner.id;
-		self.sounds.push(sound_inner);
-		id
+	fn add_sound (&mut self, mut sound: Box<SoundInner>, bus: BusId) {
+		sound.bus = self.find_bus(bus);
+		self.sounds.push(sound);
+		// the config may have changed since the sound was created
+		self.conform(self.sounds.len() - 1);
 	}
 
 
-	/// if the sound was paused ot stopped, it will start playing
+	/// if the sound was paused or stopped, it will start playing
 	/// again. otherwise, does nothing
-	pub fn play (&mut self, id: SoundId) {
-		for i in (self.playing..self.sounds.len()).rev() {
-			if self.sounds[i].id == id {
+	///
+	/// if the sound is over a voice limit and no sound can be stolen
+	/// for it, it stays stopped
+	fn play (&mut self, id: SoundId) {
+		if let Some(i) = self.find(id) {
+			if i >= self.playing {
+				if !self.make_room(i) {
+					let _ = self.events.try_send(Event::Rejected(id));
+					let sound = &mut self.sounds[i];
+					sound.envelope.set(1.0);
+					if sound.drop {
+						let sound = self.sounds.swap_remove(i);
+						self.discard(Garbage::Sound(sound));
+					}
+					return;
+				}
+				self.sounds[i].state.playing.store(true, Ordering::Relaxed);
+				self.sounds[i].state.error.store(0, Ordering::Relaxed);
+				self.sounds[i].started = self.clock;
 				self.sounds.swap(self.playing, i);
 				self.playing += 1;
-				break;
+			}
+		}
+	}
+
+
+	/// fade out the sounds the sound at `index` is over the voice limits
+	/// of, and return true if it can play
+	///
+	/// both limits are checked before anything is faded, so a sound that
+	/// can't play doesn't stop any other
+	fn make_room (&mut self, index: usize) -> bool {
+		let asset = self.sounds[index].instances;
+		let same_asset = |x: &SoundInner| match (asset, x.instances) {
+			(Some((a, _)), Some((b, _))) => a == b,
+			_ => false
+		};
+		let asset_excess = match asset {
+			Some((_, max)) => self.excess(index, max, same_asset),
+			None => Some(0)
+		};
+		let (Some(asset_excess), Some(excess)) = (asset_excess, self.excess(index, self.max_voices, |_| true)) else {
+			return false;
+		};
+		self.steal(index, asset_excess, same_asset);
+		// the sounds stolen for the instance limit were voices too
+		self.steal(index, excess.saturating_sub(asset_excess), |_| true);
+		true
+	}
+
+
+	/// how many of the playing sounds that match `filter` must be faded
+	/// out for only `max` of them to play with the sound at `index`, or
+	/// `None` if not enough of them have a low enough priority
+	///
+	/// sounds already fading out don't count
+	fn excess (&self, index: usize, max: usize, filter: impl Fn(&SoundInner) -> bool) -> Option<usize> {
+		if max == 0 {
+			return None;
+		}
+		let priority = self.sounds[index].priority;
+		let voices = (0..self.playing).filter(|&i| !self.sounds[i].stopping && filter(&self.sounds[i]));
+		let excess = (voices.clone().count() + 1).saturating_sub(max);
+		let stealable = voices.filter(|&i| self.sounds[i].priority <= priority).count();
+		(stealable >= excess).then_some(excess)
+	}
+
+
+	/// fade out `count` of the playing sounds that match `filter` and
+	/// have a priority lower than or equal to the one of the sound at
+	/// `index`, chosen by the steal policy
+	fn steal (&mut self, index: usize, count: usize, filter: impl Fn(&SoundInner) -> bool) {
+		let priority = self.sounds[index].priority;
+		let frames = self.frames(Self::STEAL_FADE);
+		for _ in 0..count {
+			let victim = (0..self.playing)
+				.filter(|&i| {
+					let x = &self.sounds[i];
+					!x.stopping && x.priority <= priority && filter(x)
+				})
+				.min_by(|&a, &b| {
+					let (a, b) = (&self.sounds[a], &self.sounds[b]);
+					match self.steal_policy {
+						StealPolicy::Oldest => a.started.cmp(&b.started),
+						StealPolicy::Quietest => a.gain().total_cmp(&b.gain()),
+						StealPolicy::LowestPriority => a.priority.cmp(&b.priority).then(a.started.cmp(&b.started))
+					}
+				});
+			if let Some(i) = victim {
+				let sound = &mut self.sounds[i];
+				sound.envelope.to(0.0, frames);
+				sound.stopping = true;
+				let _ = self.events.try_send(Event::Stolen(sound.id));
 			}
 		}
 	}
@@ -266,12 +1339,13 @@ impl Mixer {
 	/// if the sound is playing, it will pause. if play is called,
 	/// this sound will continue from where it was when pause.
 	/// if the sound is not playing, does nothing
-	pub fn pause (&mut self, id: SoundId) {
-		for i in (0..self.playing).rev() {
-			if self.sounds[i].id == id {
+	fn pause (&mut self, id: SoundId) {
+		if let Some(i) = self.find(id) {
+			if i < self.playing {
+				self.sounds[i].state.playing.store(false, Ordering::Relaxed);
 				self.playing -= 1;
 				self.sounds.swap(self.playing, i);
-				break;
+				self.remove_dropped(self.playing);
 			}
 		}
 	}
@@ -281,148 +1355,490 @@ impl Mixer {
 	/// when play is called this sound will start from the beggining
 	/// even if the sound is not playing, it will reset the sound to
 	/// the start
-	pub fn stop (&mut self, id: SoundId) {
-		for i in (0..self.sounds.len()).rev() {
-			if self.sounds[i].id == id {
-				self.sounds[i].data.reset();
-				if i < self.playing {
-					self.playing -= 1;
-					self.sounds.swap(self.playing, i);
-				}
-				break;
-			}
+	fn stop (&mut self, id: SoundId) {
+		self.pause(id);
+		self.reset(id);
+		if let Some(i) = self.find(id) {
+			self.sounds[i].envelope.set(1.0);
+			self.sounds[i].stopping = false;
 		}
 	}
 
 
 	/// this reset the sound to the start, the sound being played
 	/// or not
-	pub fn reset (&mut self, id: SoundId) {
-		for i in (0..self.sounds.len()).rev() {
-			if self.sounds[i].id == id {
-				self.sounds[i].data.reset();
-				break;
+	fn reset (&mut self, id: SoundId) {
+		if let Some(i) = self.find(id) {
+			self.sounds[i].reset();
+		}
+	}
+
+
+	/// continue the sound from `time`, if its source can seek
+	fn seek (&mut self, id: SoundId, time: Duration) {
+		let frame = self.frames(time);
+		if let Some(i) = self.find(id) {
+			let sound = &mut self.sounds[i];
+			match sound.data.seek(frame) {
+				Ok(()) => {
+					sound.position = sound.data.position().unwrap_or(frame);
+					sound.state.position.store(sound.position, Ordering::Relaxed);
+				},
+				Err(err) => log::warn!("failed to seek sound: {}", err)
 			}
 		}
 	}
 
 
 	/// set the volume of the sound
-	pub fn set_volume (&mut self, id: SoundId, volume: f32) {
-		for i in (0..self.sounds.len()).rev() {
-			if self.sounds[i].id == id {
-				self.sounds[i].volume = volume;
-				break;
+	fn set_volume (&mut self, id: SoundId, volume: f32) {
+		if let Some(i) = self.find(id) {
+			self.sounds[i].volume.set(volume);
+		}
+	}
+
+
+	/// the number of frames in `duration` at the output sample rate
+	fn frames (&self, duration: Duration) -> u64 {
+		(duration.as_secs_f64() * self.sample_rate.0 as f64).round() as u64
+	}
+
+
+	fn fade_to (&mut self, id: SoundId, volume: f32, duration: Duration) {
+		let frames = self.frames(duration);
+		if let Some(i) = self.find(id) {
+			self.sounds[i].volume.to(volume, frames);
+		}
+	}
+
+
+	/// play the sound, fading in from silence if it wasn't playing
+	fn fade_in (&mut self, id: SoundId, duration: Duration) {
+		let frames = self.frames(duration);
+		if let Some(i) = self.find(id) {
+			let sound = &mut self.sounds[i];
+			if i >= self.playing {
+				sound.envelope.set(0.0);
+			}
+			sound.envelope.to(1.0, frames);
+			sound.stopping = false;
+			self.play(id);
+		}
+	}
+
+
+	/// fade the sound to silence, and stop it when done
+	fn fade_out (&mut self, id: SoundId, duration: Duration) {
+		let frames = self.frames(duration);
+		if let Some(i) = self.find(id) {
+			if i < self.playing {
+				self.sounds[i].envelope.to(0.0, frames);
+				self.sounds[i].stopping = true;
+			} else {
+				self.stop(id);
 			}
 		}
 	}
 
 
 	/// set if the sound will repeat ever time it reach the end
-	pub fn set_loop (&mut self, id: SoundId, looping: bool) {
-		for i in (0..self.sounds.len()).rev() {
-			if self.sounds[i].id == id {
-				self.sounds[i].looping = looping;
-				break;
+	fn set_loop (&mut self, id: SoundId, looping: bool) {
+		if let Some(i) = self.find(id) {
+			self.sounds[i].region.set_looping(looping);
+		}
+	}
+
+
+	/// set the playback speed, the speed of a positional sound is set on
+	/// every block
+	fn set_speed (&mut self, id: SoundId, speed: f32) {
+		if let Some(i) = self.find(id) {
+			let sound = &mut self.sounds[i];
+			sound.rate = speed.max(0.0);
+			if sound.emitter.is_none() {
+				sound.speed.set(sound.rate);
 			}
 		}
 	}
 
 
-	/// mark the sound to be dropped after it reaches the end
-	pub fn drop_sound (&mut self, id: SoundId) {
-		for i in (0..self.sounds.len()).rev() {
-			if self.sounds[i].id == id {
-				self.sounds[i].drop = true;
-				break;
+	fn set_priority (&mut self, id: SoundId, priority: i32) {
+		if let Some(i) = self.find(id) {
+			self.sounds[i].priority = priority;
+		}
+	}
+
+
+	fn set_loop_region (&mut self, id: SoundId, start: u64, end: Option<u64>) {
+		if let Some(i) = self.find(id) {
+			self.sounds[i].region.set(start, end);
+		}
+	}
+
+
+	fn add_marker (&mut self, id: SoundId, time: Duration) {
+		if let Some(i) = self.find(id) {
+			self.sounds[i].markers.push(time);
+		}
+	}
+
+
+	fn clear_markers (&mut self, id: SoundId) {
+		if let Some(i) = self.find(id) {
+			self.sounds[i].markers.clear();
+		}
+	}
+
+
+	/// make the sound positional, or reset its pan and speed
+	fn set_spatial (&mut self, id: SoundId, spatial: Option<Spatial>) {
+		if let Some(i) = self.find(id) {
+			let sound = &mut self.sounds[i];
+			match (&mut sound.emitter, spatial) {
+				(Some(emitter), Some(spatial)) => emitter.spatial = spatial,
+				(emitter, Some(spatial)) => *emitter = Some(Emitter::new(spatial)),
+				(emitter, None) => {
+					*emitter = None;
+					sound.distance_gain = None;
+					sound.panning.pan.set(0.0);
+					sound.speed.set(sound.rate);
+				}
 			}
 		}
 	}
 
 
-	/// update sound effect
-	pub fn update_effect (&mut self, id: SoundId, effect: impl FnMut(f32) -> f32 + 'static + std::marker::Send) {
-		for i in (0..self.sounds.len()).rev() {
-			if self.sounds[i].id == id {
-				self.sounds[i].effect = Box::new(effect);
-				break;
+	fn set_location (&mut self, id: SoundId, location: [f32; 2]) {
+		if let Some(i) = self.find(id) {
+			if let Some(emitter) = &mut self.sounds[i].emitter {
+				emitter.position = location;
 			}
 		}
 	}
 
 
-}
+	fn set_velocity (&mut self, id: SoundId, velocity: [f32; 2]) {
+		if let Some(i) = self.find(id) {
+			if let Some(emitter) = &mut self.sounds[i].emitter {
+				emitter.velocity = velocity;
+			}
+		}
+	}
 
-impl SoundSource for Mixer {
 
+	/// mark the sound to be dropped after it reaches the end, or when
+	/// it is paused or stopped
+	///
+	/// a sound that is not playing is dropped right away, unless it is
+	/// scheduled to start
+	fn drop_sound (&mut self, id: SoundId) {
+		if let Some(i) = self.find(id) {
+			self.sounds[i].drop = true;
+			self.remove_dropped(i);
+		}
+	}
 
-	fn channels (&self) -> u16 {
-		self.channels
+
+	/// remove the sound at `index` if it was dropped and nothing can
+	/// play it anymore: it is not playing and not scheduled to start
+	fn remove_dropped (&mut self, index: usize) {
+		let id = self.sounds[index].id;
+		let starts = self.schedule.iter().any(|(_, command)| match **command {
+			Command::Play(x) | Command::FadeIn(x, _) => x == id,
+			_ => false
+		});
+		if self.sounds[index].drop && index >= self.playing && !starts {
+			let sound = self.sounds.swap_remove(index);
+			self.discard(Garbage::Sound(sound));
+		}
 	}
 
 
-	fn sample_rate (&self) -> u32 {
-		self.sample_rate.0
+	/// add an effect to the end of the chain of the sound
+	fn add_effect (&mut self, id: SoundId, effect: Box<dyn Effect>) {
+		match self.find(id) {
+			Some(i) => self.sounds[i].effects.push(effect),
+			None => self.discard(Garbage::Effect(effect))
+		}
 	}
 
 
-	fn reset (&mut self) {}
+	/// remove all effects of the sound
+	fn clear_effects (&mut self, id: SoundId) {
+		if let Some(i) = self.find(id) {
+			while let Some(effect) = self.sounds[i].effects.pop() {
+				self.discard(Garbage::Effect(effect));
+			}
+		}
+	}
+
+
+	fn add_bus (&mut self, mut bus: Box<BusInner>, parent: BusId) {
+		bus.parent = self.find_bus(parent);
+		if bus.buf.len() != self.buf.len() {
+			// the config changed since the bus was created
+			bus.buf = vec![0.0; self.buf.len()];
+		}
+		self.buses.push(bus);
+	}
+
+
+	fn set_bus_volume (&mut self, id: BusId, volume: f32) {
+		let i = self.find_bus(id);
+		self.buses[i].volume = volume;
+	}
+
+
+	fn set_bus_muted (&mut self, id: BusId, muted: bool) {
+		let i = self.find_bus(id);
+		self.buses[i].muted = muted;
+	}
 
 
-	fn write_samples (&mut self, buffer: &mut [i16]) -> usize {
+	fn set_bus_paused (&mut self, id: BusId, paused: bool) {
+		let i = self.find_bus(id);
+		self.buses[i].paused = paused;
+	}
+
+
+	fn add_bus_effect (&mut self, id: BusId, effect: Box<dyn Effect>) {
+		let i = self.find_bus(id);
+		self.buses[i].effects.push(effect);
+	}
+
+
+	fn clear_bus_effects (&mut self, id: BusId) {
+		let i = self.find_bus(id);
+		while let Some(effect) = self.buses[i].effects.pop() {
+			self.discard(Garbage::Effect(effect));
+		}
+	}
 
-		if self.playing == 0 {
-			for b in buffer.iter_mut() {
-				*b = 0;
+
+	/// remove the bus, routing its sounds and children to its parent
+	///
+	/// the master bus is never removed
+	fn drop_bus (&mut self, id: BusId) {
+		let i = self.find_bus(id);
+		if i == 0 {
+			return;
+		}
+		let bus = self.buses.remove(i);
+		let parent = bus.parent;
+		self.discard(Garbage::Bus(bus));
+		// fix the indices shifted by the removal
+		let reroute = |index: &mut usize| {
+			if *index == i {
+				*index = parent;
+			} else if *index > i {
+				*index -= 1;
 			}
-			return buffer.len();
+		};
+		self.sounds.iter_mut().for_each(|x| reroute(&mut x.bus));
+		self.buses.iter_mut().skip(1).for_each(|x| reroute(&mut x.parent));
+	}
+
+
+	/// mix at most [`BLOCK_FRAMES`] frames into `buffer`
+	fn mix (&mut self, buffer: &mut [f32]) {
+
+		let samples = buffer.len();
+		for i in 0..self.buses.len() {
+			let halted = self.buses[i].paused || (i > 0 && self.buses[self.buses[i].parent].halted);
+			let bus = &mut self.buses[i];
+			bus.halted = halted;
+			bus.buf[..samples].fill(0.0);
 		}
 
-		let mut buf = vec![0; buffer.len()];
+		let channels = self.channels as usize;
+		let buf = &mut self.buf[..samples];
 		let mut s = 0;
 		while s < self.playing {
-			let mut len = 0;
-			loop {
-				len += self.sounds[s].data.write_samples(&mut buf[len..]);
-				if len < buffer.len() {
-					self.sounds[s].data.reset();
-					if self.sounds[s].looping {
-						continue;
+			let sound = &mut self.sounds[s];
+			let bus = &mut self.buses[sound.bus];
+			if bus.halted {
+				s += 1;
+				continue;
+			}
+
+			// the gain of a positional sound is ramped over the block
+			let (gain_from, gain_to) = match &sound.emitter {
+				Some(emitter) => {
+					let (gain, pan, speed) = emitter.render(&self.listener);
+					sound.panning.pan.set(pan);
+					sound.speed.set(speed * sound.rate);
+					let from = sound.distance_gain.unwrap_or(gain);
+					sound.distance_gain = Some(gain);
+					(from, gain)
+				},
+				None => (1.0, 1.0)
+			};
+
+			// looping sounds are wrapped by their `Looper`, so the sound
+			// only stops short when it ended
+			let from = sound.position;
+			let len = sound.data.write_samples(buf);
+			let error = sound.data.take_error();
+			let ended = len < buf.len() || error.is_some();
+			sound.position = match sound.data.position() {
+				Some(position) if !ended => position,
+				_ => from + (len / channels) as u64
+			};
+
+			// events are dropped if the game doesn't take them
+			let looped = sound.region.take_wrapped();
+			if looped {
+				let _ = self.events.try_send(Event::Looped(sound.id));
+			}
+			if !sound.markers.is_empty() {
+				let sample_rate = self.sample_rate.0 as f64;
+				let frame = |time: Duration| (time.as_secs_f64() * sample_rate).round() as u64;
+				let (loop_start, loop_end) = sound.region.times();
+				let (loop_start, loop_end) = (frame(loop_start), loop_end.map_or(u64::MAX, frame));
+				for &marker in sound.markers.iter() {
+					let m = frame(marker);
+					let reached = if looped {
+						(m >= from && m < loop_end) || (m >= loop_start && m < sound.position)
+					} else {
+						m >= from && m < sound.position
+					};
+					if reached {
+						let _ = self.events.try_send(Event::Marker(sound.id, marker));
 					}
 				}
-				break;
+			}
+			if let Some(error) = error {
+				sound.state.error.store(error.code(), Ordering::Relaxed);
+				let _ = self.events.try_send(Event::DecoderError(sound.id, error));
+			}
+
+			if ended {
+				sound.data.reset();
+				sound.position = 0;
 			}
 
-			if (self.sounds[s].volume - 1.0).abs() < 1.0 / i16::max_value() as f32 {
-				for i in 0..len {
-					buffer[i] = buffer[i].saturating_add((self.sounds[s].effect)(buf[i] as f32) as i16);
+			for effect in sound.effects.iter_mut() {
+				effect.process(&mut buf[..len], self.channels, self.sample_rate.0);
+			}
+			if gain_from == gain_to && !sound.volume.is_moving() && !sound.envelope.is_moving() {
+				let gain = sound.volume.value * sound.envelope.value * gain_to;
+				for (b, x) in bus.buf[..len].iter_mut().zip(buf[..len].iter()) {
+					*b += x * gain;
 				}
 			} else {
-				for i in 0..len {
-					buffer[i] = buffer[i].saturating_add(((self.sounds[s].effect)(buf[i] as f32) * self.sounds[s].volume) as i16);
+				// interpolate the gains for every frame
+				let step = (gain_to - gain_from) / (buf.len() / channels) as f32;
+				let frames = bus.buf[..len].chunks_exact_mut(channels).zip(buf[..len].chunks_exact(channels));
+				for (i, (b, x)) in frames.enumerate() {
+					let gain = sound.volume.next() * sound.envelope.next() * (gain_from + step * (i + 1) as f32);
+					for (b, x) in b.iter_mut().zip(x) {
+						*b += x * gain;
+					}
 				}
 			}
+			sound.state.position.store(sound.position, Ordering::Relaxed);
+
+			let faded_out = sound.stopping && !sound.envelope.is_moving();
+			if faded_out {
+				sound.reset();
+				sound.envelope.set(1.0);
+				sound.stopping = false;
+			}
 
-			if len < buffer.len() {
-				if self.sounds[s].drop {
-					let _ = self.sounds.swap_remove(s);
+			if ended && !faded_out && error.is_none() {
+				let _ = self.events.try_send(Event::Finished(sound.id));
+			}
+			if ended || faded_out {
+				// the sound ended, move it out of the playing region
+				for effect in sound.effects.iter_mut() {
+					effect.reset();
 				}
+				sound.state.playing.store(false, Ordering::Relaxed);
 				self.playing -= 1;
-				if self.playing > 0 && self.playing < self.sounds.len() {
-					self.sounds.swap(s, self.playing);
-				} else {
-					break;
+				self.sounds.swap(s, self.playing);
+				if self.sounds[self.playing].drop {
+					// the same as `discard`, which would borrow `buf`
+					let sound = self.sounds.swap_remove(self.playing);
+					let _ = self.garbage.try_send(Garbage::Sound(sound));
 				}
 			} else {
 				s += 1;
 			}
 		}
 
-		buffer.len()
+		// children always come after their parent, so going backwards
+		// each bus is complete before it is mixed into its parent
+		for i in (1..self.buses.len()).rev() {
+			let (parents, children) = self.buses.split_at_mut(i);
+			let bus = &mut children[0];
+			if bus.halted {
+				continue;
+			}
+			bus.process(samples, self.channels, self.sample_rate.0);
+			if !bus.muted {
+				let parent = &mut parents[bus.parent];
+				for (b, x) in parent.buf[..samples].iter_mut().zip(bus.buf[..samples].iter()) {
+					*b += x * bus.volume;
+				}
+			}
+		}
+
+		let master = &mut self.buses[0];
+		if master.halted || master.muted {
+			buffer.fill(0.0);
+			return;
+		}
+		master.process(samples, self.channels, self.sample_rate.0);
+		for (b, x) in buffer.iter_mut().zip(master.buf[..samples].iter()) {
+			*b = x * master.volume;
+		}
 
 	}
 
 
 }
 
+impl SoundSource for Mixer {
+
+
+	fn channels (&self) -> u16 {
+		self.channels
+	}
+
+
+	fn sample_rate (&self) -> u32 {
+		self.sample_rate.0
+	}
+
+
+	fn reset (&mut self) {}
+
+
+	fn write_samples (&mut self, buffer: &mut [f32]) -> usize {
 
+		self.process_commands();
+
+		// split the buffer at the scheduled commands, so they are
+		// applied on their exact frame
+		let channels = self.channels as usize;
+		let written = buffer.len();
+		let mut rest = buffer;
+		while !rest.is_empty() {
+			self.process_schedule();
+			let mut len = rest.len().min(self.buf.len());
+			if let Some(&(frame, _)) = self.schedule.last() {
+				len = len.min((frame - self.clock) as usize * channels);
+			}
+			let (chunk, next) = rest.split_at_mut(len);
+			self.mix(chunk);
+			self.clock += (len / channels) as u64;
+			rest = next;
+		}
+		self.state.clock.store(self.clock, Ordering::Relaxed);
+
+		written
+
+	}
+
+
+}
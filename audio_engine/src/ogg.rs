@@ -0,0 +1,298 @@
+
+
+
+use lewton::VorbisError;
+use lewton::audio::AudioReadError;
+use lewton::inside_ogg::OggStreamReader;
+use lewton::samples::InterleavedSamples;
+use log::error;
+
+use std::io::{ Read, Seek };
+
+use crate::error::SourceError;
+use crate::mixer::SoundSource;
+
+
+
+/// Ogg Vorbis File Decoder
+///
+/// chained logical streams are played one after the other. the
+/// channel count and sample rate of the first stream are the ones
+/// reported to the mixer, later streams with a different channel
+/// count are remapped to it, and a later stream with a different
+/// sample rate ends the file with [`SourceError::Unsupported`].
+///
+/// the `LOOPSTART` comment, with `LOOPLENGTH` or `LOOPEND`, is used
+/// as the [loop region](SoundSource::loop_region) of the file. seeking
+/// backward or far forward jumps to the page of the new position by
+/// its granule position, and decodes from there. once a file is known
+/// to be chained, seeking backward decodes from the start instead
+pub struct OggDecoder <T: Read + Seek> {
+
+	reader: OggStreamReader<T>,
+	channels: u16,
+	sample_rate: u32,
+	/// the serial of the first logical stream
+	serial: u32,
+	/// whether a later logical stream was reached
+	chained: bool,
+
+	/// interleaved samples of the last decoded packets
+	buffer: Vec<f32>,
+	/// index of the next sample in `buffer` to be written
+	pos: usize,
+	/// the next frame to be written
+	position: u64,
+	loop_region: Option<(u64, Option<u64>)>,
+	/// the last decoding error, until it is taken
+	error: Option<SourceError>
+
+}
+
+impl <T: Read + Seek> OggDecoder<T> {
+
+
+	/// Create a new ogg vorbis file decoder
+	pub fn new (data: T) -> Result<Self, lewton::VorbisError> {
+		let reader = OggStreamReader::new(data)?;
+		let loop_region = read_loop_region(&reader.comment_hdr.comment_list);
+		Ok(Self {
+			channels: reader.ident_hdr.audio_channels as u16,
+			sample_rate: reader.ident_hdr.audio_sample_rate,
+			serial: reader.stream_serial(),
+			chained: false,
+			reader,
+			buffer: Vec::new(),
+			pos: 0,
+			position: 0,
+			loop_region,
+			error: None
+		})
+	}
+
+
+	/// the comments of the current logical stream, as key-value pairs
+	pub fn comments (&self) -> &[(String, String)] {
+		&self.reader.comment_hdr.comment_list
+	}
+
+
+	/// decode the next packet into `self.buffer`
+	///
+	/// return false if the stream has ended or an error occurred
+	fn next_packet (&mut self) -> bool {
+		self.buffer.clear();
+		self.pos = 0;
+		while self.buffer.is_empty() {
+			match self.decode_packet() {
+				Ok(true) => (),
+				Ok(false) => return false,
+				Err(err) => {
+					self.error = Some(err);
+					return false;
+				}
+			}
+		}
+		true
+	}
+
+
+	/// decode the next packet at the end of `self.buffer`, remapped to
+	/// the channels of the first stream
+	///
+	/// return false if the stream has ended
+	fn decode_packet (&mut self) -> Result<bool, SourceError> {
+
+		let packet = match self.reader.read_dec_packet_generic::<InterleavedSamples<f32>>() {
+			Ok(Some(x)) => x.samples,
+			Ok(None) => return Ok(false),
+			// the headers read after a seek to the start of the file
+			Err(VorbisError::BadAudio(AudioReadError::AudioIsHeader)) => return Ok(true),
+			Err(err) => {
+				error!("error while decoding ogg: {}", err);
+				return Err(err.into());
+			}
+		};
+
+		let stream_channels = self.reader.ident_hdr.audio_channels as u16;
+		if self.reader.stream_serial() != self.serial {
+			self.chained = true;
+			if self.reader.ident_hdr.audio_sample_rate != self.sample_rate {
+				error!(
+					"chained ogg stream has sample rate {}, expected {}",
+					self.reader.ident_hdr.audio_sample_rate,
+					self.sample_rate
+				);
+				return Err(SourceError::Unsupported);
+			}
+		}
+
+		if stream_channels == self.channels {
+			self.buffer.extend_from_slice(&packet);
+		} else {
+			// a chained stream with a different layout, duplicate or
+			// drop channels to keep the output layout.
+			let frames = packet.len() / stream_channels as usize;
+			for f in 0..frames {
+				for c in 0..self.channels as usize {
+					let c = c % stream_channels as usize;
+					self.buffer.push(packet[f * stream_channels as usize + c]);
+				}
+			}
+		}
+		Ok(true)
+
+	}
+
+
+	/// go back to the start of the file, where the headers of the first
+	/// stream are read again
+	fn rewind (&mut self) -> Result<(), SourceError> {
+		self.reader.seek_absgp_pg(0)?;
+		self.buffer.clear();
+		self.pos = 0;
+		self.position = 0;
+		Ok(())
+	}
+
+
+	/// jump to a page starting at or before `frame`
+	///
+	/// the granule position of a page is the frame after its last
+	/// packet, and the first packet after a seek only primes the
+	/// decoder, so the packets are decoded up to the end of a page to
+	/// learn the frame they start from, seeking earlier if it is after
+	/// `frame`.
+	fn seek_page (&mut self, frame: u64) -> Result<(), SourceError> {
+
+		// a packet decodes to at most half of the long block
+		let back = 1 << self.reader.ident_hdr.blocksize_1;
+		let mut goal = frame;
+		loop {
+			// granule positions 0 are the headers
+			self.reader.seek_absgp_pg(goal.max(1))?;
+			self.buffer.clear();
+			self.pos = 0;
+
+			let end = loop {
+				if !self.decode_packet()? {
+					// past the end of the stream
+					self.buffer.clear();
+					self.position = frame;
+					return Ok(());
+				}
+				if let Some(end) = self.reader.get_last_absgp() {
+					break end;
+				}
+			};
+
+			let start = end.saturating_sub((self.buffer.len() / self.channels as usize) as u64);
+			if start <= frame || goal <= 1 {
+				self.position = start;
+				return Ok(());
+			}
+			goal = goal.min(start).saturating_sub(back);
+		}
+
+	}
+
+
+}
+
+impl <T: Read + Seek> SoundSource for OggDecoder<T> {
+
+
+	fn reset (&mut self) {
+		if let Err(err) = self.seek(0) {
+			error!("error while rewinding ogg: {}", err);
+			self.error = Some(err);
+		}
+	}
+
+
+	fn channels (&self) -> u16 {
+		self.channels
+	}
+
+
+	fn sample_rate (&self) -> u32 {
+		self.sample_rate
+	}
+
+
+	fn write_samples (&mut self, buffer: &mut [f32]) -> usize {
+
+		let mut len = 0;
+		while len < buffer.len() {
+			if self.pos >= self.buffer.len() && !self.next_packet() {
+				break;
+			}
+			let n = (buffer.len() - len).min(self.buffer.len() - self.pos);
+			buffer[len..len + n].copy_from_slice(&self.buffer[self.pos..self.pos + n]);
+			self.pos += n;
+			len += n;
+		}
+		self.position += (len / self.channels as usize) as u64;
+		len
+
+	}
+
+
+	fn seek (&mut self, frame: u64) -> Result<(), SourceError> {
+
+		// granule positions start again in each chained stream, so only
+		// the start of the file can be found in them
+		if self.chained || frame == 0 {
+			if frame < self.position {
+				self.rewind()?;
+			}
+		} else if frame < self.position || frame - self.position > self.sample_rate as u64 {
+			self.seek_page(frame)?;
+		}
+
+		// decode up to the frame, unless the seek went past the end
+		let channels = self.channels as usize;
+		while self.position < frame {
+			if self.pos >= self.buffer.len() && !self.next_packet() {
+				break;
+			}
+			let n = ((frame - self.position) as usize).min((self.buffer.len() - self.pos) / channels);
+			self.pos += n * channels;
+			self.position += n as u64;
+		}
+		Ok(())
+
+	}
+
+
+	fn position (&self) -> Option<u64> {
+		Some(self.position)
+	}
+
+
+	fn loop_region (&self) -> Option<(u64, Option<u64>)> {
+		self.loop_region
+	}
+
+
+	fn take_error (&mut self) -> Option<SourceError> {
+		self.error.take()
+	}
+
+
+}
+
+
+
+/// the loop points in the `LOOPSTART` and `LOOPLENGTH` or `LOOPEND`
+/// comments, as used by rpg maker and many game engines
+fn read_loop_region (comments: &[(String, String)]) -> Option<(u64, Option<u64>)> {
+	let find = |key: &str| {
+		comments.iter()
+			.find(|(k, _)| k.eq_ignore_ascii_case(key))
+			.and_then(|(_, v)| v.trim().parse::<u64>().ok())
+	};
+	let start = find("LOOPSTART")?;
+	let end = find("LOOPLENGTH").map(|length| start + length).or_else(|| find("LOOPEND"));
+	Some((start, end))
+}
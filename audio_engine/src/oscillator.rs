@@ -0,0 +1,586 @@
+
+
+
+use crate::effect::Param;
+use crate::error::SourceError;
+use crate::mixer::SoundSource;
+
+use std::f64::consts::TAU;
+use std::time::Duration;
+
+
+
+/// the sample rate of the generated sources, unless they are created
+/// with another one
+pub const GENERATOR_SAMPLE_RATE: u32 = 48000;
+
+
+
+/// the length of a generated source and the frame it is at
+#[derive(Debug, Clone, Copy)]
+struct Length {
+	/// `None` for a source that never ends
+	len: Option<u64>,
+	position: u64
+}
+
+impl Length {
+
+
+	fn new (duration: Option<Duration>, sample_rate: u32) -> Self {
+		Self {
+			len: duration.map(|x| (x.as_secs_f64() * sample_rate as f64).round() as u64),
+			position: 0
+		}
+	}
+
+
+	/// advance by at most `frames` frames, and return by how many
+	fn advance (&mut self, frames: usize) -> usize {
+		let frames = match self.len {
+			Some(len) => (len.saturating_sub(self.position)).min(frames as u64) as usize,
+			None => frames
+		};
+		self.position += frames as u64;
+		frames
+	}
+
+
+	fn seek (&mut self, frame: u64) {
+		self.position = match self.len {
+			Some(len) => frame.min(len),
+			None => frame
+		};
+	}
+
+
+}
+
+
+
+/// an amplitude that is ramped over each written buffer, so changing
+/// it doesn't click
+#[derive(Debug, Clone)]
+struct Amplitude {
+	param: Param,
+	/// the amplitude at the end of the last buffer
+	last: f32
+}
+
+impl Amplitude {
+
+
+	fn new (amplitude: f32) -> Self {
+		Self {
+			param: Param::new(amplitude),
+			last: amplitude
+		}
+	}
+
+
+	/// multiply `buffer` by the amplitude, moving linearly to the new one
+	fn apply (&mut self, buffer: &mut [f32]) {
+		let target = self.param.get();
+		let step = (target - self.last) / buffer.len().max(1) as f32;
+		for (i, b) in buffer.iter_mut().enumerate() {
+			*b *= self.last + step * (i + 1) as f32;
+		}
+		self.last = target;
+	}
+
+
+}
+
+
+
+/// the shape of the wave of an [`Oscillator`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Waveform {
+	Sine,
+	/// a pulse wave, that is high for its
+	/// [pulse width](Oscillator::pulse_width) of each period
+	Square,
+	/// a wave that rises linearly, then falls back at once
+	Saw,
+	Triangle
+}
+
+
+
+/// a mono source of a periodic wave
+///
+/// the frequency, in Hz, the amplitude and the pulse width of a square
+/// wave are [`Param`]s, so they can be changed while the sound plays.
+/// they are read at the start of each write, and the amplitude is
+/// ramped over it. the square and the saw waves are band limited, so
+/// high notes don't alias
+///
+/// ```
+/// # use audio_engine::Oscillator;
+/// let tone = Oscillator::sine(440.0);
+/// let frequency = tone.frequency();
+/// // engine.new_sound(tone, None)
+/// // an octave higher
+/// frequency.set(880.0);
+/// ```
+pub struct Oscillator {
+	waveform: Waveform,
+	frequency: Param,
+	amplitude: Amplitude,
+	pulse_width: Param,
+	sample_rate: u32,
+	/// the position in the period, from 0 to 1
+	phase: f64,
+	length: Length
+}
+
+impl Oscillator {
+
+
+	/// an oscillator that never ends, with an amplitude of 1 and a
+	/// pulse width of 0.5
+	pub fn new (waveform: Waveform, frequency: f32) -> Self {
+		Self {
+			waveform,
+			frequency: Param::new(frequency),
+			amplitude: Amplitude::new(1.0),
+			pulse_width: Param::new(0.5),
+			sample_rate: GENERATOR_SAMPLE_RATE,
+			phase: 0.0,
+			length: Length::new(None, GENERATOR_SAMPLE_RATE)
+		}
+	}
+
+
+	pub fn sine (frequency: f32) -> Self {
+		Self::new(Waveform::Sine, frequency)
+	}
+
+
+	/// a square wave that is high for `pulse_width` of each period,
+	/// from 0 to 1
+	pub fn square (frequency: f32, pulse_width: f32) -> Self {
+		let oscillator = Self::new(Waveform::Square, frequency);
+		oscillator.pulse_width.set(pulse_width);
+		oscillator
+	}
+
+
+	pub fn saw (frequency: f32) -> Self {
+		Self::new(Waveform::Saw, frequency)
+	}
+
+
+	pub fn triangle (frequency: f32) -> Self {
+		Self::new(Waveform::Triangle, frequency)
+	}
+
+
+	/// end the oscillator after `duration`
+	pub fn with_duration (mut self, duration: Duration) -> Self {
+		self.length = Length::new(Some(duration), self.sample_rate);
+		self
+	}
+
+
+	/// generate the wave at `sample_rate`, instead of
+	/// [`GENERATOR_SAMPLE_RATE`]
+	pub fn with_sample_rate (mut self, sample_rate: u32) -> Self {
+		let duration = self.length.len.map(|len| Duration::from_secs_f64(len as f64 / self.sample_rate as f64));
+		self.sample_rate = sample_rate.max(1);
+		self.length = Length::new(duration, self.sample_rate);
+		self
+	}
+
+
+	/// set the initial amplitude
+	pub fn with_amplitude (self, amplitude: f32) -> Self {
+		Self {
+			amplitude: Amplitude::new(amplitude),
+			..self
+		}
+	}
+
+
+	pub fn waveform (&self) -> Waveform {
+		self.waveform
+	}
+
+
+	/// the frequency, in Hz
+	pub fn frequency (&self) -> Param {
+		self.frequency.clone()
+	}
+
+
+	/// the peak of the wave, from 0 to 1
+	pub fn amplitude (&self) -> Param {
+		self.amplitude.param.clone()
+	}
+
+
+	/// the part of each period a square wave is high, from 0 to 1
+	pub fn pulse_width (&self) -> Param {
+		self.pulse_width.clone()
+	}
+
+
+	/// the value of the wave at `phase`, `dt` being the phase advanced
+	/// every frame
+	fn sample (&self, phase: f64, dt: f64, pulse_width: f64) -> f64 {
+		match self.waveform {
+			Waveform::Sine => (TAU * phase).sin(),
+			Waveform::Square => {
+				let high = if phase < pulse_width { 1.0 } else { -1.0 };
+				high + poly_blep(phase, dt) - poly_blep((phase - pulse_width).rem_euclid(1.0), dt)
+			},
+			Waveform::Saw => 2.0 * phase - 1.0 - poly_blep(phase, dt),
+			Waveform::Triangle => 1.0 - 4.0 * ((phase + 0.25).fract() - 0.5).abs()
+		}
+	}
+
+
+}
+
+impl SoundSource for Oscillator {
+
+
+	fn channels (&self) -> u16 {
+		1
+	}
+
+
+	fn sample_rate (&self) -> u32 {
+		self.sample_rate
+	}
+
+
+	fn reset (&mut self) {
+		self.phase = 0.0;
+		self.length.position = 0;
+	}
+
+
+	fn write_samples (&mut self, buffer: &mut [f32]) -> usize {
+
+		let len = self.length.advance(buffer.len());
+		let buffer = &mut buffer[..len];
+
+		let nyquist = self.sample_rate as f64 / 2.0;
+		let dt = (self.frequency.get() as f64).clamp(0.0, nyquist) / self.sample_rate as f64;
+		let pulse_width = (self.pulse_width.get() as f64).clamp(0.0, 1.0);
+		for b in buffer.iter_mut() {
+			*b = self.sample(self.phase, dt, pulse_width) as f32;
+			self.phase = (self.phase + dt).fract();
+		}
+		self.amplitude.apply(buffer);
+		len
+
+	}
+
+
+	fn seek (&mut self, frame: u64) -> Result<(), SourceError> {
+		self.length.seek(frame);
+		let frequency = self.frequency.get() as f64;
+		self.phase = (frame as f64 * frequency / self.sample_rate as f64).rem_euclid(1.0);
+		Ok(())
+	}
+
+
+	fn len_frames (&self) -> Option<u64> {
+		self.length.len
+	}
+
+
+	fn position (&self) -> Option<u64> {
+		Some(self.length.position)
+	}
+
+
+}
+
+
+
+/// the correction of a step from -1 to 1 at phase 0, so a wave with
+/// the step is band limited
+fn poly_blep (phase: f64, dt: f64) -> f64 {
+	if dt <= 0.0 {
+		0.0
+	} else if phase < dt {
+		let t = phase / dt;
+		2.0 * t - t * t - 1.0
+	} else if phase > 1.0 - dt {
+		let t = (phase - 1.0) / dt;
+		t * t + 2.0 * t + 1.0
+	} else {
+		0.0
+	}
+}
+
+
+
+/// a xorshift random number generator, that gives the same numbers for
+/// the same seed
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Random(u32);
+
+impl Random {
+
+
+	pub fn new (seed: u32) -> Self {
+		// xorshift stays at 0 forever
+		Self(seed.max(1))
+	}
+
+
+	/// the next number, from -1 to 1
+	pub fn next (&mut self) -> f32 {
+		self.0 ^= self.0 << 13;
+		self.0 ^= self.0 >> 17;
+		self.0 ^= self.0 << 5;
+		self.0 as f32 / u32::MAX as f32 * 2.0 - 1.0
+	}
+
+
+}
+
+
+
+/// the spectrum of a [`Noise`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoiseKind {
+	/// the same power in every frequency
+	White,
+	/// the same power in every octave, softer than white noise
+	Pink
+}
+
+
+
+/// a mono source of random noise
+///
+/// the amplitude is a [`Param`], ramped over each write like the one
+/// of an [`Oscillator`]. the noise is the same every time it is reset,
+/// and differs by its seed
+pub struct Noise {
+	kind: NoiseKind,
+	amplitude: Amplitude,
+	sample_rate: u32,
+	seed: u32,
+	random: Random,
+	/// the filters that make pink noise from white noise
+	pink: [f32; 7],
+	length: Length
+}
+
+impl Noise {
+
+
+	/// a noise that never ends, with an amplitude of 1
+	pub fn new (kind: NoiseKind) -> Self {
+		Self {
+			kind,
+			amplitude: Amplitude::new(1.0),
+			sample_rate: GENERATOR_SAMPLE_RATE,
+			seed: 1,
+			random: Random::new(1),
+			pink: [0.0; 7],
+			length: Length::new(None, GENERATOR_SAMPLE_RATE)
+		}
+	}
+
+
+	pub fn white () -> Self {
+		Self::new(NoiseKind::White)
+	}
+
+
+	pub fn pink () -> Self {
+		Self::new(NoiseKind::Pink)
+	}
+
+
+	/// generate another noise, that is the same for the same `seed`
+	pub fn with_seed (mut self, seed: u32) -> Self {
+		self.seed = seed;
+		self.random = Random::new(seed);
+		self
+	}
+
+
+	/// end the noise after `duration`
+	pub fn with_duration (mut self, duration: Duration) -> Self {
+		self.length = Length::new(Some(duration), self.sample_rate);
+		self
+	}
+
+
+	/// generate the noise at `sample_rate`, instead of
+	/// [`GENERATOR_SAMPLE_RATE`]
+	pub fn with_sample_rate (mut self, sample_rate: u32) -> Self {
+		let duration = self.length.len.map(|len| Duration::from_secs_f64(len as f64 / self.sample_rate as f64));
+		self.sample_rate = sample_rate.max(1);
+		self.length = Length::new(duration, self.sample_rate);
+		self
+	}
+
+
+	/// set the initial amplitude
+	pub fn with_amplitude (self, amplitude: f32) -> Self {
+		Self {
+			amplitude: Amplitude::new(amplitude),
+			..self
+		}
+	}
+
+
+	pub fn kind (&self) -> NoiseKind {
+		self.kind
+	}
+
+
+	/// the peak of the noise, from 0 to 1
+	pub fn amplitude (&self) -> Param {
+		self.amplitude.param.clone()
+	}
+
+
+	/// the next pink noise sample, filtering white noise with the
+	/// method of paul kellet
+	fn pink_sample (&mut self) -> f32 {
+		let white = self.random.next();
+		let b = &mut self.pink;
+		b[0] = 0.99886 * b[0] + white * 0.0555179;
+		b[1] = 0.99332 * b[1] + white * 0.0750759;
+		b[2] = 0.96900 * b[2] + white * 0.153852;
+		b[3] = 0.86650 * b[3] + white * 0.3104856;
+		b[4] = 0.55000 * b[4] + white * 0.5329522;
+		b[5] = -0.7616 * b[5] - white * 0.0168980;
+		let pink = b[0] + b[1] + b[2] + b[3] + b[4] + b[5] + b[6] + white * 0.5362;
+		b[6] = white * 0.115926;
+		// about the same peak as white noise
+		(pink * 0.11).clamp(-1.0, 1.0)
+	}
+
+
+}
+
+impl SoundSource for Noise {
+
+
+	fn channels (&self) -> u16 {
+		1
+	}
+
+
+	fn sample_rate (&self) -> u32 {
+		self.sample_rate
+	}
+
+
+	fn reset (&mut self) {
+		self.random = Random::new(self.seed);
+		self.pink = [0.0; 7];
+		self.length.position = 0;
+	}
+
+
+	fn write_samples (&mut self, buffer: &mut [f32]) -> usize {
+
+		let len = self.length.advance(buffer.len());
+		let buffer = &mut buffer[..len];
+		for b in buffer.iter_mut() {
+			*b = match self.kind {
+				NoiseKind::White => self.random.next(),
+				NoiseKind::Pink => self.pink_sample()
+			};
+		}
+		self.amplitude.apply(buffer);
+		len
+
+	}
+
+
+	/// noise sounds the same from anywhere, so this only moves the
+	/// position
+	fn seek (&mut self, frame: u64) -> Result<(), SourceError> {
+		self.length.seek(frame);
+		Ok(())
+	}
+
+
+	fn len_frames (&self) -> Option<u64> {
+		self.length.len
+	}
+
+
+	fn position (&self) -> Option<u64> {
+		Some(self.length.position)
+	}
+
+
+}
+
+
+
+/// a mono source of silence, to fill a gap of a given length
+pub struct Silence {
+	sample_rate: u32,
+	length: Length
+}
+
+impl Silence {
+
+
+	pub fn new (duration: Duration) -> Self {
+		Self {
+			sample_rate: GENERATOR_SAMPLE_RATE,
+			length: Length::new(Some(duration), GENERATOR_SAMPLE_RATE)
+		}
+	}
+
+
+}
+
+impl SoundSource for Silence {
+
+
+	fn channels (&self) -> u16 {
+		1
+	}
+
+
+	fn sample_rate (&self) -> u32 {
+		self.sample_rate
+	}
+
+
+	fn reset (&mut self) {
+		self.length.position = 0;
+	}
+
+
+	fn write_samples (&mut self, buffer: &mut [f32]) -> usize {
+		let len = self.length.advance(buffer.len());
+		buffer[..len].fill(0.0);
+		len
+	}
+
+
+	fn seek (&mut self, frame: u64) -> Result<(), SourceError> {
+		self.length.seek(frame);
+		Ok(())
+	}
+
+
+	fn len_frames (&self) -> Option<u64> {
+		self.length.len
+	}
+
+
+	fn position (&self) -> Option<u64> {
+		Some(self.length.position)
+	}
+
+
+}
@@ -0,0 +1,174 @@
+
+
+
+use crate::effect::Param;
+use crate::error::SourceError;
+use crate::mixer::{ BLOCK_FRAMES, SoundSource };
+
+use std::f64::consts::{ FRAC_PI_2, FRAC_PI_4 };
+
+
+
+/// the pan and width of a sound, shared by `Sound` and its `Panner`
+#[derive(Clone)]
+pub(crate) struct Panning {
+	pub pan: Param,
+	pub width: Param
+}
+
+impl Panning {
+
+	pub fn new () -> Self {
+		Self {
+			pan: Param::new(0.0),
+			width: Param::new(1.0)
+		}
+	}
+
+
+	/// return true if a source with `source` channels is panned when
+	/// the output has `channels` channels
+	pub fn applies (source: u16, channels: u16) -> bool {
+		(1..=2).contains(&source) && channels >= 2
+	}
+
+}
+
+
+
+/// pan a mono or stereo source, outputting stereo
+///
+/// mono sources use a constant power pan law, with a gain of 1 on both
+/// sides when centered. stereo sources are first narrowed or widened
+/// around their center, then the pan moves each side toward the other
+/// one, like the Web Audio `StereoPannerNode`
+///
+/// when the pan or width change, the gains are interpolated over the
+/// next block to avoid clicks
+pub(crate) struct Panner <T: SoundSource> {
+	inner: T,
+	panning: Panning,
+	/// the gains of the last block, `[ll, rl, lr, rr]` where `rl` is
+	/// the gain of the right input in the left output
+	gains: [f32; 4],
+	/// scratch buffer for up to `BLOCK_FRAMES` frames of `inner`
+	in_buffer: Box<[f32]>
+}
+
+impl <T: SoundSource> Panner<T> {
+
+
+	pub fn new (inner: T, panning: Panning) -> Self {
+		assert!(Panning::applies(inner.channels(), 2), "only mono and stereo sources can be panned");
+		let in_buffer = vec![0.0; BLOCK_FRAMES * inner.channels() as usize];
+		let mut panner = Self {
+			inner,
+			panning,
+			gains: [0.0; 4],
+			in_buffer: in_buffer.into_boxed_slice()
+		};
+		panner.gains = panner.target();
+		panner
+	}
+
+
+	/// the gains for the current pan and width
+	fn target (&self) -> [f32; 4] {
+		let pan = (self.panning.pan.get() as f64).clamp(-1.0, 1.0);
+
+		if self.inner.channels() == 1 {
+			// the mono input is in both columns, only use the first one. the
+			// power is the one of the input, so each side is at -3dB when
+			// centered and at 0dB when panned to it
+			let angle = (pan + 1.0) * FRAC_PI_4;
+			return [angle.cos() as f32, 0.0, angle.sin() as f32, 0.0];
+		}
+
+		let width = (self.panning.width.get() as f64).max(0.0);
+		let (same, other) = ((1.0 + width) / 2.0, (1.0 - width) / 2.0);
+		let width = [same, other, other, same];
+
+		let pan = if pan == 0.0 {
+			[1.0, 0.0, 0.0, 1.0]
+		} else if pan < 0.0 {
+			let angle = (pan + 1.0) * FRAC_PI_2;
+			[1.0, angle.cos(), 0.0, angle.sin()]
+		} else {
+			let angle = pan * FRAC_PI_2;
+			[angle.cos(), 0.0, angle.sin(), 1.0]
+		};
+
+		[
+			(pan[0] * width[0] + pan[1] * width[2]) as f32,
+			(pan[0] * width[1] + pan[1] * width[3]) as f32,
+			(pan[2] * width[0] + pan[3] * width[2]) as f32,
+			(pan[2] * width[1] + pan[3] * width[3]) as f32
+		]
+	}
+
+
+}
+
+impl <T: SoundSource> SoundSource for Panner<T> {
+
+	fn channels (&self) -> u16 {
+		2
+	}
+
+	fn sample_rate (&self) -> u32 {
+		self.inner.sample_rate()
+	}
+
+	fn reset (&mut self) {
+		self.inner.reset()
+	}
+
+	fn seek (&mut self, frame: u64) -> Result<(), SourceError> {
+		self.inner.seek(frame)
+	}
+
+	fn len_frames (&self) -> Option<u64> {
+		self.inner.len_frames()
+	}
+
+	fn position (&self) -> Option<u64> {
+		self.inner.position()
+	}
+
+	fn loop_region (&self) -> Option<(u64, Option<u64>)> {
+		self.inner.loop_region()
+	}
+
+	fn take_error (&mut self) -> Option<SourceError> {
+		self.inner.take_error()
+	}
+
+	fn write_samples (&mut self, buffer: &mut [f32]) -> usize {
+		let in_channels = self.inner.channels() as usize;
+		let mut written = 0;
+		for chunk in buffer.chunks_mut(BLOCK_FRAMES * 2) {
+			let frames = chunk.len() / 2;
+			let (from, to) = (self.gains, self.target());
+			self.gains = to;
+
+			let in_buffer = &mut self.in_buffer[..frames * in_channels];
+			let len = self.inner.write_samples(in_buffer);
+			let step = 1.0 / frames as f32;
+			let in_frames = in_buffer[..len].chunks_exact(in_channels);
+			for (i, (x, out)) in in_frames.zip(chunk.chunks_exact_mut(2)).enumerate() {
+				let t = (i + 1) as f32 * step;
+				let g = |k: usize| from[k] + (to[k] - from[k]) * t;
+				let (l, r) = (x[0], x[in_channels - 1]);
+				out[0] = g(0) * l + g(1) * r;
+				out[1] = g(2) * l + g(3) * r;
+			}
+
+			written += len / in_channels * 2;
+			if len < in_buffer.len() {
+				break;
+			}
+		}
+		written
+	}
+
+}
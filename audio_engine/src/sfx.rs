@@ -0,0 +1,469 @@
+
+
+
+use crate::buffer::SoundBuffer;
+use crate::effect::{ Effect, Param };
+use crate::envelope::Adsr;
+use crate::mixer::SoundSource;
+use crate::oscillator::{ Oscillator, Random, Waveform, GENERATOR_SAMPLE_RATE };
+use crate::filter::Biquad;
+
+use std::f64::consts::TAU;
+use std::time::Duration;
+
+
+
+/// the frames generated between updates of the frequency, the duty and
+/// the filters
+const CONTROL_FRAMES: usize = 32;
+
+/// the random values of the noise in each period of its frequency
+const NOISE_STEPS: f64 = 32.0;
+
+
+
+/// the wave of a [`Sfx`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SfxWave {
+	Square,
+	Saw,
+	Sine,
+	Triangle,
+	/// noise that changes at a rate that follows the frequency, for
+	/// explosions and hits
+	Noise
+}
+
+
+
+/// the parameters of a [`Sfx`], in the style of sfxr
+///
+/// these are plain data, so sound effects can be designed and stored as
+/// values instead of recorded. the presets are starting points, and
+/// [`mutate`](SfxParams::mutate) makes variations of a sound so repeated
+/// effects don't sound the same
+///
+/// ```
+/// # use audio_engine::SfxParams;
+/// let hit = SfxParams::hit();
+/// // a slightly different hit every time
+/// let buffers: Vec<_> = (0..4).map(|seed| hit.mutate(seed, 0.1).bake()).collect();
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct SfxParams {
+	pub wave: SfxWave,
+	/// the gain of the whole sound
+	pub volume: f32,
+	pub envelope: Adsr,
+	/// how long the note is held before the release of the envelope
+	pub hold: Duration,
+	/// the starting frequency, in Hz
+	pub frequency: f32,
+	/// the sound ends if the frequency slides below this, in Hz
+	pub min_frequency: f32,
+	/// the change of the frequency, in octaves per second
+	pub slide: f32,
+	/// the change of the slide, in octaves per second squared
+	pub delta_slide: f32,
+	/// the depth of the vibrato, in semitones
+	pub vibrato_depth: f32,
+	/// the frequency of the vibrato, in Hz
+	pub vibrato_speed: f32,
+	/// the frequency is multiplied by this once, after `arpeggio_delay`
+	pub arpeggio_ratio: f32,
+	pub arpeggio_delay: Duration,
+	/// the part of each period a square wave is high, from 0 to 1
+	pub duty: f32,
+	/// the change of the duty, per second
+	pub duty_sweep: f32,
+	/// the cutoff of a low pass filter, in Hz, or `None` for no filter
+	pub low_pass: Option<f32>,
+	/// the change of the low pass cutoff, in octaves per second
+	pub low_pass_sweep: f32,
+	/// the quality factor of the low pass filter
+	pub low_pass_resonance: f32,
+	/// the cutoff of a high pass filter, in Hz, or `None` for no filter
+	pub high_pass: Option<f32>,
+	/// the change of the high pass cutoff, in octaves per second
+	pub high_pass_sweep: f32
+}
+
+impl Default for SfxParams {
+	/// a square wave at 440Hz, held for 0.1 second
+	fn default () -> Self {
+		Self {
+			wave: SfxWave::Square,
+			volume: 0.5,
+			envelope: Adsr::default(),
+			hold: Duration::from_millis(100),
+			frequency: 440.0,
+			min_frequency: 0.0,
+			slide: 0.0,
+			delta_slide: 0.0,
+			vibrato_depth: 0.0,
+			vibrato_speed: 0.0,
+			arpeggio_ratio: 1.0,
+			arpeggio_delay: Duration::ZERO,
+			duty: 0.5,
+			duty_sweep: 0.0,
+			low_pass: None,
+			low_pass_sweep: 0.0,
+			low_pass_resonance: 0.7,
+			high_pass: None,
+			high_pass_sweep: 0.0
+		}
+	}
+}
+
+impl SfxParams {
+
+
+	/// a short punch of falling noise
+	pub fn hit () -> Self {
+		Self {
+			wave: SfxWave::Noise,
+			envelope: Adsr::new(Duration::ZERO, Duration::from_millis(40), 0.4, Duration::from_millis(120)),
+			hold: Duration::from_millis(40),
+			frequency: 1200.0,
+			slide: -4.0,
+			low_pass: Some(6000.0),
+			low_pass_sweep: -3.0,
+			..Self::default()
+		}
+	}
+
+
+	/// a soft sweep of filtered noise, as a blade cutting the air
+	pub fn swoosh () -> Self {
+		Self {
+			wave: SfxWave::Noise,
+			envelope: Adsr::new(Duration::from_millis(80), Duration::ZERO, 1.0, Duration::from_millis(150)),
+			hold: Duration::from_millis(80),
+			frequency: 4000.0,
+			low_pass: Some(600.0),
+			low_pass_sweep: 8.0,
+			low_pass_resonance: 2.0,
+			high_pass: Some(200.0),
+			..Self::default()
+		}
+	}
+
+
+	/// a quick falling square wave
+	pub fn laser () -> Self {
+		Self {
+			wave: SfxWave::Square,
+			envelope: Adsr::new(Duration::ZERO, Duration::ZERO, 1.0, Duration::from_millis(150)),
+			hold: Duration::from_millis(50),
+			frequency: 1500.0,
+			min_frequency: 100.0,
+			slide: -12.0,
+			duty: 0.2,
+			duty_sweep: 1.0,
+			..Self::default()
+		}
+	}
+
+
+	/// a rising square wave
+	pub fn jump () -> Self {
+		Self {
+			wave: SfxWave::Square,
+			envelope: Adsr::new(Duration::ZERO, Duration::ZERO, 1.0, Duration::from_millis(200)),
+			hold: Duration::from_millis(50),
+			frequency: 300.0,
+			slide: 4.0,
+			duty: 0.4,
+			..Self::default()
+		}
+	}
+
+
+	/// two notes a fifth apart, as a coin
+	pub fn pickup () -> Self {
+		Self {
+			wave: SfxWave::Square,
+			envelope: Adsr::new(Duration::ZERO, Duration::ZERO, 1.0, Duration::from_millis(250)),
+			hold: Duration::from_millis(80),
+			frequency: 1000.0,
+			arpeggio_ratio: 1.5,
+			arpeggio_delay: Duration::from_millis(60),
+			..Self::default()
+		}
+	}
+
+
+	/// a long rumble of falling noise
+	pub fn explosion () -> Self {
+		Self {
+			wave: SfxWave::Noise,
+			volume: 0.7,
+			envelope: Adsr::new(Duration::ZERO, Duration::from_millis(100), 0.6, Duration::from_millis(600)),
+			hold: Duration::from_millis(150),
+			frequency: 400.0,
+			slide: -1.5,
+			vibrato_depth: 2.0,
+			vibrato_speed: 12.0,
+			low_pass: Some(3000.0),
+			low_pass_sweep: -2.0,
+			..Self::default()
+		}
+	}
+
+
+	/// a random variation of the parameters, the same for the same
+	/// `seed`
+	///
+	/// `amount`, from 0 to 1, is how much they change. `0.1` gives
+	/// variations of the same sound, `1.0` very different sounds. the
+	/// wave doesn't change
+	pub fn mutate (&self, seed: u32, amount: f32) -> Self {
+		let mut random = Random::new(seed);
+		let mut scale = || (1.0 + amount * random.next()).max(0.0);
+		let envelope = Adsr::new(
+			self.envelope.attack.mul_f64(scale() as f64),
+			self.envelope.decay.mul_f64(scale() as f64),
+			(self.envelope.sustain * scale()).min(1.0),
+			self.envelope.release.mul_f64(scale() as f64)
+		);
+		let hold = self.hold.mul_f64(scale() as f64);
+		let frequency = self.frequency * 2f32.powf(amount * random.next());
+		Self {
+			wave: self.wave,
+			volume: self.volume,
+			envelope,
+			hold,
+			frequency,
+			min_frequency: self.min_frequency,
+			slide: self.slide + amount * 4.0 * random.next(),
+			delta_slide: self.delta_slide + amount * 4.0 * random.next(),
+			vibrato_depth: (self.vibrato_depth + amount * random.next()).max(0.0),
+			vibrato_speed: (self.vibrato_speed * (1.0 + amount * random.next())).max(0.0),
+			arpeggio_ratio: self.arpeggio_ratio,
+			arpeggio_delay: self.arpeggio_delay,
+			duty: (self.duty + amount * 0.5 * random.next()).clamp(0.0, 1.0),
+			duty_sweep: self.duty_sweep + amount * random.next(),
+			low_pass: self.low_pass.map(|x| x * 2f32.powf(amount * random.next())),
+			low_pass_sweep: self.low_pass_sweep + amount * 2.0 * random.next(),
+			low_pass_resonance: self.low_pass_resonance,
+			high_pass: self.high_pass.map(|x| x * 2f32.powf(amount * random.next())),
+			high_pass_sweep: self.high_pass_sweep + amount * 2.0 * random.next()
+		}
+	}
+
+
+	/// a new source that plays the sound effect
+	pub fn source (&self) -> Sfx {
+		Sfx::new(self.clone())
+	}
+
+
+	/// generate the whole sound effect in a buffer, to play it many
+	/// times without generating it again
+	pub fn bake (&self) -> SoundBuffer {
+		SoundBuffer::decode(self.source()).expect("generated sources don't fail")
+	}
+
+
+}
+
+
+
+/// a mono source that generates a sound effect from [`SfxParams`]
+///
+/// the frequency, the duty and the filters are updated every 32 frames.
+/// the sound ends after the release of its envelope, or when its
+/// frequency slides below the minimum. it is the same every time it is
+/// reset
+pub struct Sfx {
+	params: SfxParams,
+	oscillator: Oscillator,
+	/// the frequency and the pulse width of `oscillator`
+	oscillator_frequency: Param,
+	pulse_width: Param,
+	/// the noise of a `SfxWave::Noise`, its value, its phase in the
+	/// current step and the phase advanced every frame
+	random: Random,
+	noise: f32,
+	noise_phase: f64,
+	noise_step: f64,
+	low_pass: Option<Biquad>,
+	high_pass: Option<Biquad>,
+	/// the frequency with the slide and the arpeggio, in Hz
+	frequency: f64,
+	slide: f64,
+	duty: f32,
+	/// the frames of the envelope, shortened when the frequency goes
+	/// below the minimum
+	len: u64,
+	/// the next frame to be written
+	position: u64
+}
+
+impl Sfx {
+
+
+	pub fn new (params: SfxParams) -> Self {
+		let waveform = match params.wave {
+			SfxWave::Square => Waveform::Square,
+			SfxWave::Saw => Waveform::Saw,
+			SfxWave::Triangle => Waveform::Triangle,
+			SfxWave::Sine | SfxWave::Noise => Waveform::Sine
+		};
+		let oscillator = Oscillator::new(waveform, params.frequency);
+		let mut sfx = Self {
+			oscillator_frequency: oscillator.frequency(),
+			pulse_width: oscillator.pulse_width(),
+			oscillator,
+			random: Random::new(1),
+			noise: 0.0,
+			noise_phase: 0.0,
+			noise_step: 0.0,
+			low_pass: params.low_pass.map(|cutoff| Biquad::low_pass(cutoff, params.low_pass_resonance)),
+			high_pass: params.high_pass.map(|cutoff| Biquad::high_pass(cutoff, 0.7)),
+			frequency: 0.0,
+			slide: 0.0,
+			duty: 0.0,
+			len: 0,
+			position: 0,
+			params
+		};
+		sfx.reset();
+		sfx
+	}
+
+
+	pub fn params (&self) -> &SfxParams {
+		&self.params
+	}
+
+
+	/// update the frequency, the duty and the filters at the current
+	/// position, for the next `frames` frames
+	///
+	/// return false if the frequency went below the minimum
+	fn update (&mut self, frames: usize) -> bool {
+		let sample_rate = GENERATOR_SAMPLE_RATE as f64;
+		let time = self.position as f64 / sample_rate;
+		let dt = frames as f64 / sample_rate;
+		let params = &self.params;
+
+		let arpeggio = params.arpeggio_delay.as_secs_f64();
+		if params.arpeggio_ratio != 1.0 && time <= arpeggio && arpeggio < time + dt {
+			self.frequency *= params.arpeggio_ratio as f64;
+		}
+		if self.frequency < params.min_frequency as f64 {
+			return false;
+		}
+
+		let vibrato = params.vibrato_depth as f64 / 12.0 * (TAU * params.vibrato_speed as f64 * time).sin();
+		let frequency = self.frequency * 2f64.powf(vibrato);
+		self.oscillator_frequency.set(frequency as f32);
+		self.pulse_width.set(self.duty);
+		self.noise_step = frequency * NOISE_STEPS / sample_rate;
+
+		// the sweeps apply for the next block
+		self.slide += params.delta_slide as f64 * dt;
+		self.frequency *= 2f64.powf(self.slide * dt);
+		self.duty = (self.duty + params.duty_sweep * dt as f32).clamp(0.0, 1.0);
+		for (filter, sweep) in [(&self.low_pass, params.low_pass_sweep), (&self.high_pass, params.high_pass_sweep)] {
+			if let Some(filter) = filter {
+				let cutoff = filter.cutoff();
+				cutoff.set(cutoff.get() * 2f32.powf(sweep * dt as f32));
+			}
+		}
+		true
+	}
+
+
+}
+
+impl SoundSource for Sfx {
+
+
+	fn channels (&self) -> u16 {
+		1
+	}
+
+
+	fn sample_rate (&self) -> u32 {
+		GENERATOR_SAMPLE_RATE
+	}
+
+
+	fn reset (&mut self) {
+		let params = &self.params;
+		let sample_rate = GENERATOR_SAMPLE_RATE as f64;
+		self.oscillator.reset();
+		self.random = Random::new(1);
+		self.noise = 0.0;
+		self.noise_phase = 0.0;
+		// the filters are built once, the mixer resets sounds on the audio
+		// thread so this must not allocate
+		for (filter, cutoff) in [(&mut self.low_pass, params.low_pass), (&mut self.high_pass, params.high_pass)] {
+			if let (Some(filter), Some(cutoff)) = (filter, cutoff) {
+				filter.reset();
+				filter.cutoff().set(cutoff);
+			}
+		}
+		self.frequency = params.frequency as f64;
+		self.slide = params.slide as f64;
+		self.duty = params.duty.clamp(0.0, 1.0);
+		self.len = (params.envelope.duration(params.hold).as_secs_f64() * sample_rate).round() as u64;
+		self.position = 0;
+	}
+
+
+	fn write_samples (&mut self, buffer: &mut [f32]) -> usize {
+
+		let sample_rate = GENERATOR_SAMPLE_RATE as f64;
+		let hold = self.params.hold.as_secs_f64();
+		let mut written = 0;
+		while written < buffer.len() && self.position < self.len {
+			let frames = (buffer.len() - written).min(CONTROL_FRAMES).min((self.len - self.position) as usize);
+			if !self.update(frames) {
+				self.len = self.position;
+				break;
+			}
+
+			let chunk = &mut buffer[written..written + frames];
+			if self.params.wave == SfxWave::Noise {
+				for x in chunk.iter_mut() {
+					self.noise_phase += self.noise_step;
+					if self.noise_phase >= 1.0 {
+						self.noise_phase = self.noise_phase.fract();
+						self.noise = self.random.next();
+					}
+					*x = self.noise;
+				}
+			} else {
+				self.oscillator.write_samples(chunk);
+			}
+
+			for filter in [&mut self.low_pass, &mut self.high_pass].into_iter().flatten() {
+				filter.process(chunk, 1, GENERATOR_SAMPLE_RATE);
+			}
+			for (i, x) in chunk.iter_mut().enumerate() {
+				let time = (self.position + i as u64) as f64 / sample_rate;
+				*x = (*x * self.params.envelope.gain(time, hold) * self.params.volume).clamp(-1.0, 1.0);
+			}
+
+			self.position += frames as u64;
+			written += frames;
+		}
+		written
+
+	}
+
+
+	fn len_frames (&self) -> Option<u64> {
+		Some(self.len)
+	}
+
+
+	fn position (&self) -> Option<u64> {
+		Some(self.position)
+	}
+
+
+}
@@ -0,0 +1,144 @@
+
+
+
+/// how the volume of a positional sound decreases with its distance
+/// to the listener
+///
+/// the distance is clamped between the minimum and the maximum
+/// distance of the [`Spatial`] settings, so sounds closer than the
+/// minimum distance play at full volume, and sounds further than the
+/// maximum distance don't get any quieter
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Attenuation {
+	/// `1 - rolloff * (distance - min) / (max - min)`, reaching
+	/// silence at the maximum distance with a rolloff of 1
+	Linear,
+	/// `min / (min + rolloff * (distance - min))`, like a sound in
+	/// the open
+	#[default]
+	Inverse,
+	/// `(distance / min) ^ -rolloff`
+	Exponential
+}
+
+
+
+/// the settings of a positional sound, see [`Sound::set_spatial`](crate::Sound::set_spatial)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Spatial {
+	pub attenuation: Attenuation,
+	/// the distance under which the sound is not attenuated
+	pub min_distance: f32,
+	/// the distance over which the sound is not attenuated further
+	pub max_distance: f32,
+	/// how fast the volume decreases with the distance
+	pub rolloff: f32,
+	/// how much the velocities change the pitch, `0.0` disables the
+	/// doppler effect and `1.0` is physically accurate
+	pub doppler: f32
+}
+
+impl Default for Spatial {
+	/// an inverse attenuation from 1 to 1000 units, without doppler
+	fn default () -> Self {
+		Self {
+			attenuation: Attenuation::Inverse,
+			min_distance: 1.0,
+			max_distance: 1000.0,
+			rolloff: 1.0,
+			doppler: 0.0
+		}
+	}
+}
+
+
+
+/// the point the positional sounds are heard from, see
+/// [`AudioEngine::set_listener`](crate::AudioEngine::set_listener)
+///
+/// the listener faces the positive y axis, so sounds with a greater x
+/// are on the right. in a side view, put the listener at the same y
+/// as the sounds, so they are panned by their x alone
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Listener {
+	pub position: [f32; 2],
+	/// in units per second
+	pub velocity: [f32; 2],
+	/// in units per second, used by the doppler effect
+	pub speed_of_sound: f32
+}
+
+impl Default for Listener {
+	/// a listener at the origin, with a speed of sound of 343 units
+	/// per second, as in meters in the air
+	fn default () -> Self {
+		Self {
+			position: [0.0, 0.0],
+			velocity: [0.0, 0.0],
+			speed_of_sound: 343.0
+		}
+	}
+}
+
+
+
+/// the position and settings of a positional sound in the mixer
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Emitter {
+	pub spatial: Spatial,
+	pub position: [f32; 2],
+	pub velocity: [f32; 2]
+}
+
+impl Emitter {
+
+
+	pub fn new (spatial: Spatial) -> Self {
+		Self {
+			spatial,
+			position: [0.0, 0.0],
+			velocity: [0.0, 0.0]
+		}
+	}
+
+
+	/// the gain, the pan and the doppler speed of the emitter as heard
+	/// by `listener`
+	pub fn render (&self, listener: &Listener) -> (f32, f32, f32) {
+		let Spatial { attenuation, min_distance, max_distance, rolloff, doppler } = self.spatial;
+		let min_distance = min_distance.max(f32::EPSILON);
+		let max_distance = max_distance.max(min_distance);
+
+		let offset = [self.position[0] - listener.position[0], self.position[1] - listener.position[1]];
+		let distance = offset[0].hypot(offset[1]);
+
+		let d = distance.clamp(min_distance, max_distance);
+		let gain = match attenuation {
+			Attenuation::Linear if max_distance > min_distance => {
+				1.0 - rolloff * (d - min_distance) / (max_distance - min_distance)
+			},
+			Attenuation::Linear => 1.0,
+			Attenuation::Inverse => min_distance / (min_distance + rolloff * (d - min_distance)),
+			Attenuation::Exponential => (d / min_distance).powf(-rolloff)
+		};
+
+		// sounds closer than the minimum distance move toward the
+		// center, so passing through the listener doesn't jump sides
+		let pan = offset[0] / distance.max(min_distance);
+
+		let speed = if doppler > 0.0 && distance > 0.0 {
+			// the velocities along the direction from the sound to the
+			// listener, kept under the speed of sound
+			let c = listener.speed_of_sound;
+			let direction = [-offset[0] / distance, -offset[1] / distance];
+			let along = |v: [f32; 2]| (doppler * (v[0] * direction[0] + v[1] * direction[1])).clamp(-c * 0.5, c * 0.5);
+			(c - along(listener.velocity)) / (c - along(self.velocity))
+		} else {
+			1.0
+		};
+
+		(gain.clamp(0.0, 1.0), pan.clamp(-1.0, 1.0), speed)
+	}
+
+
+}
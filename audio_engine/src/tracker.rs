@@ -0,0 +1,1152 @@
+
+
+
+use crate::error::SourceError;
+use crate::mixer::SoundSource;
+use crate::oscillator::GENERATOR_SAMPLE_RATE;
+
+use std::sync::Arc;
+use std::sync::atomic::{ AtomicU64, Ordering };
+use std::sync::mpsc::{ sync_channel, Receiver, SyncSender };
+
+mod protracker;
+mod s3m;
+mod xm;
+
+
+
+/// the clock of the amiga, which periods divide to give a frequency
+const AMIGA_CLOCK: f64 = 3546894.6;
+
+/// the note that plays a sample at its `c4_rate`
+const MIDDLE_NOTE: f64 = 48.0;
+
+/// the vibrato and tremolo sine, for the first half of a period
+const SINE: [u8; 32] = [
+	0, 24, 49, 74, 97, 120, 141, 161, 180, 197, 212, 224, 235, 244, 250, 253,
+	255, 253, 250, 244, 235, 224, 212, 197, 180, 161, 141, 120, 97, 74, 49, 24
+];
+
+/// the row positions kept until they are taken
+const ROWS_CAPACITY: usize = 256;
+
+/// the most channels a module can have, so their mute state fits in a
+/// `u64`
+const MAX_CHANNELS: usize = 64;
+
+
+
+/// the format of a tracker module
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModuleFormat {
+	/// protracker and compatible, with 4 to 32 channels
+	Mod,
+	/// scream tracker 3
+	S3m,
+	/// fast tracker 2
+	Xm
+}
+
+
+
+/// a note of a cell
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) enum Note {
+	#[default]
+	None,
+	/// the number of semitones from C-0
+	On(u8),
+	/// release the key, letting the envelope and the fadeout end the note
+	Off,
+	/// stop the note at once
+	Cut
+}
+
+
+/// the volume column of a cell
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) enum VolumeCommand {
+	#[default]
+	None,
+	Set(u8),
+	SlideDown(u8),
+	SlideUp(u8),
+	FineDown(u8),
+	FineUp(u8),
+	/// from 0 to 15
+	Panning(u8),
+	TonePorta(u8)
+}
+
+
+/// the effect of a cell, the same for all formats
+///
+/// a parameter of 0 repeats the last one of the effect, where the
+/// formats do
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) enum Effect {
+	#[default]
+	None,
+	Arpeggio(u8),
+	PortaUp(u8),
+	PortaDown(u8),
+	FinePortaUp(u8),
+	FinePortaDown(u8),
+	ExtraFinePortaUp(u8),
+	ExtraFinePortaDown(u8),
+	TonePorta(u8),
+	/// speed and depth
+	Vibrato(u8, u8),
+	FineVibrato(u8, u8),
+	TonePortaVolumeSlide(u8),
+	VibratoVolumeSlide(u8),
+	Tremolo(u8, u8),
+	/// from 0, left, to 255, right
+	SetPanning(u8),
+	SampleOffset(u8),
+	/// up by the high nibble, or down by the low one
+	VolumeSlide(u8),
+	FineVolumeUp(u8),
+	FineVolumeDown(u8),
+	PositionJump(u8),
+	SetVolume(u8),
+	PatternBreak(u8),
+	SetSpeed(u8),
+	SetTempo(u8),
+	PatternLoop(u8),
+	NoteCut(u8),
+	NoteDelay(u8),
+	PatternDelay(u8),
+	Retrigger(u8),
+	SetGlobalVolume(u8),
+	GlobalVolumeSlide(u8),
+	KeyOff(u8)
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) struct Cell {
+	pub note: Note,
+	/// starts at 1, 0 for none
+	pub instrument: u8,
+	pub volume: VolumeCommand,
+	pub effect: Effect
+}
+
+
+pub(crate) struct Pattern {
+	pub rows: usize,
+	/// `rows` times the channels of the module
+	pub cells: Vec<Cell>
+}
+
+impl Pattern {
+
+	pub fn empty (rows: usize, channels: usize) -> Self {
+		Self {
+			rows,
+			cells: vec![Cell::default(); rows * channels]
+		}
+	}
+
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum LoopKind {
+	None,
+	Forward,
+	PingPong
+}
+
+
+pub(crate) struct Sample {
+	/// mono samples from -1 to 1
+	pub data: Vec<f32>,
+	pub loop_kind: LoopKind,
+	pub loop_start: usize,
+	pub loop_end: usize,
+	/// from 0 to 64
+	pub volume: u8,
+	/// from 0 to 255, or `None` to keep the panning of the channel
+	pub panning: Option<u8>,
+	/// the rate the sample plays at on the middle note, with its
+	/// finetune
+	pub c4_rate: f64
+}
+
+
+/// the points of an envelope, with the ticks and the values from 0 to 64
+pub(crate) struct InstrumentEnvelope {
+	pub points: Vec<(u16, u8)>,
+	pub sustain: Option<usize>,
+	pub loop_points: Option<(usize, usize)>
+}
+
+impl InstrumentEnvelope {
+
+
+	/// the value at `tick`, from 0 to 1
+	fn value (&self, tick: u16) -> f32 {
+		let points = &self.points;
+		let i = points.partition_point(|&(x, _)| x <= tick);
+		if i == 0 {
+			return points.first().map_or(1.0, |&(_, y)| y as f32 / 64.0);
+		}
+		if i == points.len() {
+			return points[i - 1].1 as f32 / 64.0;
+		}
+		let ((x0, y0), (x1, y1)) = (points[i - 1], points[i]);
+		let t = (tick - x0) as f32 / (x1 - x0).max(1) as f32;
+		(y0 as f32 + (y1 as f32 - y0 as f32) * t) / 64.0
+	}
+
+
+	/// the tick after `tick`, holding at the sustain point while the key
+	/// is on and going back at the end of the loop
+	fn next (&self, tick: u16, key_on: bool) -> u16 {
+		if let Some(sustain) = self.sustain.and_then(|i| self.points.get(i)) {
+			if key_on && tick == sustain.0 {
+				return tick;
+			}
+		}
+		let tick = tick.saturating_add(1);
+		match self.loop_points.and_then(|(start, end)| Some((self.points.get(start)?, self.points.get(end)?))) {
+			Some((start, end)) if tick > end.0 => start.0,
+			_ => tick
+		}
+	}
+
+
+}
+
+
+pub(crate) struct Instrument {
+	pub samples: Vec<Sample>,
+	/// the sample played by each note
+	pub keymap: [u8; 96],
+	pub volume_envelope: Option<InstrumentEnvelope>,
+	pub panning_envelope: Option<InstrumentEnvelope>,
+	/// subtracted from 65536 every tick after the key is released
+	pub fadeout: u16
+}
+
+impl Instrument {
+
+	/// an instrument of a single sample
+	pub fn single (sample: Sample) -> Self {
+		Self {
+			samples: vec![sample],
+			keymap: [0; 96],
+			volume_envelope: None,
+			panning_envelope: None,
+			fadeout: 0
+		}
+	}
+
+}
+
+
+/// a tracker module, with its format differences resolved by the loader
+pub(crate) struct Module {
+	pub format: ModuleFormat,
+	pub title: String,
+	pub channels: usize,
+	/// the patterns to play, in order
+	pub orders: Vec<u8>,
+	pub patterns: Vec<Pattern>,
+	pub instruments: Vec<Instrument>,
+	pub speed: u8,
+	pub tempo: u8,
+	/// from 0 to 64
+	pub global_volume: u8,
+	/// the initial panning of each channel, from 0 to 255
+	pub panning: Vec<u8>,
+	/// if the periods are in 1/64 of a semitone instead of amiga periods
+	pub linear: bool
+}
+
+impl Module {
+
+
+	fn load (data: &[u8]) -> Result<Self, SourceError> {
+		let module = if xm::detect(data) {
+			xm::load(data)?
+		} else if s3m::detect(data) {
+			s3m::load(data)?
+		} else if protracker::detect(data) {
+			protracker::load(data)?
+		} else {
+			return Err(SourceError::Unsupported);
+		};
+		if module.channels == 0 || module.channels > MAX_CHANNELS {
+			return Err(SourceError::Unsupported);
+		}
+		if (0..module.orders.len()).any(|i| !module.is_marker(i) && module.orders[i] as usize >= module.patterns.len()) {
+			return Err(SourceError::Malformed);
+		}
+		Ok(module)
+	}
+
+
+	/// whether the order `order` is a marker of an s3m order list, which
+	/// is skipped
+	fn is_marker (&self, order: usize) -> bool {
+		self.format == ModuleFormat::S3m && self.orders.get(order) == Some(&s3m::MARKER)
+	}
+
+
+	/// the period of `note`, for a sample playing at `c4_rate`
+	fn period (&self, note: u8, c4_rate: f64) -> f64 {
+		let semitones = note as f64 - MIDDLE_NOTE;
+		if self.linear {
+			-semitones * 64.0
+		} else {
+			AMIGA_CLOCK / (c4_rate * 2f64.powf(semitones / 12.0))
+		}
+	}
+
+
+	/// the frequency of `period` moved by `semitones`
+	fn frequency (&self, period: f64, c4_rate: f64, semitones: f64) -> f64 {
+		let frequency = if self.linear {
+			c4_rate * 2f64.powf(-period / 768.0)
+		} else {
+			AMIGA_CLOCK / period.max(1.0)
+		};
+		frequency * 2f64.powf(semitones / 12.0)
+	}
+
+
+	/// the change of period of `units` of a portamento
+	fn porta (&self, units: f64) -> f64 {
+		if self.linear { units * 4.0 } else { units }
+	}
+
+
+}
+
+
+
+/// reads the little endian values of a module, failing past its end
+pub(crate) struct Reader <'a> {
+	data: &'a [u8],
+	pub pos: usize
+}
+
+impl <'a> Reader<'a> {
+
+
+	pub fn new (data: &'a [u8], pos: usize) -> Self {
+		Self { data, pos }
+	}
+
+
+	pub fn bytes (&mut self, len: usize) -> Result<&'a [u8], SourceError> {
+		let end = self.pos.checked_add(len).ok_or(SourceError::Malformed)?;
+		let bytes = self.data.get(self.pos..end).ok_or(SourceError::Malformed)?;
+		self.pos = end;
+		Ok(bytes)
+	}
+
+
+	/// go to the end of a header of `len` bytes starting at `start`,
+	/// which must hold what was read of it
+	pub fn skip_header (&mut self, start: usize, len: usize) -> Result<(), SourceError> {
+		match start.checked_add(len) {
+			Some(end) if end >= self.pos => {
+				self.pos = end;
+				Ok(())
+			},
+			_ => Err(SourceError::Malformed)
+		}
+	}
+
+
+	/// up to `len` bytes, as many as there are before the end
+	pub fn bytes_lossy (&mut self, len: usize) -> &'a [u8] {
+		let start = self.pos.min(self.data.len());
+		let end = start.saturating_add(len).min(self.data.len());
+		self.pos = end;
+		&self.data[start..end]
+	}
+
+
+	pub fn u8 (&mut self) -> Result<u8, SourceError> {
+		Ok(self.bytes(1)?[0])
+	}
+
+
+	pub fn u16 (&mut self) -> Result<u16, SourceError> {
+		let x = self.bytes(2)?;
+		Ok(u16::from_le_bytes([x[0], x[1]]))
+	}
+
+
+	pub fn u32 (&mut self) -> Result<u32, SourceError> {
+		let x = self.bytes(4)?;
+		Ok(u32::from_le_bytes([x[0], x[1], x[2], x[3]]))
+	}
+
+
+	/// a text padded with zeros or spaces
+	pub fn text (&mut self, len: usize) -> Result<String, SourceError> {
+		let bytes = self.bytes(len)?;
+		let end = bytes.iter().position(|&x| x == 0).unwrap_or(len);
+		Ok(String::from_utf8_lossy(&bytes[..end]).trim_end().to_string())
+	}
+
+
+}
+
+
+
+/// a position in the song of a module
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ModulePosition {
+	/// the index in the order list
+	pub order: u16,
+	/// the pattern played at `order`
+	pub pattern: u16,
+	pub row: u16
+}
+
+impl ModulePosition {
+
+	fn pack (self) -> u64 {
+		(self.order as u64) << 32 | (self.pattern as u64) << 16 | self.row as u64
+	}
+
+	fn unpack (x: u64) -> Self {
+		Self {
+			order: (x >> 32) as u16,
+			pattern: (x >> 16) as u16,
+			row: x as u16
+		}
+	}
+
+}
+
+
+
+/// the state of a [`ModuleDecoder`] shared with its handles
+struct Shared {
+	position: AtomicU64,
+	/// a bit for each muted channel
+	muted: AtomicU64
+}
+
+
+
+/// controls a [`ModuleDecoder`] after it is given to the engine
+#[derive(Clone)]
+pub struct ModuleHandle {
+	shared: Arc<Shared>,
+	channels: usize
+}
+
+impl ModuleHandle {
+
+
+	/// the position of the last row that started, as of the last mixed
+	/// buffer
+	pub fn position (&self) -> ModulePosition {
+		ModulePosition::unpack(self.shared.position.load(Ordering::Relaxed))
+	}
+
+
+	/// the number of channels of the module
+	pub fn channels (&self) -> usize {
+		self.channels
+	}
+
+
+	/// silence a channel of the module, or play it again
+	///
+	/// a muted channel keeps playing in silence, so it comes back in
+	/// time when it is unmuted
+	pub fn set_muted (&self, channel: usize, muted: bool) {
+		if channel < self.channels {
+			if muted {
+				self.shared.muted.fetch_or(1 << channel, Ordering::Relaxed);
+			} else {
+				self.shared.muted.fetch_and(!(1 << channel), Ordering::Relaxed);
+			}
+		}
+	}
+
+
+	pub fn is_muted (&self, channel: usize) -> bool {
+		channel < self.channels && self.shared.muted.load(Ordering::Relaxed) & (1 << channel) != 0
+	}
+
+
+}
+
+
+
+/// a channel of the module while it plays
+#[derive(Default)]
+struct Channel {
+	instrument: Option<usize>,
+	sample: Option<usize>,
+	active: bool,
+	/// the position in the sample, and if it goes backward in a
+	/// ping-pong loop
+	pos: f64,
+	backward: bool,
+	/// the period of the note, and the one a tone portamento moves to
+	period: f64,
+	target: f64,
+	/// from 0 to 64
+	volume: i32,
+	/// from 0 to 255
+	panning: u8,
+	key_on: bool,
+	/// from 65536 to 0 after the key is released
+	fade: i32,
+	volume_tick: u16,
+	panning_tick: u16,
+
+	cell: Cell,
+	/// a cell waiting for its note delay
+	delayed: Option<Cell>,
+
+	porta_up: u8,
+	porta_down: u8,
+	fine_porta: u8,
+	tone_porta: u8,
+	vibrato: (u8, u8),
+	vibrato_pos: u8,
+	tremolo: (u8, u8),
+	tremolo_pos: u8,
+	volume_slide: u8,
+	offset: u8,
+	retrigger: u8,
+
+	loop_row: usize,
+	loop_count: u8,
+
+	/// the changes of this tick to the period, in period units, to the
+	/// pitch, in semitones, and to the volume
+	period_offset: f64,
+	semitones: f64,
+	volume_offset: i32,
+	/// the frequency in Hz and the gains of this tick
+	frequency: f64,
+	gains: [f32; 2],
+	/// the gains at the end of the last tick, ramped to `gains`
+	last_gains: [f32; 2]
+}
+
+impl Channel {
+
+	fn slide_volume (&mut self, param: u8) {
+		let (up, down) = (param >> 4, param & 0x0F);
+		self.volume = if up > 0 { self.volume + up as i32 } else { self.volume - down as i32 }.clamp(0, 64);
+	}
+
+	fn sine (pos: u8, depth: u8) -> i32 {
+		let x = SINE[(pos & 31) as usize] as i32 * depth as i32;
+		if pos & 32 == 0 { x } else { -x }
+	}
+
+}
+
+
+
+/// Tracker Module Decoder
+///
+/// plays mod, s3m and xm modules in stereo, at
+/// [`GENERATOR_SAMPLE_RATE`]. the song ends after the last pattern of
+/// its order list, unless a position jump goes back. modules are much
+/// smaller than the same music in wav or ogg files
+///
+/// the [handle](ModuleDecoder::handle) mutes channels and tells the
+/// current position, and [`rows`](ModuleDecoder::rows) reports each row
+/// as it is played, for music synced gameplay
+///
+/// ```no_run
+/// # use audio_engine::{ AudioEngine, ModuleDecoder };
+/// # let engine = AudioEngine::new().unwrap();
+/// let mut music = ModuleDecoder::new(&std::fs::read("song.xm").unwrap()).unwrap();
+/// let rows = music.rows();
+/// let handle = music.handle();
+/// let mut sound = engine.new_sound(music, None).unwrap();
+/// sound.play();
+///
+/// // once per frame of the game
+/// while let Ok(position) = rows.try_recv() {
+///     if position.row % 16 == 0 {
+///         // on the beat
+///     }
+/// }
+/// handle.set_muted(3, true);
+/// ```
+pub struct ModuleDecoder {
+	module: Arc<Module>,
+	channels: Vec<Channel>,
+	shared: Arc<Shared>,
+	rows: Option<SyncSender<ModulePosition>>,
+
+	order: usize,
+	row: usize,
+	tick: u32,
+	speed: u32,
+	tempo: u32,
+	global_volume: i32,
+	global_slide: u8,
+	pattern_delay: u32,
+	/// the order and the row to go to after this row
+	jump_order: Option<usize>,
+	break_row: Option<usize>,
+	loop_row: Option<usize>,
+	ended: bool,
+
+	/// the frames of the current tick, the ones left, and the fraction
+	/// of a frame carried to the next tick
+	tick_length: usize,
+	tick_frames: usize,
+	tick_fraction: f64,
+	/// the frames written since the start
+	position: u64
+}
+
+impl ModuleDecoder {
+
+
+	/// load a mod, s3m or xm module
+	pub fn new (data: &[u8]) -> Result<Self, SourceError> {
+		let module = Module::load(data)?;
+		let mut decoder = Self {
+			channels: (0..module.channels).map(|_| Channel::default()).collect(),
+			module: Arc::new(module),
+			shared: Arc::new(Shared {
+				position: AtomicU64::new(0),
+				muted: AtomicU64::new(0)
+			}),
+			rows: None,
+			order: 0,
+			row: 0,
+			tick: 0,
+			speed: 6,
+			tempo: 125,
+			global_volume: 64,
+			global_slide: 0,
+			pattern_delay: 0,
+			jump_order: None,
+			break_row: None,
+			loop_row: None,
+			ended: false,
+			tick_length: 0,
+			tick_frames: 0,
+			tick_fraction: 0.0,
+			position: 0
+		};
+		decoder.reset();
+		Ok(decoder)
+	}
+
+
+	pub fn format (&self) -> ModuleFormat {
+		self.module.format
+	}
+
+
+	pub fn title (&self) -> &str {
+		&self.module.title
+	}
+
+
+	/// a handle to mute the channels and to read the position, that can
+	/// be kept after the decoder is given to the engine
+	pub fn handle (&self) -> ModuleHandle {
+		ModuleHandle {
+			shared: self.shared.clone(),
+			channels: self.module.channels
+		}
+	}
+
+
+	/// a receiver of the position of each row, sent when the row starts
+	/// to be mixed
+	///
+	/// up to 256 rows are kept until they are received, later ones are
+	/// dropped. calling this again replaces the previous receiver
+	pub fn rows (&mut self) -> Receiver<ModulePosition> {
+		let (sender, receiver) = sync_channel(ROWS_CAPACITY);
+		self.rows = Some(sender);
+		receiver
+	}
+
+
+	/// play the row at `self.order` and `self.row`
+	fn start_row (&mut self) {
+		let module = self.module.clone();
+		let pattern_index = module.orders[self.order] as usize;
+		let pattern = &module.patterns[pattern_index];
+		let position = ModulePosition {
+			order: self.order as u16,
+			pattern: pattern_index as u16,
+			row: self.row as u16
+		};
+		self.shared.position.store(position.pack(), Ordering::Relaxed);
+		if let Some(rows) = &self.rows {
+			let _ = rows.try_send(position);
+		}
+
+		for c in 0..self.channels.len() {
+			let cell = pattern.cells[self.row * module.channels + c];
+			let channel = &mut self.channels[c];
+			channel.cell = cell;
+			channel.delayed = None;
+			match cell.effect {
+				Effect::NoteDelay(ticks) if ticks > 0 => channel.delayed = Some(cell),
+				_ => self.trigger(c, cell)
+			}
+			self.row_effect(c, cell.effect);
+		}
+	}
+
+
+	/// apply the note, the instrument and the volume column of `cell`
+	fn trigger (&mut self, c: usize, cell: Cell) {
+		let module = self.module.clone();
+		let channel = &mut self.channels[c];
+		let tone_porta = matches!(cell.effect, Effect::TonePorta(_) | Effect::TonePortaVolumeSlide(_))
+			|| matches!(cell.volume, VolumeCommand::TonePorta(_));
+
+		if cell.instrument > 0 && (cell.instrument as usize) <= module.instruments.len() {
+			channel.instrument = Some(cell.instrument as usize - 1);
+		}
+		let instrument = channel.instrument.map(|i| &module.instruments[i]);
+
+		match cell.note {
+			Note::On(note) => if let Some(instrument) = instrument {
+				let index = instrument.keymap[(note as usize).min(95)] as usize;
+				if let Some(sample) = instrument.samples.get(index) {
+					let period = module.period(note, sample.c4_rate);
+					if tone_porta && channel.active {
+						channel.target = period;
+					} else {
+						channel.sample = Some(index);
+						channel.active = !sample.data.is_empty();
+						channel.period = period;
+						channel.target = period;
+						channel.pos = 0.0;
+						channel.backward = false;
+						channel.vibrato_pos = 0;
+						channel.tremolo_pos = 0;
+						if let Effect::SampleOffset(offset) = cell.effect {
+							if offset > 0 {
+								channel.offset = offset;
+							}
+							channel.pos = channel.offset as f64 * 256.0;
+							channel.active &= channel.pos < sample.data.len() as f64;
+						}
+					}
+				}
+			},
+			Note::Off => {
+				channel.key_on = false;
+				if instrument.is_none_or(|x| x.volume_envelope.is_none()) {
+					channel.volume = 0;
+				}
+			},
+			Note::Cut => channel.active = false,
+			Note::None => {}
+		}
+
+		if cell.instrument > 0 {
+			let sample = instrument.zip(channel.sample).and_then(|(x, i)| x.samples.get(i));
+			if let Some(sample) = sample {
+				channel.volume = sample.volume as i32;
+				if let Some(panning) = sample.panning {
+					channel.panning = panning;
+				}
+			}
+			channel.key_on = true;
+			channel.fade = 65536;
+			channel.volume_tick = 0;
+			channel.panning_tick = 0;
+		}
+
+		match cell.volume {
+			VolumeCommand::Set(volume) => channel.volume = (volume as i32).min(64),
+			VolumeCommand::FineDown(x) => channel.volume = (channel.volume - x as i32).max(0),
+			VolumeCommand::FineUp(x) => channel.volume = (channel.volume + x as i32).min(64),
+			VolumeCommand::Panning(x) => channel.panning = x * 17,
+			VolumeCommand::TonePorta(x) if x > 0 => channel.tone_porta = x * 16,
+			_ => {}
+		}
+	}
+
+
+	/// apply the part of an effect done once at the start of a row
+	fn row_effect (&mut self, c: usize, effect: Effect) {
+		let module = self.module.clone();
+		let channel = &mut self.channels[c];
+		let memory = |memory: &mut u8, x: u8| {
+			if x > 0 {
+				*memory = x;
+			}
+			*memory
+		};
+		match effect {
+			Effect::PortaUp(x) => { memory(&mut channel.porta_up, x); },
+			Effect::PortaDown(x) => { memory(&mut channel.porta_down, x); },
+			Effect::FinePortaUp(x) => channel.period -= module.porta(memory(&mut channel.fine_porta, x) as f64),
+			Effect::FinePortaDown(x) => channel.period += module.porta(memory(&mut channel.fine_porta, x) as f64),
+			Effect::ExtraFinePortaUp(x) => channel.period -= module.porta(memory(&mut channel.fine_porta, x) as f64 / 4.0),
+			Effect::ExtraFinePortaDown(x) => channel.period += module.porta(memory(&mut channel.fine_porta, x) as f64 / 4.0),
+			Effect::TonePorta(x) => { memory(&mut channel.tone_porta, x); },
+			Effect::Vibrato(speed, depth) | Effect::FineVibrato(speed, depth) => {
+				memory(&mut channel.vibrato.0, speed);
+				memory(&mut channel.vibrato.1, depth);
+			},
+			Effect::Tremolo(speed, depth) => {
+				memory(&mut channel.tremolo.0, speed);
+				memory(&mut channel.tremolo.1, depth);
+			},
+			Effect::VolumeSlide(x) | Effect::TonePortaVolumeSlide(x) | Effect::VibratoVolumeSlide(x) => {
+				memory(&mut channel.volume_slide, x);
+			},
+			Effect::FineVolumeUp(x) => channel.volume = (channel.volume + x as i32).min(64),
+			Effect::FineVolumeDown(x) => channel.volume = (channel.volume - x as i32).max(0),
+			Effect::SetVolume(x) => channel.volume = (x as i32).min(64),
+			Effect::SetPanning(x) => channel.panning = x,
+			Effect::Retrigger(x) => { memory(&mut channel.retrigger, x); },
+			Effect::NoteCut(0) => channel.volume = 0,
+			Effect::KeyOff(0) => channel.key_on = false,
+			Effect::PatternLoop(0) => channel.loop_row = self.row,
+			Effect::PatternLoop(count) => {
+				if channel.loop_count == 0 {
+					channel.loop_count = count;
+					self.loop_row = Some(channel.loop_row);
+				} else {
+					channel.loop_count -= 1;
+					if channel.loop_count > 0 {
+						self.loop_row = Some(channel.loop_row);
+					}
+				}
+			},
+			Effect::SetSpeed(x) if x > 0 => self.speed = x as u32,
+			Effect::SetTempo(x) if x > 0 => self.tempo = x as u32,
+			Effect::PositionJump(x) => {
+				self.jump_order = Some(x as usize);
+				self.break_row.get_or_insert(0);
+			},
+			Effect::PatternBreak(x) => self.break_row = Some(x as usize),
+			Effect::PatternDelay(x) if self.pattern_delay == 0 => self.pattern_delay = x as u32,
+			Effect::SetGlobalVolume(x) => self.global_volume = (x as i32).min(64),
+			Effect::GlobalVolumeSlide(x) => { memory(&mut self.global_slide, x); },
+			_ => {}
+		}
+	}
+
+
+	/// apply the effects of every tick and update the frequency and the
+	/// gains of a channel
+	fn update_channel (&mut self, c: usize) {
+		let module = self.module.clone();
+		let first = self.tick == 0;
+		let tick = self.tick;
+
+		if let Some(cell) = self.channels[c].delayed {
+			if let Effect::NoteDelay(delay) = cell.effect {
+				if tick == delay as u32 {
+					self.channels[c].delayed = None;
+					self.trigger(c, cell);
+				}
+			}
+		}
+
+		let channel = &mut self.channels[c];
+		channel.period_offset = 0.0;
+		channel.semitones = 0.0;
+		channel.volume_offset = 0;
+
+		match channel.cell.effect {
+			Effect::Arpeggio(x) => {
+				channel.semitones = [0, x >> 4, x & 0x0F][(tick % 3) as usize] as f64;
+			},
+			Effect::PortaUp(_) if !first => channel.period -= module.porta(channel.porta_up as f64),
+			Effect::PortaDown(_) if !first => channel.period += module.porta(channel.porta_down as f64),
+			Effect::TonePorta(_) | Effect::TonePortaVolumeSlide(_) if !first => {
+				let step = module.porta(channel.tone_porta as f64);
+				channel.period = if channel.period < channel.target {
+					(channel.period + step).min(channel.target)
+				} else {
+					(channel.period - step).max(channel.target)
+				};
+			},
+			Effect::Vibrato(..) | Effect::FineVibrato(..) | Effect::VibratoVolumeSlide(_) => {
+				let fine = matches!(channel.cell.effect, Effect::FineVibrato(..));
+				let delta = Channel::sine(channel.vibrato_pos, channel.vibrato.1) as f64 / 128.0;
+				channel.period_offset = module.porta(if fine { delta / 4.0 } else { delta });
+				if !first {
+					channel.vibrato_pos = channel.vibrato_pos.wrapping_add(channel.vibrato.0) & 63;
+				}
+			},
+			Effect::Tremolo(..) => {
+				channel.volume_offset = Channel::sine(channel.tremolo_pos, channel.tremolo.1) / 64;
+				if !first {
+					channel.tremolo_pos = channel.tremolo_pos.wrapping_add(channel.tremolo.0) & 63;
+				}
+			},
+			Effect::NoteCut(t) if tick == t as u32 => channel.volume = 0,
+			Effect::KeyOff(t) if tick == t as u32 && !first => channel.key_on = false,
+			Effect::Retrigger(_) if !first && channel.retrigger > 0 && tick.is_multiple_of(channel.retrigger as u32) => {
+				channel.pos = 0.0;
+				channel.backward = false;
+			},
+			Effect::GlobalVolumeSlide(_) if !first => {
+				let (up, down) = (self.global_slide >> 4, self.global_slide & 0x0F);
+				self.global_volume = if up > 0 { self.global_volume + up as i32 } else { self.global_volume - down as i32 }.clamp(0, 64);
+			},
+			_ => {}
+		}
+		if !first && matches!(channel.cell.effect, Effect::VolumeSlide(_) | Effect::TonePortaVolumeSlide(_) | Effect::VibratoVolumeSlide(_)) {
+			channel.slide_volume(channel.volume_slide);
+		}
+		match channel.cell.volume {
+			VolumeCommand::SlideDown(x) if !first => channel.volume = (channel.volume - x as i32).max(0),
+			VolumeCommand::SlideUp(x) if !first => channel.volume = (channel.volume + x as i32).min(64),
+			VolumeCommand::TonePorta(_) if !first => {
+				let step = module.porta(channel.tone_porta as f64);
+				channel.period = if channel.period < channel.target {
+					(channel.period + step).min(channel.target)
+				} else {
+					(channel.period - step).max(channel.target)
+				};
+			},
+			_ => {}
+		}
+
+		// the envelopes and the fadeout of the instrument
+		let instrument = channel.instrument.map(|i| &module.instruments[i]);
+		let mut envelope_volume = 1.0;
+		let mut panning = channel.panning as f32 / 127.5 - 1.0;
+		if let Some(instrument) = instrument {
+			if let Some(envelope) = &instrument.volume_envelope {
+				envelope_volume = envelope.value(channel.volume_tick);
+				channel.volume_tick = envelope.next(channel.volume_tick, channel.key_on);
+			}
+			if let Some(envelope) = &instrument.panning_envelope {
+				let offset = envelope.value(channel.panning_tick) * 2.0 - 1.0;
+				panning += offset * (1.0 - panning.abs());
+				channel.panning_tick = envelope.next(channel.panning_tick, channel.key_on);
+			}
+			if !channel.key_on {
+				channel.fade = (channel.fade - instrument.fadeout as i32).max(0);
+			}
+		}
+
+		let sample = instrument.zip(channel.sample).and_then(|(x, i)| x.samples.get(i));
+		if let Some(sample) = sample {
+			let period = channel.period + channel.period_offset;
+			channel.frequency = module.frequency(period, sample.c4_rate, channel.semitones);
+		}
+
+		let volume = (channel.volume + channel.volume_offset).clamp(0, 64) as f32 / 64.0;
+		let fade = if instrument.is_some_and(|x| x.fadeout > 0) { channel.fade as f32 / 65536.0 } else { 1.0 };
+		let gain = volume * envelope_volume * fade * self.global_volume as f32 / 64.0;
+		let panning = panning.clamp(-1.0, 1.0);
+		channel.gains = [gain * (1.0 - panning).min(1.0), gain * (1.0 + panning).min(1.0)];
+	}
+
+
+	/// go to the next row, following the jumps of the last one
+	fn next_row (&mut self) {
+		let rows = |order: usize| self.module.patterns[self.module.orders[order] as usize].rows;
+		if let Some(row) = self.loop_row.take() {
+			self.row = row;
+			self.jump_order = None;
+			self.break_row = None;
+			return;
+		}
+		if let Some(row) = self.break_row.take() {
+			self.order = self.jump_order.take().unwrap_or(self.order + 1);
+			for channel in self.channels.iter_mut() {
+				channel.loop_row = 0;
+			}
+			self.row = row;
+		} else {
+			self.row += 1;
+			if self.row >= rows(self.order) {
+				self.row = 0;
+				self.order += 1;
+			}
+		}
+		while self.module.is_marker(self.order) {
+			self.order += 1;
+		}
+		if self.order >= self.module.orders.len() {
+			self.ended = true;
+		} else if self.row >= rows(self.order) {
+			self.row = 0;
+		}
+	}
+
+
+	/// play the next tick, and return false if the song ended
+	fn next_tick (&mut self) -> bool {
+		if self.ended {
+			return false;
+		}
+		if self.tick == 0 {
+			self.start_row();
+		}
+		for c in 0..self.channels.len() {
+			self.update_channel(c);
+		}
+		self.tick += 1;
+		if self.tick >= self.speed * (1 + self.pattern_delay) {
+			self.tick = 0;
+			self.pattern_delay = 0;
+			self.next_row();
+		}
+
+		// a tick lasts 2.5 seconds divided by the tempo
+		let frames = GENERATOR_SAMPLE_RATE as f64 * 2.5 / self.tempo.max(1) as f64 + self.tick_fraction;
+		self.tick_length = frames as usize;
+		self.tick_frames = self.tick_length;
+		self.tick_fraction = frames.fract();
+		true
+	}
+
+
+	/// mix the channels into the stereo `buffer`, for the current tick
+	fn mix (&mut self, buffer: &mut [f32]) {
+		buffer.fill(0.0);
+		let frames = buffer.len() / 2;
+		let muted = self.shared.muted.load(Ordering::Relaxed);
+		let scale = 1.0 / (self.channels.len() as f32).sqrt();
+		let module = &self.module;
+		for (c, channel) in self.channels.iter_mut().enumerate() {
+			let sample = channel.instrument.zip(channel.sample)
+				.and_then(|(i, s)| module.instruments[i].samples.get(s));
+			let sample = match sample {
+				Some(sample) if channel.active => sample,
+				_ => continue
+			};
+
+			let step = channel.frequency / GENERATOR_SAMPLE_RATE as f64;
+			let start = self.tick_length - self.tick_frames - frames;
+			let length = self.tick_length as f32;
+			let data = &sample.data;
+			for (i, frame) in buffer.chunks_exact_mut(2).enumerate() {
+				let index = channel.pos as usize;
+				if index >= data.len() {
+					channel.active = false;
+					break;
+				}
+				let next = data.get(index + 1).copied().unwrap_or(0.0);
+				let t = channel.pos.fract() as f32;
+				let x = (data[index] + (next - data[index]) * t) * scale;
+				if muted & (1 << c) == 0 {
+					// ramp from the gains of the last tick over the whole tick
+					let t = (start + i + 1) as f32 / length;
+					for (out, (from, to)) in frame.iter_mut().zip(channel.last_gains.iter().zip(channel.gains.iter())) {
+						*out += x * (from + (to - from) * t);
+					}
+				}
+
+				channel.pos += if channel.backward { -step } else { step };
+				match sample.loop_kind {
+					LoopKind::Forward if channel.pos >= sample.loop_end as f64 => {
+						let len = (sample.loop_end - sample.loop_start) as f64;
+						channel.pos = sample.loop_start as f64 + (channel.pos - sample.loop_start as f64) % len;
+					},
+					LoopKind::PingPong if !channel.backward && channel.pos >= sample.loop_end as f64 => {
+						channel.pos = (2.0 * sample.loop_end as f64 - channel.pos - 1.0).max(sample.loop_start as f64);
+						channel.backward = true;
+					},
+					LoopKind::PingPong if channel.backward && channel.pos < sample.loop_start as f64 => {
+						channel.pos = (2.0 * sample.loop_start as f64 - channel.pos).min(sample.loop_end as f64 - 1.0);
+						channel.backward = false;
+					},
+					_ => {}
+				}
+			}
+		}
+	}
+
+
+}
+
+impl SoundSource for ModuleDecoder {
+
+
+	fn channels (&self) -> u16 {
+		2
+	}
+
+
+	fn sample_rate (&self) -> u32 {
+		GENERATOR_SAMPLE_RATE
+	}
+
+
+	fn reset (&mut self) {
+		let module = self.module.clone();
+		for (channel, &panning) in self.channels.iter_mut().zip(module.panning.iter()) {
+			*channel = Channel {
+				panning,
+				..Channel::default()
+			};
+		}
+		self.order = 0;
+		while module.is_marker(self.order) {
+			self.order += 1;
+		}
+		self.row = 0;
+		self.tick = 0;
+		self.speed = module.speed.max(1) as u32;
+		self.tempo = module.tempo.max(1) as u32;
+		self.global_volume = module.global_volume.min(64) as i32;
+		self.global_slide = 0;
+		self.pattern_delay = 0;
+		self.jump_order = None;
+		self.break_row = None;
+		self.loop_row = None;
+		self.ended = self.order >= module.orders.len();
+		self.tick_length = 0;
+		self.tick_frames = 0;
+		self.tick_fraction = 0.0;
+		self.position = 0;
+		self.shared.position.store(ModulePosition::default().pack(), Ordering::Relaxed);
+	}
+
+
+	fn write_samples (&mut self, buffer: &mut [f32]) -> usize {
+
+		// whole frames only
+		let len = buffer.len() / 2 * 2;
+		let mut written = 0;
+		while written < len {
+			if self.tick_frames == 0 {
+				for channel in self.channels.iter_mut() {
+					channel.last_gains = channel.gains;
+				}
+				if !self.next_tick() {
+					break;
+				}
+			}
+			let frames = self.tick_frames.min((len - written) / 2);
+			self.tick_frames -= frames;
+			self.mix(&mut buffer[written..written + frames * 2]);
+			written += frames * 2;
+		}
+		self.position += (written / 2) as u64;
+		written
+
+	}
+
+
+	fn position (&self) -> Option<u64> {
+		Some(self.position)
+	}
+
+
+}
@@ -0,0 +1,152 @@
+
+
+
+use super::{ AMIGA_CLOCK, Cell, Effect, Instrument, LoopKind, Module, ModuleFormat, Note, Pattern, Reader, Sample };
+use crate::error::SourceError;
+
+
+
+/// the period of C-2 in protracker, the note played at the rate of the
+/// sample
+const MIDDLE_PERIOD: f64 = 428.0;
+
+
+
+/// the number of channels, from the tag after the sample headers
+fn channels (data: &[u8]) -> Option<usize> {
+	let digit = |x: u8| x.is_ascii_digit().then(|| (x - b'0') as usize);
+	match data.get(1080..1084)? {
+		b"M.K." | b"M!K!" | b"M&K!" | b"FLT4" | b"4CHN" => Some(4),
+		b"6CHN" => Some(6),
+		b"8CHN" | b"OCTA" | b"FLT8" | b"CD81" => Some(8),
+		&[x, b'C', b'H', b'N'] => digit(x),
+		&[x, y, b'C', b'H'] | &[x, y, b'C', b'N'] => Some(digit(x)? * 10 + digit(y)?),
+		_ => None
+	}
+}
+
+
+pub(super) fn detect (data: &[u8]) -> bool {
+	channels(data).is_some()
+}
+
+
+/// the effect of the protracker `effect` and `param`, also used by xm
+pub(super) fn effect (effect: u8, param: u8) -> Effect {
+	let (x, y) = (param >> 4, param & 0x0F);
+	match effect {
+		0x0 if param == 0 => Effect::None,
+		0x0 => Effect::Arpeggio(param),
+		0x1 => Effect::PortaUp(param),
+		0x2 => Effect::PortaDown(param),
+		0x3 => Effect::TonePorta(param),
+		0x4 => Effect::Vibrato(x, y),
+		0x5 => Effect::TonePortaVolumeSlide(param),
+		0x6 => Effect::VibratoVolumeSlide(param),
+		0x7 => Effect::Tremolo(x, y),
+		0x8 => Effect::SetPanning(param),
+		0x9 => Effect::SampleOffset(param),
+		0xA => Effect::VolumeSlide(param),
+		0xB => Effect::PositionJump(param),
+		0xC => Effect::SetVolume(param),
+		0xD => Effect::PatternBreak(x * 10 + y),
+		0xE => match x {
+			0x1 => Effect::FinePortaUp(y),
+			0x2 => Effect::FinePortaDown(y),
+			0x6 => Effect::PatternLoop(y),
+			0x8 => Effect::SetPanning(y * 17),
+			0x9 => Effect::Retrigger(y),
+			0xA => Effect::FineVolumeUp(y),
+			0xB => Effect::FineVolumeDown(y),
+			0xC => Effect::NoteCut(y),
+			0xD => Effect::NoteDelay(y),
+			0xE => Effect::PatternDelay(y),
+			_ => Effect::None
+		},
+		0xF if param < 32 => Effect::SetSpeed(param),
+		0xF => Effect::SetTempo(param),
+		_ => Effect::None
+	}
+}
+
+
+fn u16_be (reader: &mut Reader) -> Result<usize, SourceError> {
+	let x = reader.bytes(2)?;
+	Ok(u16::from_be_bytes([x[0], x[1]]) as usize)
+}
+
+
+pub(super) fn load (data: &[u8]) -> Result<Module, SourceError> {
+
+	let channels = channels(data).ok_or(SourceError::Unsupported)?;
+	let mut reader = Reader::new(data, 0);
+	let title = reader.text(20)?;
+
+	// the lengths are in words
+	let mut headers = Vec::with_capacity(31);
+	for _ in 0..31 {
+		reader.bytes(22)?;
+		let len = u16_be(&mut reader)? * 2;
+		let finetune = (reader.u8()? << 4) as i8 >> 4;
+		let volume = reader.u8()?.min(64);
+		let loop_start = u16_be(&mut reader)? * 2;
+		let loop_len = u16_be(&mut reader)? * 2;
+		headers.push((len, finetune, volume, loop_start, loop_len));
+	}
+
+	let song_length = (reader.u8()? as usize).clamp(1, 128);
+	reader.u8()?;
+	let orders = reader.bytes(128)?;
+	let pattern_count = *orders.iter().max().unwrap_or(&0) as usize + 1;
+	let orders = orders[..song_length].to_vec();
+
+	reader.pos = 1084;
+	let mut patterns = Vec::with_capacity(pattern_count);
+	for _ in 0..pattern_count {
+		let bytes = reader.bytes(64 * channels * 4)?;
+		let cells = bytes.chunks_exact(4).map(|x| {
+			let period = ((x[0] & 0x0F) as u16) << 8 | x[1] as u16;
+			Cell {
+				note: match period {
+					0 => Note::None,
+					_ => Note::On((48.0 + 12.0 * (MIDDLE_PERIOD / period as f64).log2()).round().clamp(0.0, 95.0) as u8)
+				},
+				instrument: (x[0] & 0xF0) | x[2] >> 4,
+				effect: effect(x[2] & 0x0F, x[3]),
+				..Cell::default()
+			}
+		}).collect();
+		patterns.push(Pattern { rows: 64, cells });
+	}
+
+	// the samples may be cut short at the end of the file
+	let instruments = headers.into_iter().map(|(len, finetune, volume, loop_start, loop_len)| {
+		let data: Vec<f32> = reader.bytes_lossy(len).iter().map(|&x| x as i8 as f32 / 128.0).collect();
+		let loop_end = (loop_start + loop_len).min(data.len());
+		Instrument::single(Sample {
+			loop_kind: if loop_len > 2 && loop_start < loop_end { LoopKind::Forward } else { LoopKind::None },
+			loop_start,
+			loop_end,
+			data,
+			volume,
+			panning: None,
+			c4_rate: AMIGA_CLOCK / MIDDLE_PERIOD * 2f64.powf(finetune as f64 / 96.0)
+		})
+	}).collect();
+
+	Ok(Module {
+		format: ModuleFormat::Mod,
+		title,
+		channels,
+		orders,
+		patterns,
+		instruments,
+		speed: 6,
+		tempo: 125,
+		global_volume: 64,
+		// the amiga played channels left, right, right, left
+		panning: (0..channels).map(|c| if c % 4 == 0 || c % 4 == 3 { 64 } else { 191 }).collect(),
+		linear: false
+	})
+
+}
@@ -0,0 +1,211 @@
+
+
+
+use super::{ Cell, Effect, Instrument, LoopKind, Module, ModuleFormat, Note, Pattern, Reader, Sample, VolumeCommand };
+use crate::error::SourceError;
+
+
+
+/// the orders that play nothing, kept in the order list so the jumps and
+/// the reported orders match the ones of the file
+pub(super) const MARKER: u8 = 254;
+
+
+
+pub(super) fn detect (data: &[u8]) -> bool {
+	data.get(0x2C..0x30) == Some(b"SCRM")
+}
+
+
+/// the effect of the s3m `command`, from 1 for A to 26 for Z
+fn effect (command: u8, param: u8) -> Effect {
+	let (x, y) = (param >> 4, param & 0x0F);
+	match command {
+		1 => Effect::SetSpeed(param),
+		2 => Effect::PositionJump(param),
+		3 => Effect::PatternBreak(x * 10 + y),
+		4 if y == 0x0F && x > 0 => Effect::FineVolumeUp(x),
+		4 if x == 0x0F && y > 0 => Effect::FineVolumeDown(y),
+		4 => Effect::VolumeSlide(param),
+		5 if x == 0x0F => Effect::FinePortaDown(y),
+		5 if x == 0x0E => Effect::ExtraFinePortaDown(y),
+		5 => Effect::PortaDown(param),
+		6 if x == 0x0F => Effect::FinePortaUp(y),
+		6 if x == 0x0E => Effect::ExtraFinePortaUp(y),
+		6 => Effect::PortaUp(param),
+		7 => Effect::TonePorta(param),
+		8 => Effect::Vibrato(x, y),
+		10 => Effect::Arpeggio(param),
+		11 => Effect::VibratoVolumeSlide(param),
+		12 => Effect::TonePortaVolumeSlide(param),
+		15 => Effect::SampleOffset(param),
+		17 => Effect::Retrigger(y),
+		18 => Effect::Tremolo(x, y),
+		19 => match x {
+			0x8 => Effect::SetPanning(y * 17),
+			0xB => Effect::PatternLoop(y),
+			0xC => Effect::NoteCut(y),
+			0xD => Effect::NoteDelay(y),
+			0xE => Effect::PatternDelay(y),
+			_ => Effect::None
+		},
+		20 => Effect::SetTempo(param),
+		21 => Effect::FineVibrato(x, y),
+		22 => Effect::SetGlobalVolume(param),
+		24 => Effect::SetPanning((param.min(0x80) as u16 * 255 / 0x80) as u8),
+		_ => Effect::None
+	}
+}
+
+
+/// the pattern at `offset`, with the channels of the file mapped to the
+/// ones of the module
+fn pattern (data: &[u8], offset: usize, map: &[Option<usize>; 32], channels: usize) -> Result<Pattern, SourceError> {
+	let mut pattern = Pattern::empty(64, channels);
+	if offset == 0 {
+		return Ok(pattern);
+	}
+	let mut reader = Reader::new(data, offset + 2);
+	for row in 0..64 {
+		loop {
+			let what = reader.u8()?;
+			if what == 0 {
+				break;
+			}
+			let mut cell = Cell::default();
+			if what & 0x20 != 0 {
+				cell.note = match reader.u8()? {
+					255 => Note::None,
+					254 => Note::Cut,
+					x => Note::On((x >> 4) * 12 + (x & 0x0F).min(11))
+				};
+				cell.instrument = reader.u8()?;
+			}
+			if what & 0x40 != 0 {
+				cell.volume = VolumeCommand::Set(reader.u8()?.min(64));
+			}
+			if what & 0x80 != 0 {
+				let command = reader.u8()?;
+				cell.effect = effect(command, reader.u8()?);
+			}
+			if let Some(c) = map[(what & 0x1F) as usize] {
+				pattern.cells[row * channels + c] = cell;
+			}
+		}
+	}
+	Ok(pattern)
+}
+
+
+fn instrument (data: &[u8], offset: usize, signed: bool) -> Result<Instrument, SourceError> {
+	let mut reader = Reader::new(data, offset);
+	let kind = reader.u8()?;
+	reader.bytes(12)?;
+	let x = reader.bytes(3)?;
+	let data_offset = ((x[0] as usize) << 16 | (x[2] as usize) << 8 | x[1] as usize) * 16;
+	let len = reader.u32()? as usize;
+	let loop_start = reader.u32()? as usize;
+	let loop_end = reader.u32()? as usize;
+	let volume = reader.u8()?.min(64);
+	reader.bytes(2)?;
+	let flags = reader.u8()?;
+	let c4_rate = reader.u32()? as f64;
+
+	// only the left channel of stereo samples is played
+	let data: Vec<f32> = if kind != 1 {
+		Vec::new()
+	} else if flags & 4 != 0 {
+		let bytes = Reader::new(data, data_offset).bytes_lossy(len * 2);
+		bytes.chunks_exact(2).map(|x| {
+			let x = u16::from_le_bytes([x[0], x[1]]);
+			let x = if signed { x as i16 } else { (x ^ 0x8000) as i16 };
+			x as f32 / 32768.0
+		}).collect()
+	} else {
+		let bytes = Reader::new(data, data_offset).bytes_lossy(len);
+		bytes.iter().map(|&x| {
+			let x = if signed { x as i8 } else { (x ^ 0x80) as i8 };
+			x as f32 / 128.0
+		}).collect()
+	};
+
+	let loop_end = loop_end.min(data.len());
+	Ok(Instrument::single(Sample {
+		loop_kind: if flags & 1 != 0 && loop_start < loop_end { LoopKind::Forward } else { LoopKind::None },
+		loop_start,
+		loop_end,
+		data,
+		volume,
+		panning: None,
+		c4_rate
+	}))
+}
+
+
+pub(super) fn load (data: &[u8]) -> Result<Module, SourceError> {
+
+	let mut reader = Reader::new(data, 0);
+	let title = reader.text(28)?;
+	reader.pos = 0x20;
+	let order_count = reader.u16()? as usize;
+	let instrument_count = reader.u16()? as usize;
+	let pattern_count = reader.u16()? as usize;
+	reader.bytes(4)?;
+	let signed = reader.u16()? == 1;
+	reader.pos = 0x30;
+	let global_volume = reader.u8()?;
+	let speed = reader.u8()?;
+	let tempo = reader.u8()?;
+	reader.bytes(2)?;
+	let default_panning = reader.u8()? == 252;
+	reader.pos = 0x40;
+	let settings = reader.bytes(32)?;
+
+	// the orders stop at the end, 255, and the markers are skipped while
+	// playing
+	let orders: Vec<u8> = reader.bytes(order_count)?.iter()
+		.copied()
+		.take_while(|&x| x != 255)
+		.collect();
+	let mut pointers = || -> Result<usize, SourceError> { Ok(reader.u16()? as usize * 16) };
+	let instrument_offsets = (0..instrument_count).map(|_| pointers()).collect::<Result<Vec<_>, _>>()?;
+	let pattern_offsets = (0..pattern_count).map(|_| pointers()).collect::<Result<Vec<_>, _>>()?;
+	let pan_table = if default_panning { Some(reader.bytes(32)?) } else { None };
+
+	// the enabled channels, 0 to 7 on the left and 8 to 15 on the right
+	let mut map = [None; 32];
+	let mut panning = Vec::new();
+	for (c, &setting) in settings.iter().enumerate() {
+		if setting < 16 {
+			map[c] = Some(panning.len());
+			let pan = match pan_table.map(|x| x[c]) {
+				Some(x) if x & 0x20 != 0 => (x & 0x0F) * 17,
+				_ => if setting < 8 { 64 } else { 191 }
+			};
+			panning.push(pan);
+		}
+	}
+	let channels = panning.len();
+
+	let instruments = instrument_offsets.into_iter().map(|x| instrument(data, x, signed)).collect::<Result<Vec<_>, _>>()?;
+	let mut patterns = pattern_offsets.into_iter().map(|x| pattern(data, x, &map, channels)).collect::<Result<Vec<_>, _>>()?;
+	let used = orders.iter().filter(|&&x| x != MARKER).max().map_or(0, |&x| x as usize + 1);
+	while patterns.len() < used {
+		patterns.push(Pattern::empty(64, channels));
+	}
+
+	Ok(Module {
+		format: ModuleFormat::S3m,
+		title,
+		channels,
+		orders,
+		patterns,
+		instruments,
+		speed,
+		tempo,
+		global_volume,
+		panning,
+		linear: false
+	})
+
+}
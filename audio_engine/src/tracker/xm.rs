@@ -0,0 +1,260 @@
+
+
+
+use super::{ Cell, Effect, Instrument, InstrumentEnvelope, LoopKind, Module, ModuleFormat, Note, Pattern, Reader, Sample, VolumeCommand };
+use super::protracker;
+use crate::error::SourceError;
+
+
+
+const MAGIC: &[u8] = b"Extended Module: ";
+
+/// the limits of fast tracker 2, checked before anything is allocated
+const MAX_CHANNELS: usize = 32;
+const MAX_ROWS: usize = 256;
+const MAX_PATTERNS: usize = 256;
+const MAX_INSTRUMENTS: usize = 128;
+const MAX_SAMPLES: usize = 16;
+
+
+
+pub(super) fn detect (data: &[u8]) -> bool {
+	data.starts_with(MAGIC)
+}
+
+
+fn effect (effect: u8, param: u8) -> Effect {
+	let (x, y) = (param >> 4, param & 0x0F);
+	match effect {
+		0x00..=0x0F => protracker::effect(effect, param),
+		// G, H, K, R and X
+		16 => Effect::SetGlobalVolume(param),
+		17 => Effect::GlobalVolumeSlide(param),
+		20 => Effect::KeyOff(param),
+		27 => Effect::Retrigger(y),
+		33 if x == 1 => Effect::ExtraFinePortaUp(y),
+		33 if x == 2 => Effect::ExtraFinePortaDown(y),
+		_ => Effect::None
+	}
+}
+
+
+fn cell (note: u8, instrument: u8, volume: u8, effect_type: u8, param: u8) -> Cell {
+	Cell {
+		note: match note {
+			1..=96 => Note::On(note - 1),
+			97 => Note::Off,
+			_ => Note::None
+		},
+		instrument,
+		volume: match volume >> 4 {
+			0x1..=0x4 => VolumeCommand::Set(volume - 0x10),
+			0x5 if volume == 0x50 => VolumeCommand::Set(64),
+			0x6 => VolumeCommand::SlideDown(volume & 0x0F),
+			0x7 => VolumeCommand::SlideUp(volume & 0x0F),
+			0x8 => VolumeCommand::FineDown(volume & 0x0F),
+			0x9 => VolumeCommand::FineUp(volume & 0x0F),
+			0xC => VolumeCommand::Panning(volume & 0x0F),
+			0xF => VolumeCommand::TonePorta(volume & 0x0F),
+			_ => VolumeCommand::None
+		},
+		effect: effect(effect_type, param)
+	}
+}
+
+
+fn pattern (reader: &mut Reader, channels: usize) -> Result<Pattern, SourceError> {
+	let start = reader.pos;
+	let header_len = reader.u32()? as usize;
+	reader.u8()?;
+	let rows = (reader.u16()? as usize).max(1);
+	let packed_len = reader.u16()? as usize;
+	if rows > MAX_ROWS {
+		return Err(SourceError::Malformed);
+	}
+	reader.skip_header(start, header_len)?;
+
+	// each cell is packed, a first byte with the high bit set telling
+	// which of the five bytes follow
+	let mut packed = Reader::new(reader.bytes(packed_len)?, 0);
+	let mut pattern = Pattern::empty(rows, channels);
+	if packed_len > 0 {
+		for x in pattern.cells.iter_mut() {
+			let first = packed.u8()?;
+			let mut bytes = [0; 5];
+			if first & 0x80 == 0 {
+				bytes[0] = first;
+				for byte in bytes[1..].iter_mut() {
+					*byte = packed.u8()?;
+				}
+			} else {
+				for (i, byte) in bytes.iter_mut().enumerate() {
+					if first & (1 << i) != 0 {
+						*byte = packed.u8()?;
+					}
+				}
+			}
+			*x = cell(bytes[0], bytes[1], bytes[2], bytes[3], bytes[4]);
+		}
+	}
+	Ok(pattern)
+}
+
+
+fn envelope (points: &[u8], count: u8, sustain: u8, loop_start: u8, loop_end: u8, flags: u8) -> Option<InstrumentEnvelope> {
+	if flags & 1 == 0 || count == 0 {
+		return None;
+	}
+	// the ticks of the points must increase, the points from the first
+	// one going back are dropped
+	let mut envelope = Vec::with_capacity(12);
+	for x in points.chunks_exact(4).take((count as usize).min(12)) {
+		let point = (u16::from_le_bytes([x[0], x[1]]), u16::from_le_bytes([x[2], x[3]]).min(64) as u8);
+		if envelope.last().is_some_and(|&(tick, _)| point.0 <= tick) {
+			break;
+		}
+		envelope.push(point);
+	}
+	let last = envelope.len() - 1;
+	let loop_end = (loop_end as usize).min(last);
+	Some(InstrumentEnvelope {
+		sustain: (flags & 2 != 0).then_some((sustain as usize).min(last)),
+		loop_points: (flags & 4 != 0).then_some(((loop_start as usize).min(loop_end), loop_end)),
+		points: envelope
+	})
+}
+
+
+fn instrument (reader: &mut Reader) -> Result<Instrument, SourceError> {
+	let start = reader.pos;
+	let header_len = reader.u32()? as usize;
+	reader.bytes(23)?;
+	let sample_count = reader.u16()? as usize;
+	if sample_count > MAX_SAMPLES {
+		return Err(SourceError::Unsupported);
+	}
+	if sample_count == 0 {
+		reader.skip_header(start, header_len)?;
+		return Ok(Instrument {
+			samples: Vec::new(),
+			keymap: [0; 96],
+			volume_envelope: None,
+			panning_envelope: None,
+			fadeout: 0
+		});
+	}
+
+	let sample_header_len = reader.u32()? as usize;
+	let mut keymap = [0; 96];
+	keymap.copy_from_slice(reader.bytes(96)?);
+	let volume_points = reader.bytes(48)?;
+	let panning_points = reader.bytes(48)?;
+	let x = reader.bytes(14)?;
+	let volume_envelope = envelope(volume_points, x[0], x[2], x[3], x[4], x[8]);
+	let panning_envelope = envelope(panning_points, x[1], x[5], x[6], x[7], x[9]);
+	let fadeout = reader.u16()?;
+	reader.skip_header(start, header_len)?;
+
+	let mut headers = Vec::with_capacity(sample_count);
+	for _ in 0..sample_count {
+		let start = reader.pos;
+		let len = reader.u32()? as usize;
+		let loop_start = reader.u32()? as usize;
+		let loop_len = reader.u32()? as usize;
+		let x = reader.bytes(6)?;
+		headers.push((len, loop_start, loop_len, x[0].min(64), x[1] as i8, x[2], x[3], x[4] as i8));
+		reader.skip_header(start, sample_header_len)?;
+	}
+
+	// the sample data is stored as the differences between samples
+	let samples = headers.into_iter().map(|(len, loop_start, loop_len, volume, finetune, flags, panning, relative_note)| {
+		let bytes = reader.bytes_lossy(len);
+		let (data, width): (Vec<f32>, usize) = if flags & 0x10 != 0 {
+			let mut old = 0i16;
+			(bytes.chunks_exact(2).map(|x| {
+				old = old.wrapping_add(i16::from_le_bytes([x[0], x[1]]));
+				old as f32 / 32768.0
+			}).collect(), 2)
+		} else {
+			let mut old = 0i8;
+			(bytes.iter().map(|&x| {
+				old = old.wrapping_add(x as i8);
+				old as f32 / 128.0
+			}).collect(), 1)
+		};
+		let loop_start = loop_start / width;
+		let loop_end = ((loop_start * width + loop_len) / width).min(data.len());
+		let loop_kind = match flags & 3 {
+			_ if loop_start >= loop_end => LoopKind::None,
+			1 => LoopKind::Forward,
+			2 => LoopKind::PingPong,
+			_ => LoopKind::None
+		};
+		Sample {
+			data,
+			loop_kind,
+			loop_start,
+			loop_end,
+			volume,
+			panning: Some(panning),
+			c4_rate: 8363.0 * 2f64.powf((relative_note as f64 * 128.0 + finetune as f64) / 1536.0)
+		}
+	}).collect();
+
+	Ok(Instrument {
+		samples,
+		keymap,
+		volume_envelope,
+		panning_envelope,
+		fadeout
+	})
+}
+
+
+pub(super) fn load (data: &[u8]) -> Result<Module, SourceError> {
+
+	let mut reader = Reader::new(data, MAGIC.len());
+	let title = reader.text(20)?;
+	reader.pos = 58;
+	if reader.u16()? < 0x0104 {
+		return Err(SourceError::Unsupported);
+	}
+	let header_len = reader.u32()? as usize;
+	let song_length = (reader.u16()? as usize).min(256);
+	reader.u16()?;
+	let channels = reader.u16()? as usize;
+	let pattern_count = reader.u16()? as usize;
+	let instrument_count = reader.u16()? as usize;
+	let flags = reader.u16()?;
+	let speed = reader.u16()?.min(255) as u8;
+	let tempo = reader.u16()?.min(255) as u8;
+	let orders = reader.bytes(256)?[..song_length].to_vec();
+	reader.skip_header(60, header_len)?;
+	if channels == 0 || channels > MAX_CHANNELS || pattern_count > MAX_PATTERNS || instrument_count > MAX_INSTRUMENTS {
+		return Err(SourceError::Unsupported);
+	}
+
+	let mut patterns = (0..pattern_count).map(|_| pattern(&mut reader, channels)).collect::<Result<Vec<_>, _>>()?;
+	// the orders may play patterns that are not stored, which are empty
+	let used = orders.iter().max().map_or(0, |&x| x as usize + 1);
+	while patterns.len() < used {
+		patterns.push(Pattern::empty(64, channels));
+	}
+
+	let instruments = (0..instrument_count).map(|_| instrument(&mut reader)).collect::<Result<Vec<_>, _>>()?;
+
+	Ok(Module {
+		format: ModuleFormat::Xm,
+		title,
+		channels,
+		orders,
+		patterns,
+		instruments,
+		speed,
+		tempo,
+		global_volume: 64,
+		panning: vec![128; channels],
+		linear: flags & 1 != 0
+	})
+
+}
@@ -4,18 +4,29 @@
 use hound::WavReader;
 use log::error;
 
-use std::io::{ Read, Seek };
+use std::io::{ Read, Seek, SeekFrom };
 
+use crate::error::SourceError;
 use crate::mixer::SoundSource;
 
 
 
 /// Wav File Decoder
+///
+/// the first loop of a `smpl` chunk is used as the
+/// [loop region](SoundSource::loop_region) of the file. without it, the
+/// first two points of a `cue ` chunk are the start and the end of the
+/// loop, or a single point is the start of a loop that goes to the end
 pub struct WavDecoder <T: Seek + Read + Send + 'static> {
 
 	reader: WavReader<T>,
 	channels: u16,
-	sample_rate: u32
+	sample_rate: u32,
+	/// the next frame to be read
+	position: u64,
+	loop_region: Option<(u64, Option<u64>)>,
+	/// the last decoding error, until it is taken
+	error: Option<SourceError>
 
 }
 
@@ -23,11 +34,20 @@ impl <T: Seek + Read + Send + 'static> WavDecoder<T> {
 
 
 	/// Create a new wav file decoder
-	pub fn new (data: T) -> Result<Self, hound::Error> {
+	pub fn new (mut data: T) -> Result<Self, hound::Error> {
+		// hound skips the chunks it doesn't know, so look for the loop
+		// points before it reads the file
+		let start = data.stream_position()?;
+		let loop_region = read_loop_region(&mut data).unwrap_or(None);
+		data.seek(SeekFrom::Start(start))?;
+
 		let reader = WavReader::new(data)?;
 		Ok(Self {
 			channels: reader.spec().channels,
 			sample_rate: reader.spec().sample_rate,
+			position: 0,
+			loop_region,
+			error: None,
 			reader
 		})
 	}
@@ -35,29 +55,32 @@ impl <T: Seek + Read + Send + 'static> WavDecoder<T> {
 
 	fn inner_write_sample <S: hound::Sample> (
 		&mut self,
-		buffer: &mut [i16],
-		to_i16: impl Fn(S) -> i16
+		buffer: &mut [f32],
+		to_f32: impl Fn(S) -> f32
 	) -> usize {
 
+		let channels = self.channels as usize;
 		let mut samples = self.reader.samples::<S>();
-		for i in 0..buffer.len() {
+		for (i, b) in buffer.iter_mut().enumerate() {
 			if let Some(sample) = samples.next() {
-				buffer[i] = match sample {
-					Ok(x) => to_i16(x),
+				*b = match sample {
+					Ok(x) => to_f32(x),
 					Err(err) => {
 						error!("error while decoding wav: {}", err);
-						// https://github.com/Rodrigodd/audio-engine/blob/3d0da3711b5cc78e7192d616ebb1d4069920707d/src/wav.rs#L36
 						// Return the current number of decoded samples before the error,
-						// indicating that the SoundSource finished. If this SoundSource was marked
-						// to loop, then this Error will repeat indefinitely. Maybe there should be
-						// a mechanism to report errors from a SoundSource.
+						// indicating that the SoundSource finished. The error is reported by
+						// `take_error`, so the mixer stops the sound even if it loops.
+						self.error = Some(err.into());
+						self.position += (i / channels) as u64;
 						return i;
 					}
 				}
 			} else {
+				self.position += (i / channels) as u64;
 				return i;
 			}
 		}
+		self.position += (buffer.len() / channels) as u64;
 		buffer.len()
 
 	}
@@ -69,7 +92,42 @@ impl <T: Seek + Read + Send + 'static> SoundSource for WavDecoder<T> {
 
 
 	fn reset (&mut self) {
-		self.reader.seek(0).unwrap();
+		if let Err(err) = self.reader.seek(0) {
+			error!("failed to rewind wav: {}", err);
+			self.error = Some(SourceError::Io);
+		}
+		self.position = 0;
+	}
+
+
+	fn seek (&mut self, frame: u64) -> Result<(), SourceError> {
+		let frame = frame.min(self.reader.duration() as u64);
+		self.reader.seek(frame as u32).map_err(|err| {
+			error!("failed to seek wav: {}", err);
+			SourceError::Io
+		})?;
+		self.position = frame;
+		Ok(())
+	}
+
+
+	fn len_frames (&self) -> Option<u64> {
+		Some(self.reader.duration() as u64)
+	}
+
+
+	fn position (&self) -> Option<u64> {
+		Some(self.position)
+	}
+
+
+	fn loop_region (&self) -> Option<(u64, Option<u64>)> {
+		self.loop_region
+	}
+
+
+	fn take_error (&mut self) -> Option<SourceError> {
+		self.error.take()
 	}
 
 
@@ -83,22 +141,23 @@ impl <T: Seek + Read + Send + 'static> SoundSource for WavDecoder<T> {
 	}
 
 
-	fn write_samples (&mut self, buffer: &mut [i16]) -> usize {
+	fn write_samples (&mut self, buffer: &mut [f32]) -> usize {
 
 		let sample_format = self.reader.spec().sample_format;
 		let bits_per_sample = self.reader.spec().bits_per_sample;
 
 		match (sample_format, bits_per_sample) {
-			(hound::SampleFormat::Float, _) => self.inner_write_sample(buffer, f32_to_i16),
+			(hound::SampleFormat::Float, _) => self.inner_write_sample(buffer, |x: f32| x),
 			// 24bit or 32bit
 			(hound::SampleFormat::Int, x) if x > 16 => {
-				self.inner_write_sample(buffer, |x: i32| (x >> (bits_per_sample - 16)) as i16)
+				let scale = 1.0 / (1u64 << (bits_per_sample - 1)) as f32;
+				self.inner_write_sample(buffer, |x: i32| x as f32 * scale)
 			},
 			// 16bit
-			(hound::SampleFormat::Int, x) if x == 16 => self.inner_write_sample(buffer, |x: i16| x),
+			(hound::SampleFormat::Int, 16) => self.inner_write_sample(buffer, |x: i16| x as f32 / 32768.0),
 			// 8bit
 			(hound::SampleFormat::Int, _) => {
-				self.inner_write_sample(buffer, |x: i8| (x as i16) << 8)
+				self.inner_write_sample(buffer, |x: i8| x as f32 / 128.0)
 			}
 		}
 
@@ -109,18 +168,57 @@ impl <T: Seek + Read + Send + 'static> SoundSource for WavDecoder<T> {
 
 
 
-fn f32_to_i16 (mut x: f32) -> i16 {
-	if x > 1.0 {
-		x = 1.0
-	}
-	if x < -1.0 {
-		x = -1.0
+/// the loop points of the `smpl` or `cue ` chunk of a riff wave file,
+/// reading all chunks but the audio data
+fn read_loop_region <R: Read + Seek> (data: &mut R) -> std::io::Result<Option<(u64, Option<u64>)>> {
+
+	/// chunks bigger than this are not loop points
+	const MAX_CHUNK: u64 = 1 << 20;
+
+	let u32_at = |bytes: &[u8], offset: usize| -> Option<u32> {
+		let bytes = bytes.get(offset..offset + 4)?;
+		Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+	};
+
+	let mut header = [0; 12];
+	data.read_exact(&mut header)?;
+	if &header[0..4] != b"RIFF" || &header[8..12] != b"WAVE" {
+		return Ok(None);
 	}
-	if x >= 0.0 {
-		(x * i16::MAX as f32) as i16
-	} else {
-		(-x * i16::MIN as f32) as i16
+
+	let mut cues = Vec::new();
+	let mut chunk = [0; 8];
+	while data.read_exact(&mut chunk).is_ok() {
+		let size = u32_at(&chunk, 4).unwrap_or(0) as u64;
+		// chunks are padded to an even size
+		let padded = size + size % 2;
+		let id = &chunk[0..4];
+		if (id != b"smpl" && id != b"cue ") || size > MAX_CHUNK {
+			data.seek(SeekFrom::Current(padded as i64))?;
+			continue;
+		}
+
+		let mut body = vec![0; padded as usize];
+		data.read_exact(&mut body)?;
+		if id == b"smpl" {
+			// the loops come after 36 bytes of sampler settings, with 24
+			// bytes each. the end of a loop is its last frame
+			let loops = u32_at(&body, 28).unwrap_or(0);
+			if let (true, Some(start), Some(end)) = (loops > 0, u32_at(&body, 44), u32_at(&body, 48)) {
+				return Ok(Some((start as u64, Some(end as u64 + 1))));
+			}
+		} else {
+			// each cue point has 24 bytes, ending with its frame
+			let points = u32_at(&body, 0).unwrap_or(0) as usize;
+			cues = (0..points).filter_map(|i| u32_at(&body, 4 + i * 24 + 20)).map(u64::from).collect();
+			cues.sort_unstable();
+		}
 	}
-}
 
+	Ok(match cues[..] {
+		[] => None,
+		[start] => Some((start, None)),
+		[start, end, ..] => Some((start, Some(end)))
+	})
 
+}
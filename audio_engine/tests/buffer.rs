@@ -0,0 +1,65 @@
+//! Sounds played from a shared, decoded sound buffer.
+
+mod common;
+
+use audio_engine::{ AudioEngine, SoundBuffer, SoundSource };
+use common::{ wav_i16, I16_STEP };
+
+use std::time::Duration;
+
+
+
+#[test]
+fn decode () {
+	for buffer in [SoundBuffer::decode(wav_i16(5000)).unwrap(), SoundBuffer::decode_i16(wav_i16(5000)).unwrap()] {
+		assert_eq!(buffer.channels(), 1);
+		assert_eq!(buffer.sample_rate(), 48000);
+		assert_eq!(buffer.len_frames(), 5000);
+
+		let mut source = buffer.source();
+		let mut output = [0.0; 3];
+		source.seek(4000).unwrap();
+		assert_eq!(source.write_samples(&mut output), 3);
+		assert_eq!(output, [4001.0, 4002.0, 4003.0].map(|x| x * I16_STEP));
+		assert_eq!(source.position(), Some(4003));
+	}
+}
+
+
+#[test]
+fn overlapping_instances () {
+	let engine = AudioEngine::new_offline(1, 48000);
+	let buffer = SoundBuffer::decode(wav_i16(100)).unwrap();
+
+	let mut first = engine.new_sound(buffer.source(), None).unwrap();
+	let mut second = engine.new_sound(buffer.source(), None).unwrap();
+	assert_eq!(first.duration(), Some(Duration::from_secs_f64(100.0 / 48000.0)));
+
+	first.play();
+	engine.render_frames(10).unwrap();
+	second.play();
+	let output = engine.render_frames(2).unwrap();
+	assert_eq!(output, [11.0 + 1.0, 12.0 + 2.0].map(|x| x * I16_STEP));
+
+	// each instance ends on its own
+	engine.render_frames(90).unwrap();
+	assert!(!first.is_playing());
+	assert!(second.is_playing());
+	assert_eq!(second.position(), 92);
+}
+
+
+#[test]
+fn from_samples () {
+	assert!(SoundBuffer::from_samples(2, 48000, vec![0.0; 3]).is_err());
+	assert!(SoundBuffer::from_samples(0, 48000, vec![]).is_err());
+
+	let engine = AudioEngine::new_offline(2, 48000);
+	let buffer = SoundBuffer::from_i16_samples(2, 48000, vec![16384, -16384, 8192, -8192]).unwrap()
+		.with_loop_region(Some((1, None)));
+	let mut sound = engine.new_sound(buffer.source(), None).unwrap();
+	sound.set_loop(true);
+	sound.play();
+	let output = engine.render_frames(3).unwrap();
+	assert_eq!(output, [0.5, -0.5, 0.25, -0.25, 0.25, -0.25]);
+}
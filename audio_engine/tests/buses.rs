@@ -0,0 +1,109 @@
+//! Check the routing of sounds through nested buses.
+
+use audio_engine::{ AudioEngine, FnEffect, SoundSource };
+
+
+
+/// a mono source that outputs `value` for `len` frames
+struct Constant {
+	value: f32,
+	len: usize,
+	pos: usize
+}
+
+impl Constant {
+	fn new (value: f32, len: usize) -> Self {
+		Self { value, len, pos: 0 }
+	}
+}
+
+impl SoundSource for Constant {
+
+	fn channels (&self) -> u16 {
+		1
+	}
+
+	fn sample_rate (&self) -> u32 {
+		48000
+	}
+
+	fn reset (&mut self) {
+		self.pos = 0;
+	}
+
+	fn write_samples (&mut self, buffer: &mut [f32]) -> usize {
+		let len = buffer.len().min(self.len - self.pos);
+		buffer[..len].fill(self.value);
+		self.pos += len;
+		len
+	}
+
+}
+
+
+
+#[test]
+fn volume_and_mute () {
+	let engine = AudioEngine::new_offline(1, 48000);
+	let music = engine.new_bus("music", None);
+	let sfx = engine.new_bus("sfx", None);
+	let mut a = engine.new_sound(Constant::new(0.25, 100), Some(&music)).unwrap();
+	let mut b = engine.new_sound(Constant::new(0.5, 100), Some(&sfx)).unwrap();
+	a.play();
+	b.play();
+	assert_eq!(engine.render_frames(2).unwrap(), [0.75, 0.75]);
+
+	sfx.set_volume(0.5);
+	assert_eq!(engine.render_frames(2).unwrap(), [0.5, 0.5]);
+
+	music.set_muted(true);
+	assert_eq!(engine.render_frames(2).unwrap(), [0.25, 0.25]);
+
+	engine.master().set_volume(2.0);
+	assert_eq!(engine.render_frames(2).unwrap(), [0.5, 0.5]);
+
+	// the muted sound kept playing
+	assert_eq!(a.position(), 8);
+}
+
+
+#[test]
+fn nested_buses () {
+	let engine = AudioEngine::new_offline(1, 48000);
+	let sfx = engine.new_bus("sfx", None);
+	let ui = engine.new_bus("ui", Some(&sfx));
+	let mut sound = engine.new_sound(Constant::new(0.5, 100), Some(&ui)).unwrap();
+	sound.play();
+
+	ui.set_volume(0.5);
+	sfx.set_volume(0.5);
+	ui.add_effect(FnEffect::new(|x| x * 3.0));
+	sfx.add_effect(FnEffect::new(|x| x * 2.0));
+	assert_eq!(engine.render_frames(2).unwrap(), [0.75, 0.75]);
+
+	// the sounds of a dropped bus are routed to its parent
+	drop(ui);
+	assert_eq!(engine.render_frames(2).unwrap(), [0.5, 0.5]);
+}
+
+
+#[test]
+fn pause_and_resume () {
+	let engine = AudioEngine::new_offline(1, 48000);
+	let sfx = engine.new_bus("sfx", None);
+	let ui = engine.new_bus("ui", Some(&sfx));
+	let mut a = engine.new_sound(Constant::new(0.25, 4), Some(&ui)).unwrap();
+	let mut b = engine.new_sound(Constant::new(0.5, 100), None).unwrap();
+	a.play();
+	b.play();
+	assert_eq!(engine.render_frames(2).unwrap(), [0.75, 0.75]);
+
+	sfx.pause();
+	assert_eq!(engine.render_frames(4).unwrap(), [0.5; 4]);
+	assert_eq!(a.position(), 2);
+	assert!(a.is_playing());
+
+	sfx.resume();
+	assert_eq!(engine.render_frames(4).unwrap(), [0.75, 0.75, 0.5, 0.5]);
+	assert!(!a.is_playing());
+}
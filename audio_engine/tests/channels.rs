@@ -0,0 +1,102 @@
+use audio_engine::{ AudioEngine, ChannelMatrix, SoundSource };
+
+
+
+/// a single frame with a given value for each channel
+struct Frame {
+	values: Vec<f32>,
+	done: bool
+}
+
+impl Frame {
+	fn new (values: &[f32]) -> Self {
+		Self { values: values.to_vec(), done: false }
+	}
+}
+
+impl SoundSource for Frame {
+
+	fn channels (&self) -> u16 {
+		self.values.len() as u16
+	}
+
+	fn sample_rate (&self) -> u32 {
+		48000
+	}
+
+	fn reset (&mut self) {
+		self.done = false;
+	}
+
+	fn write_samples (&mut self, buffer: &mut [f32]) -> usize {
+		if self.done || buffer.len() < self.values.len() {
+			return 0;
+		}
+		self.done = true;
+		buffer[..self.values.len()].copy_from_slice(&self.values);
+		self.values.len()
+	}
+
+}
+
+
+
+/// the first frame of `source` mixed to a given number of channels
+fn convert (engine: &AudioEngine, source: Frame) -> Vec<f32> {
+	let mut sound = engine.new_sound(source, None).unwrap();
+	sound.play();
+	engine.render_frames(1).unwrap()
+}
+
+
+#[test]
+fn upmix () {
+	let quad = AudioEngine::new_offline(4, 48000);
+	assert_eq!(convert(&quad, Frame::new(&[0.25, 0.5])), [0.25, 0.5, 0.0, 0.0]);
+
+	let surround = AudioEngine::new_offline(6, 48000);
+	// mono is panned to the front speakers, at -3dB on each
+	let h = std::f32::consts::FRAC_1_SQRT_2;
+	assert_eq!(convert(&surround, Frame::new(&[0.5])), [0.5 * h, 0.5 * h, 0.0, 0.0, 0.0, 0.0]);
+	assert_eq!(
+		convert(&surround, Frame::new(&[0.1, 0.2, 0.3, 0.4])),
+		[0.1, 0.2, 0.0, 0.0, 0.3, 0.4]
+	);
+
+	let seven_one = AudioEngine::new_offline(8, 48000);
+	assert_eq!(
+		convert(&seven_one, Frame::new(&[0.25, 0.5])),
+		[0.25, 0.5, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0]
+	);
+}
+
+
+#[test]
+fn downmix () {
+	let h = std::f32::consts::FRAC_1_SQRT_2;
+
+	let stereo = AudioEngine::new_offline(2, 48000);
+	let out = convert(&stereo, Frame::new(&[0.1, 0.2, 0.3, 0.9, 0.4, 0.5]));
+	assert!((out[0] - (0.1 + h * 0.3 + h * 0.4)).abs() < 1e-6);
+	assert!((out[1] - (0.2 + h * 0.3 + h * 0.5)).abs() < 1e-6);
+
+	let mono = AudioEngine::new_offline(1, 48000);
+	assert_eq!(convert(&mono, Frame::new(&[0.25, 0.75])), [0.5]);
+
+	// 7.1 side and back channels are merged before the 5.1 mix
+	let quad = AudioEngine::new_offline(4, 48000);
+	let out = convert(&quad, Frame::new(&[0.0, 0.0, 0.0, 0.0, 0.5, 0.0, 0.5, 0.0]));
+	assert!((out[2] - h).abs() < 1e-6);
+	assert_eq!(out[3], 0.0);
+}
+
+
+#[test]
+fn user_matrix () {
+	let engine = AudioEngine::new_offline(2, 48000);
+	// mono only to the left speaker
+	engine.set_channel_matrix(ChannelMatrix::new(1, 2, vec![1.0, 0.0]).unwrap());
+	assert_eq!(convert(&engine, Frame::new(&[0.5])), [0.5, 0.0]);
+
+	assert!(ChannelMatrix::new(2, 2, vec![1.0]).is_err());
+}
@@ -0,0 +1,111 @@
+//! Sources and helpers shared by the tests.
+
+#![allow(dead_code)]
+
+pub mod vorbis;
+
+use audio_engine::{ SoundSource, WavDecoder };
+
+use std::io::Cursor;
+use std::time::Duration;
+
+
+
+/// the value of each step of the float test files, exactly
+/// representable so samples can be compared with `==`
+pub const STEP: f32 = 1.0 / 1024.0;
+
+/// the value of each step of the 16 bit test files
+pub const I16_STEP: f32 = 1.0 / 32768.0;
+
+
+
+/// a mono float wav file of `len` frames with the values `1, 2, 3, ...`
+/// times [`STEP`]
+pub fn wav (len: usize, sample_rate: u32) -> WavDecoder<Cursor<Vec<u8>>> {
+	wav_with_chunks(len, sample_rate, &[])
+}
+
+
+/// the same file as [`wav`], followed by the given chunks
+pub fn wav_with_chunks (len: usize, sample_rate: u32, chunks: &[(&[u8; 4], Vec<u32>)]) -> WavDecoder<Cursor<Vec<u8>>> {
+	let spec = hound::WavSpec {
+		channels: 1,
+		sample_rate,
+		bits_per_sample: 32,
+		sample_format: hound::SampleFormat::Float
+	};
+	let mut data = Cursor::new(Vec::new());
+	let mut writer = hound::WavWriter::new(&mut data, spec).unwrap();
+	for i in 0..len {
+		writer.write_sample((i + 1) as f32 * STEP).unwrap();
+	}
+	writer.finalize().unwrap();
+
+	let mut data = data.into_inner();
+	for (id, words) in chunks {
+		data.extend_from_slice(*id);
+		data.extend_from_slice(&(words.len() as u32 * 4).to_le_bytes());
+		for word in words {
+			data.extend_from_slice(&word.to_le_bytes());
+		}
+	}
+	let riff = data.len() as u32 - 8;
+	data[4..8].copy_from_slice(&riff.to_le_bytes());
+	WavDecoder::new(Cursor::new(data)).unwrap()
+}
+
+
+/// a mono 16 bit wav file at 48kHz of `len` frames with the values
+/// `1, 2, 3, ...` times [`I16_STEP`]
+pub fn wav_i16 (len: usize) -> WavDecoder<Cursor<Vec<u8>>> {
+	let spec = hound::WavSpec {
+		channels: 1,
+		sample_rate: 48000,
+		bits_per_sample: 16,
+		sample_format: hound::SampleFormat::Int
+	};
+	let mut data = Cursor::new(Vec::new());
+	let mut writer = hound::WavWriter::new(&mut data, spec).unwrap();
+	for i in 0..len {
+		writer.write_sample(i as i16 + 1).unwrap();
+	}
+	writer.finalize().unwrap();
+	data.set_position(0);
+	WavDecoder::new(data).unwrap()
+}
+
+
+/// the duration of `n` frames at 48kHz
+pub fn frames (n: u32) -> Duration {
+	Duration::from_secs_f64(n as f64 / 48000.0)
+}
+
+
+/// the expected output, in steps
+pub fn steps (values: &[i32]) -> Vec<f32> {
+	values.iter().map(|&x| x as f32 * STEP).collect()
+}
+
+
+/// up to `len` samples written by `source`
+pub fn write (source: &mut impl SoundSource, len: usize) -> Vec<f32> {
+	let mut buffer = vec![0.0; len];
+	let len = source.write_samples(&mut buffer);
+	buffer.truncate(len);
+	buffer
+}
+
+
+/// the samples of channel `c` of interleaved `samples`
+pub fn channel (samples: &[f32], channels: usize, c: usize) -> Vec<f32> {
+	samples.iter().skip(c).step_by(channels).copied().collect()
+}
+
+
+/// the number of times the signal crosses zero, twice the number of
+/// periods of a sine. the samples at 0 are skipped, as silence
+pub fn crossings (samples: &[f32]) -> usize {
+	let samples: Vec<_> = samples.iter().filter(|&&x| x != 0.0).collect();
+	samples.windows(2).filter(|w| (*w[0] < 0.0) != (*w[1] < 0.0)).count()
+}
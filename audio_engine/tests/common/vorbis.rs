@@ -0,0 +1,307 @@
+//! A tiny ogg vorbis encoder, for the ogg decoder tests.
+//!
+//! the files use short blocks of 256 samples, so each audio packet after
+//! the first decodes to 128 frames, and one codebook whose entries are a
+//! single coefficient of -0.5, 0 or 0.5. each packet of each channel sets a
+//! different coefficient, so every packet sounds different.
+
+use audio_engine::OggDecoder;
+
+use std::io::Cursor;
+
+
+
+/// the frames decoded from each audio packet
+pub const PACKET_FRAMES: u64 = 128;
+
+/// the audio packets in each page
+const PAGE_PACKETS: usize = 4;
+
+
+
+/// a logical stream of an ogg file
+pub struct Stream {
+	pub channels: u8,
+	pub sample_rate: u32,
+	/// the length of the stream, a multiple of [`PACKET_FRAMES`]
+	pub frames: u64,
+	/// the channels that are left silent
+	pub silent: Vec<u8>,
+	/// the comments, as `KEY=value`
+	pub comments: Vec<&'static str>
+}
+
+impl Default for Stream {
+	fn default () -> Self {
+		Self {
+			channels: 1,
+			sample_rate: 8000,
+			frames: 40 * PACKET_FRAMES,
+			silent: Vec::new(),
+			comments: Vec::new()
+		}
+	}
+}
+
+
+
+/// an ogg vorbis file of `streams`, chained one after the other
+pub fn ogg (streams: &[Stream]) -> OggDecoder<Cursor<Vec<u8>>> {
+	OggDecoder::new(Cursor::new(ogg_data(streams))).unwrap()
+}
+
+
+/// the data of the file made by [`ogg`]
+pub fn ogg_data (streams: &[Stream]) -> Vec<u8> {
+	let mut data = Vec::new();
+	for (i, stream) in streams.iter().enumerate() {
+		assert_eq!(stream.frames % PACKET_FRAMES, 0);
+		let serial = 0x5EED + i as u32;
+		let mut sequence = 0;
+		let mut page = |packets: &[Vec<u8>], granule: u64, flags: u8| {
+			data.extend_from_slice(&page_data(packets, granule, flags, serial, sequence));
+			sequence += 1;
+		};
+
+		page(&[ident(stream)], 0, 0x02);
+		page(&[comments(stream), setup()], 0, 0);
+		let packets: Vec<_> = (0..=stream.frames / PACKET_FRAMES).map(|p| audio(stream, p)).collect();
+		let pages = packets.chunks(PAGE_PACKETS).count();
+		for (p, chunk) in packets.chunks(PAGE_PACKETS).enumerate() {
+			// the first packet of the stream only primes the decoder
+			let granule = ((p + 1) * PAGE_PACKETS).min(packets.len()) as u64 - 1;
+			let flags = if p + 1 == pages { 0x04 } else { 0 };
+			page(chunk, granule * PACKET_FRAMES, flags);
+		}
+	}
+	data
+}
+
+
+
+/// bits packed from the least significant bit of each byte, as vorbis
+/// does
+#[derive(Default)]
+struct Bits {
+	data: Vec<u8>,
+	len: usize
+}
+
+impl Bits {
+
+
+	fn write (&mut self, value: u32, bits: u32) {
+		for i in 0..bits {
+			if self.len.is_multiple_of(8) {
+				self.data.push(0);
+			}
+			if value >> i & 1 != 0 {
+				*self.data.last_mut().unwrap() |= 1 << (self.len % 8);
+			}
+			self.len += 1;
+		}
+	}
+
+
+	fn bytes (&mut self, bytes: &[u8]) {
+		for &byte in bytes {
+			self.write(byte as u32, 8);
+		}
+	}
+
+
+	/// a huffman code, written from its first bit
+	fn code (&mut self, code: &[u32]) {
+		for &bit in code {
+			self.write(bit, 1);
+		}
+	}
+
+
+}
+
+
+
+/// `value` in the float format of the vorbis codebooks, for powers of 2
+fn float (value: f32) -> u32 {
+	let exponent = value.abs().log2() as i32;
+	let sign = if value < 0.0 { 0x8000_0000 } else { 0 };
+	sign | ((788 + exponent - 20) as u32) << 21 | 1 << 20
+}
+
+
+fn ident (stream: &Stream) -> Vec<u8> {
+	let mut bits = Bits::default();
+	bits.bytes(b"\x01vorbis");
+	bits.write(0, 32);
+	bits.write(stream.channels as u32, 8);
+	bits.write(stream.sample_rate, 32);
+	bits.write(0, 32);
+	bits.write(0, 32);
+	bits.write(0, 32);
+	// both block sizes are 2^8
+	bits.write(8, 4);
+	bits.write(8, 4);
+	bits.write(1, 8);
+	bits.data
+}
+
+
+fn comments (stream: &Stream) -> Vec<u8> {
+	let mut data = b"\x03vorbis".to_vec();
+	let vendor = b"audio_engine tests";
+	data.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+	data.extend_from_slice(vendor);
+	data.extend_from_slice(&(stream.comments.len() as u32).to_le_bytes());
+	for comment in &stream.comments {
+		data.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+		data.extend_from_slice(comment.as_bytes());
+	}
+	data.push(1);
+	data
+}
+
+
+fn setup () -> Vec<u8> {
+	let mut bits = Bits::default();
+	bits.bytes(b"\x05vorbis");
+
+	// two codebooks of one dimension, the classifications and the
+	// coefficients, of 0, 0.5 and -0.5 for the codes 0, 10 and 11
+	bits.write(1, 8);
+	bits.write(0x564342, 24);
+	bits.write(1, 16);
+	bits.write(2, 24);
+	bits.write(0, 2);
+	bits.write(0, 5);
+	bits.write(0, 5);
+	bits.write(0, 4);
+
+	bits.write(0x564342, 24);
+	bits.write(1, 16);
+	bits.write(3, 24);
+	bits.write(0, 2);
+	for length in [1, 2, 2] {
+		bits.write(length - 1, 5);
+	}
+	bits.write(1, 4);
+	bits.write(float(-0.5), 32);
+	bits.write(float(0.5), 32);
+	bits.write(1, 4);
+	bits.write(0, 1);
+	for multiplicand in [1, 2, 0] {
+		bits.write(multiplicand, 2);
+	}
+
+	// the unused time domain transform
+	bits.write(0, 6);
+	bits.write(0, 16);
+
+	// a flat floor 1 at full volume
+	bits.write(0, 6);
+	bits.write(1, 16);
+	bits.write(0, 5);
+	bits.write(0, 2);
+	bits.write(7, 4);
+
+	// a residue 1 of one partition over the whole block
+	bits.write(0, 6);
+	bits.write(1, 16);
+	bits.write(0, 24);
+	bits.write(128, 24);
+	bits.write(127, 24);
+	bits.write(0, 6);
+	bits.write(0, 8);
+	bits.write(1, 3);
+	bits.write(0, 1);
+	bits.write(1, 8);
+
+	// one mapping and one mode of short blocks
+	bits.write(0, 6);
+	bits.write(0, 16);
+	bits.write(0, 1);
+	bits.write(0, 1);
+	bits.write(0, 2);
+	bits.write(0, 24);
+
+	bits.write(0, 6);
+	bits.write(0, 1);
+	bits.write(0, 16);
+	bits.write(0, 16);
+	bits.write(0, 8);
+
+	bits.write(1, 1);
+	bits.data
+}
+
+
+/// the audio packet `p`, of a single coefficient in each channel
+fn audio (stream: &Stream, p: u64) -> Vec<u8> {
+	let mut bits = Bits::default();
+	bits.write(0, 1);
+	let channels: Vec<_> = (0..stream.channels).filter(|c| !stream.silent.contains(c)).collect();
+	for c in 0..stream.channels {
+		if channels.contains(&c) {
+			bits.write(1, 1);
+			bits.write(255, 8);
+			bits.write(255, 8);
+		} else {
+			bits.write(0, 1);
+		}
+	}
+	for _ in &channels {
+		bits.code(&[0]);
+	}
+	for &c in &channels {
+		let bin = (p * 7 + c as u64 * 13) % 60 + 4;
+		for i in 0..128 {
+			match (i == bin, p.is_multiple_of(2)) {
+				(false, _) => bits.code(&[0]),
+				(true, true) => bits.code(&[1, 0]),
+				(true, false) => bits.code(&[1, 1])
+			}
+		}
+	}
+	bits.data
+}
+
+
+/// an ogg page of `packets`, which must fit in it
+fn page_data (packets: &[Vec<u8>], granule: u64, flags: u8, serial: u32, sequence: u32) -> Vec<u8> {
+	let mut data = b"OggS".to_vec();
+	data.push(0);
+	data.push(flags);
+	data.extend_from_slice(&granule.to_le_bytes());
+	data.extend_from_slice(&serial.to_le_bytes());
+	data.extend_from_slice(&sequence.to_le_bytes());
+	data.extend_from_slice(&[0; 4]);
+
+	let mut lacing = Vec::new();
+	for packet in packets {
+		lacing.extend(std::iter::repeat_n(255, packet.len() / 255));
+		lacing.push((packet.len() % 255) as u8);
+	}
+	assert!(lacing.len() <= 255);
+	data.push(lacing.len() as u8);
+	data.extend_from_slice(&lacing);
+	for packet in packets {
+		data.extend_from_slice(packet);
+	}
+
+	let crc = crc(&data);
+	data[22..26].copy_from_slice(&crc.to_le_bytes());
+	data
+}
+
+
+/// the crc of the ogg pages, of polynomial 0x04C11DB7 without reflection
+fn crc (data: &[u8]) -> u32 {
+	let mut crc = 0u32;
+	for &byte in data {
+		crc ^= (byte as u32) << 24;
+		for _ in 0..8 {
+			crc = if crc & 0x8000_0000 != 0 { crc << 1 ^ 0x04C1_1DB7 } else { crc << 1 };
+		}
+	}
+	crc
+}
@@ -0,0 +1,134 @@
+//! Check that the limiter keeps loud mixes under the ceiling without
+//! the kinks of clipping, and the steady state of the compressor.
+
+use audio_engine::{ AudioEngine, Compressor, Limiter, SoundSource };
+
+use std::f32::consts::TAU;
+
+
+
+/// a mono sine wave, or a constant if `frequency` is 0
+struct Wave {
+	frequency: f32,
+	amplitude: f32,
+	pos: usize
+}
+
+impl Wave {
+	fn new (frequency: f32, amplitude: f32) -> Self {
+		Self { frequency, amplitude, pos: 0 }
+	}
+}
+
+impl SoundSource for Wave {
+
+	fn channels (&self) -> u16 {
+		1
+	}
+
+	fn sample_rate (&self) -> u32 {
+		48000
+	}
+
+	fn reset (&mut self) {
+		self.pos = 0;
+	}
+
+	fn write_samples (&mut self, buffer: &mut [f32]) -> usize {
+		for (i, b) in buffer.iter_mut().enumerate() {
+			let t = (self.pos + i) as f32 / 48000.0;
+			*b = self.amplitude * if self.frequency == 0.0 { 1.0 } else { (TAU * self.frequency * t).sin() };
+		}
+		self.pos += buffer.len();
+		buffer.len()
+	}
+
+}
+
+
+
+/// one second of a mix of full scale sines
+fn loud_mix (engine: &AudioEngine) -> Vec<f32> {
+	let mut sounds = [110.0, 220.0, 330.0]
+		.map(|frequency| engine.new_sound(Wave::new(frequency, 1.0), None).unwrap());
+	for sound in sounds.iter_mut() {
+		sound.play();
+	}
+	engine.render_frames(48000).unwrap()
+}
+
+
+/// the largest change of slope between consecutive samples, after
+/// the sines started
+fn max_kink (samples: &[f32]) -> f32 {
+	samples[1000..]
+		.windows(3)
+		.map(|x| (x[0] - 2.0 * x[1] + x[2]).abs())
+		.fold(0.0, f32::max)
+}
+
+
+#[test]
+fn limiter_ceiling () {
+	let ceiling = 10f32.powf(-1.0 / 20.0);
+
+	let engine = AudioEngine::new_offline(1, 48000);
+	engine.master().add_effect(Limiter::default());
+	let limited = loud_mix(&engine);
+	let peak = limited.iter().fold(0.0f32, |peak, x| peak.max(x.abs()));
+	assert!(peak <= ceiling + 1e-6, "peak is {}", peak);
+	// the limiter doesn't just lower the volume
+	assert!(peak > ceiling * 0.99);
+
+	// the limiter only changes the gain smoothly, clipping bends the
+	// waveform at every peak
+	let clipped = loud_mix(&AudioEngine::new_offline(1, 48000))
+		.into_iter()
+		.map(|x| x.clamp(-ceiling, ceiling))
+		.collect::<Vec<_>>();
+	let (limited, clipped) = (max_kink(&limited), max_kink(&clipped));
+	assert!(limited < 0.01, "largest kink of the limited mix is {}", limited);
+	assert!(clipped > 0.05, "largest kink of the clipped mix is {}", clipped);
+}
+
+
+#[test]
+fn limiter_lookahead () {
+	let engine = AudioEngine::new_offline(1, 48000);
+	let limiter = Limiter::new(-6.0, 10.0);
+	let ceiling = limiter.ceiling();
+	engine.master().add_effect(limiter);
+	let mut sound = engine.new_sound(Wave::new(0.0, 0.25), None).unwrap();
+	sound.play();
+
+	// quiet signals are only delayed
+	let delay = (Limiter::LOOKAHEAD * 48.0) as usize;
+	let output = engine.render_frames(1000).unwrap();
+	assert!(output[..delay].iter().all(|&x| x == 0.0));
+	assert!(output[delay..].iter().all(|&x| x == 0.25));
+
+	ceiling.set(-20.0);
+	let output = engine.render_frames(4800).unwrap();
+	assert!((output[4799] - 0.1).abs() < 1e-6);
+}
+
+
+#[test]
+fn compressor () {
+	let engine = AudioEngine::new_offline(1, 48000);
+	engine.master().add_effect(Compressor::new(-20.0, 4.0, 1.0, 50.0));
+	let mut loud = engine.new_sound(Wave::new(0.0, 0.5), None).unwrap();
+	loud.play();
+
+	// 14dB above the threshold become 3.5dB
+	let output = engine.render_frames(4800).unwrap();
+	let expected = 10f32.powf((-20.0 + (20.0 * 0.5f32.log10() + 20.0) / 4.0) / 20.0);
+	assert!((output[4799] - expected).abs() < 1e-4, "{} != {}", output[4799], expected);
+
+	// quiet sounds are left alone
+	loud.stop();
+	let mut quiet = engine.new_sound(Wave::new(0.0, 0.05), None).unwrap();
+	quiet.play();
+	let output = engine.render_frames(48000).unwrap();
+	assert!((output[47999] - 0.05).abs() < 1e-4);
+}
@@ -0,0 +1,176 @@
+//! Events sent by the mixer when sounds end, loop, reach markers or fail.
+
+mod common;
+
+use audio_engine::{ AudioEngine, Event, SoundSource, SourceError, WavDecoder };
+use common::frames;
+
+use std::io::Cursor;
+use std::sync::mpsc::{ channel, RecvTimeoutError };
+use std::time::Duration;
+
+
+
+/// a mono source of `len` frames, that fails after `fail_at` frames
+struct Source {
+	len: usize,
+	fail_at: Option<usize>,
+	pos: usize,
+	error: Option<SourceError>
+}
+
+impl Source {
+	fn new (len: usize) -> Self {
+		Self { len, fail_at: None, pos: 0, error: None }
+	}
+}
+
+impl SoundSource for Source {
+
+	fn channels (&self) -> u16 {
+		1
+	}
+
+	fn sample_rate (&self) -> u32 {
+		48000
+	}
+
+	fn reset (&mut self) {
+		self.pos = 0;
+	}
+
+	fn write_samples (&mut self, buffer: &mut [f32]) -> usize {
+		let end = self.fail_at.unwrap_or(self.len).min(self.len);
+		let len = buffer.len().min(end - self.pos);
+		buffer[..len].fill(0.5);
+		self.pos += len;
+		if len < buffer.len() && self.fail_at.is_some() {
+			self.error = Some(SourceError::Malformed);
+		}
+		len
+	}
+
+	fn take_error (&mut self) -> Option<SourceError> {
+		self.error.take()
+	}
+
+}
+
+
+
+fn events (engine: &AudioEngine) -> Vec<Event> {
+	std::iter::from_fn(|| engine.poll_event()).collect()
+}
+
+
+
+#[test]
+fn finished () {
+	let engine = AudioEngine::new_offline(1, 48000);
+	let mut sound = engine.new_sound(Source::new(10), None).unwrap();
+
+	sound.play();
+	engine.render_frames(8).unwrap();
+	assert_eq!(events(&engine), []);
+	engine.render_frames(8).unwrap();
+	assert_eq!(events(&engine), [Event::Finished(sound.id())]);
+
+	// stopping is not finishing
+	sound.play();
+	engine.render_frames(4).unwrap();
+	sound.stop();
+	engine.render_frames(20).unwrap();
+	assert_eq!(events(&engine), []);
+}
+
+
+#[test]
+fn looped_and_markers () {
+	let engine = AudioEngine::new_offline(1, 48000);
+	let mut sound = engine.new_sound(Source::new(10), None).unwrap();
+
+	sound.add_marker(frames(2));
+	sound.add_marker(frames(8));
+	sound.set_loop_region(0, Some(6));
+	sound.set_loop(true);
+	sound.play();
+	engine.render_frames(4).unwrap();
+	assert_eq!(events(&engine), [Event::Marker(sound.id(), frames(2))]);
+
+	// the marker at 8 is after the loop, so it is never reached
+	engine.render_frames(4).unwrap();
+	assert_eq!(events(&engine), [Event::Looped(sound.id())]);
+	// the sound goes back to the start as soon as it plays the last
+	// frame of the loop
+	engine.render_frames(4).unwrap();
+	assert_eq!(events(&engine), [Event::Looped(sound.id()), Event::Marker(sound.id(), frames(2))]);
+}
+
+
+#[test]
+fn decoder_error () {
+	let engine = AudioEngine::new_offline(1, 48000);
+	let mut source = Source::new(100);
+	source.fail_at = Some(5);
+	let mut sound = engine.new_sound(source, None).unwrap();
+
+	// the error stops the sound, even if it loops
+	sound.set_loop(true);
+	sound.play();
+	engine.render_frames(8).unwrap();
+	assert_eq!(events(&engine), [Event::DecoderError(sound.id(), SourceError::Malformed)]);
+	assert_eq!(sound.error(), Some(SourceError::Malformed));
+	assert!(!sound.is_playing());
+
+	// and is cleared when it plays again
+	sound.play();
+	engine.render_frames(2).unwrap();
+	assert_eq!(sound.error(), None);
+}
+
+
+#[test]
+fn callback () {
+	let engine = AudioEngine::new_offline(1, 48000);
+	let mut sound = engine.new_sound(Source::new(4), None).unwrap();
+
+	let (sender, receiver) = channel();
+	engine.set_event_callback(move |event| sender.send(event).unwrap()).unwrap();
+	assert!(engine.set_event_callback(|_| {}).is_err());
+
+	sound.play();
+	engine.render_frames(8).unwrap();
+	assert_eq!(receiver.recv_timeout(Duration::from_secs(5)), Ok(Event::Finished(sound.id())));
+	assert_eq!(engine.poll_event(), None);
+
+	// the thread ends with the engine, dropping the callback
+	drop(engine);
+	assert_eq!(receiver.recv_timeout(Duration::from_secs(5)), Err(RecvTimeoutError::Disconnected));
+}
+
+
+#[test]
+fn truncated_wav () {
+	let spec = hound::WavSpec {
+		channels: 1,
+		sample_rate: 48000,
+		bits_per_sample: 16,
+		sample_format: hound::SampleFormat::Int
+	};
+	let mut data = Cursor::new(Vec::new());
+	let mut writer = hound::WavWriter::new(&mut data, spec).unwrap();
+	for _ in 0..100 {
+		writer.write_sample(1000i16).unwrap();
+	}
+	writer.finalize().unwrap();
+	// cut the file in the middle of the samples
+	let mut data = data.into_inner();
+	data.truncate(data.len() - 100);
+
+	let engine = AudioEngine::new_offline(1, 48000);
+	let source = WavDecoder::new(Cursor::new(data)).unwrap();
+	let mut sound = engine.new_sound(source, None).unwrap();
+	sound.play();
+	engine.render_frames(200).unwrap();
+	assert_eq!(events(&engine), [Event::DecoderError(sound.id(), SourceError::Io)]);
+}
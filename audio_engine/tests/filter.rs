@@ -0,0 +1,112 @@
+//! Check the response of the biquad filters on pure tones.
+
+use audio_engine::{ AudioEngine, Biquad, SoundSource };
+
+use std::f32::consts::TAU;
+
+
+
+/// a mono sine wave at a given frequency
+struct Tone {
+	frequency: f32,
+	pos: usize
+}
+
+impl Tone {
+	fn new (frequency: f32) -> Self {
+		Self { frequency, pos: 0 }
+	}
+}
+
+impl SoundSource for Tone {
+
+	fn channels (&self) -> u16 {
+		1
+	}
+
+	fn sample_rate (&self) -> u32 {
+		48000
+	}
+
+	fn reset (&mut self) {
+		self.pos = 0;
+	}
+
+	fn write_samples (&mut self, buffer: &mut [f32]) -> usize {
+		for (i, b) in buffer.iter_mut().enumerate() {
+			*b = (TAU * self.frequency * (self.pos + i) as f32 / 48000.0).sin();
+		}
+		self.pos += buffer.len();
+		buffer.len()
+	}
+
+}
+
+
+
+/// the rms level, in dB, of the rendered samples after the filter settled
+fn level (samples: &[f32]) -> f32 {
+	let samples = &samples[samples.len() / 2..];
+	let energy = samples.iter().map(|x| x * x).sum::<f32>() / samples.len() as f32;
+	// relative to a full scale sine
+	10.0 * (energy * 2.0).log10()
+}
+
+
+/// the level of a tone of `frequency` through `filter`
+fn response (filter: Biquad, frequency: f32) -> f32 {
+	let engine = AudioEngine::new_offline(1, 48000);
+	let mut sound = engine.new_sound(Tone::new(frequency), None).unwrap();
+	sound.add_effect(filter);
+	sound.play();
+	level(&engine.render_frames(9600).unwrap())
+}
+
+
+#[test]
+fn pass_filters () {
+	assert!(response(Biquad::low_pass(1000.0, 0.7), 100.0).abs() < 0.1);
+	assert!(response(Biquad::low_pass(1000.0, 0.7), 10000.0) < -35.0);
+
+	assert!(response(Biquad::high_pass(1000.0, 0.7), 10000.0).abs() < 0.1);
+	assert!(response(Biquad::high_pass(1000.0, 0.7), 100.0) < -35.0);
+
+	// -3dB at the cutoff with a butterworth q
+	let cutoff = response(Biquad::low_pass(1000.0, std::f32::consts::FRAC_1_SQRT_2), 1000.0);
+	assert!((cutoff + 3.0).abs() < 0.1, "{}", cutoff);
+}
+
+
+#[test]
+fn band_filters () {
+	assert!(response(Biquad::band_pass(1000.0, 2.0), 1000.0).abs() < 0.1);
+	assert!(response(Biquad::band_pass(1000.0, 2.0), 10000.0) < -20.0);
+
+	assert!(response(Biquad::notch(1000.0, 2.0), 1000.0) < -40.0);
+	assert!(response(Biquad::notch(1000.0, 2.0), 10000.0).abs() < 0.1);
+}
+
+
+#[test]
+fn shelf_filters () {
+	assert!((response(Biquad::low_shelf(500.0, -12.0), 50.0) + 12.0).abs() < 0.2);
+	assert!(response(Biquad::low_shelf(500.0, -12.0), 10000.0).abs() < 0.2);
+
+	assert!((response(Biquad::high_shelf(2000.0, 6.0), 15000.0) - 6.0).abs() < 0.2);
+	assert!(response(Biquad::high_shelf(2000.0, 6.0), 100.0).abs() < 0.2);
+}
+
+
+#[test]
+fn automation () {
+	let engine = AudioEngine::new_offline(1, 48000);
+	let mut sound = engine.new_sound(Tone::new(5000.0), None).unwrap();
+	let filter = Biquad::low_pass(20000.0, 0.7);
+	let cutoff = filter.cutoff();
+	sound.add_effect(filter);
+	sound.play();
+	assert!(level(&engine.render_frames(4800).unwrap()).abs() < 0.5);
+
+	cutoff.set(300.0);
+	assert!(level(&engine.render_frames(4800).unwrap()) < -40.0);
+}
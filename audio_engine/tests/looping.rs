@@ -0,0 +1,100 @@
+//! Loop regions, set on the sound or read from the files.
+
+mod common;
+
+use audio_engine::{ AudioEngine, SoundSource };
+use common::vorbis::{ ogg, Stream };
+use common::{ steps, wav_with_chunks, write, STEP };
+
+
+
+#[test]
+fn intro_and_loop () {
+	let engine = AudioEngine::new_offline(1, 48000);
+	let mut sound = engine.new_sound(wav_with_chunks(10, 48000, &[]), None).unwrap();
+
+	sound.set_loop_region(4, Some(8));
+	sound.set_loop(true);
+	sound.play();
+	let output = engine.render_frames(14).unwrap();
+	assert_eq!(output, steps(&[1, 2, 3, 4, 5, 6, 7, 8, 5, 6, 7, 8, 5, 6]));
+
+	// without an end, the loop goes to the end of the sound
+	sound.stop();
+	sound.set_loop_region(6, None);
+	sound.play();
+	let output = engine.render_frames(14).unwrap();
+	assert_eq!(output, steps(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 7, 8, 9, 10]));
+}
+
+
+#[test]
+fn smpl_chunk () {
+	// a forward loop from frame 2 to frame 4, included
+	let mut smpl = vec![0; 9];
+	smpl[7] = 1;
+	smpl.extend_from_slice(&[0, 0, 2, 4, 0, 0]);
+	let engine = AudioEngine::new_offline(1, 48000);
+	let mut sound = engine.new_sound(wav_with_chunks(10, 48000, &[(b"smpl", smpl)]), None).unwrap();
+
+	sound.set_loop(true);
+	sound.play();
+	assert_eq!(engine.render_frames(9).unwrap(), steps(&[1, 2, 3, 4, 5, 3, 4, 5, 3]));
+}
+
+
+#[test]
+fn cue_points () {
+	// two cue points, at frames 6 and 3
+	let cue = vec![2, 1, 0, 0, 0, 0, 6, 2, 0, 0, 0, 0, 3];
+	let engine = AudioEngine::new_offline(1, 48000);
+	let mut sound = engine.new_sound(wav_with_chunks(10, 48000, &[(b"cue ", cue)]), None).unwrap();
+
+	sound.set_loop(true);
+	sound.play();
+	assert_eq!(engine.render_frames(9).unwrap(), steps(&[1, 2, 3, 4, 5, 6, 4, 5, 6]));
+}
+
+
+#[test]
+fn resampled_loop_has_no_gap () {
+	let engine = AudioEngine::new_offline(1, 48000);
+	let mut sound = engine.new_sound(wav_with_chunks(100, 24000, &[]), None).unwrap();
+
+	sound.set_loop_region(10, Some(18));
+	sound.set_loop(true);
+	sound.play();
+	let output = engine.render_frames(200).unwrap();
+
+	// the 8 frames of the loop take 16 frames of the output, and the
+	// interpolation goes straight from the end of the loop to its start
+	for i in 40..184 {
+		assert_eq!(output[i], output[i + 16], "frame {}", i);
+	}
+	let loop_end = (20..40).find(|&i| output[i] == 18.0 * STEP).unwrap();
+	assert_eq!(output[loop_end + 1], (18.0 + 11.0) / 2.0 * STEP);
+	assert_eq!(output[loop_end + 2], 11.0 * STEP);
+}
+
+
+#[test]
+fn ogg_loop_comments () {
+	let region = |comments: Vec<&'static str>| ogg(&[Stream { comments, ..Stream::default() }]).loop_region();
+	assert_eq!(region(vec!["LOOPSTART=1000", "LOOPLENGTH=2000"]), Some((1000, Some(3000))));
+	assert_eq!(region(vec!["loopstart=1000", "LoopEnd= 2500 "]), Some((1000, Some(2500))));
+	assert_eq!(region(vec!["LOOPSTART=1000"]), Some((1000, None)));
+	assert_eq!(region(vec!["LOOPSTART=soon", "LOOPEND=2500"]), None);
+	assert_eq!(region(vec!["LOOPEND=2500"]), None);
+
+	// the loop seeks back in the file
+	let mut source = ogg(&[Stream::default()]);
+	let expected = write(&mut source, 1 << 20);
+	let engine = AudioEngine::new_offline(1, 8000);
+	let mut sound = engine.new_sound(ogg(&[Stream { comments: vec!["LOOPSTART=1000", "LOOPLENGTH=1000"], ..Stream::default() }]), None).unwrap();
+	sound.set_loop(true);
+	sound.play();
+	let output = engine.render_frames(4000).unwrap();
+	assert_eq!(output[..2000], expected[..2000]);
+	assert_eq!(output[2000..3000], expected[1000..2000]);
+	assert_eq!(output[3000..], expected[1000..2000]);
+}
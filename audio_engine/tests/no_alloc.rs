@@ -0,0 +1,215 @@
+//! Check that mixing doesn't allocate or free memory, using a global
+//! allocator that counts the allocations and frees made by the current
+//! thread.
+
+use audio_engine::{ AudioEngine, FnEffect, ResampleQuality, SfxParams, SoundSource, Spatial };
+
+use std::alloc::{ GlobalAlloc, Layout, System };
+use std::cell::Cell;
+
+
+
+struct CountingAllocator;
+
+thread_local! {
+	static COUNTING: Cell<bool> = const { Cell::new(false) };
+	static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+fn count () {
+	if COUNTING.with(Cell::get) {
+		ALLOCATIONS.with(|x| x.set(x.get() + 1));
+	}
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+
+	unsafe fn alloc (&self, layout: Layout) -> *mut u8 {
+		count();
+		System.alloc(layout)
+	}
+
+	unsafe fn alloc_zeroed (&self, layout: Layout) -> *mut u8 {
+		count();
+		System.alloc_zeroed(layout)
+	}
+
+	unsafe fn realloc (&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+		count();
+		System.realloc(ptr, layout, new_size)
+	}
+
+	unsafe fn dealloc (&self, ptr: *mut u8, layout: Layout) {
+		count();
+		System.dealloc(ptr, layout)
+	}
+
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+
+
+/// run `f` and return how many allocations and frees it made in this
+/// thread
+fn allocations (f: impl FnOnce()) -> usize {
+	ALLOCATIONS.with(|x| x.set(0));
+	COUNTING.with(|x| x.set(true));
+	f();
+	COUNTING.with(|x| x.set(false));
+	ALLOCATIONS.with(Cell::get)
+}
+
+
+
+/// a sine wave with a given number of channels and sample rate
+struct Sine {
+	channels: u16,
+	sample_rate: u32,
+	len: usize,
+	pos: usize
+}
+
+impl SoundSource for Sine {
+
+	fn channels (&self) -> u16 {
+		self.channels
+	}
+
+	fn sample_rate (&self) -> u32 {
+		self.sample_rate
+	}
+
+	fn reset (&mut self) {
+		self.pos = 0;
+	}
+
+	fn write_samples (&mut self, buffer: &mut [f32]) -> usize {
+		let channels = self.channels as usize;
+		let frames = (buffer.len() / channels).min(self.len - self.pos);
+		for (i, frame) in buffer[..frames * channels].chunks_exact_mut(channels).enumerate() {
+			let t = (self.pos + i) as f32 / self.sample_rate as f32;
+			frame.fill((t * 440.0 * std::f32::consts::TAU).sin() * 0.25);
+		}
+		self.pos += frames;
+		frames * channels
+	}
+
+}
+
+
+
+fn render_without_allocating (engine: &AudioEngine) {
+	// buffer sizes below, at and above the mixer block size
+	let mut buffers = [17, 512, 4000]
+		.map(|frames| vec![0.0; frames * engine.channels() as usize]);
+
+	// the first render applies the pending commands
+	let count = allocations(|| engine.render(&mut buffers[0]).unwrap());
+	assert_eq!(count, 0, "applying the commands allocated");
+
+	for _ in 0..20 {
+		for buffer in buffers.iter_mut() {
+			let count = allocations(|| engine.render(buffer).unwrap());
+			assert_eq!(count, 0, "rendering {} samples allocated", buffer.len());
+		}
+	}
+}
+
+
+#[test]
+fn upmix_and_resample () {
+	let engine = AudioEngine::new_offline(2, 48000);
+
+	let mut a = engine.new_sound(Sine { channels: 1, sample_rate: 44100, len: 30000, pos: 0 }, None).unwrap();
+	let mut b = engine.new_sound(Sine { channels: 2, sample_rate: 48000, len: 1000, pos: 0 }, None).unwrap();
+	let sfx = engine.new_bus("sfx", None);
+	let mut c = engine.new_sound(Sine { channels: 1, sample_rate: 22050, len: 5000, pos: 0 }, Some(&sfx)).unwrap();
+	a.set_loop(true);
+	a.set_spatial(Some(Spatial { doppler: 1.0, ..Spatial::default() }));
+	a.set_location([3.0, 4.0]);
+	a.set_velocity([0.0, -20.0]);
+	b.set_loop(true);
+	b.add_effect(FnEffect::new(|x| x * 0.5));
+	c.set_volume(0.3);
+	sfx.set_volume(0.5);
+	a.play();
+	b.play();
+	c.play();
+
+	render_without_allocating(&engine);
+}
+
+
+#[test]
+fn downmix () {
+	let engine = AudioEngine::new_offline(1, 48000);
+
+	let mut a = engine.new_sound(Sine { channels: 2, sample_rate: 44100, len: 3000, pos: 0 }, None).unwrap();
+	let mut b = engine.new_sound(Sine { channels: 2, sample_rate: 48000, len: 100000, pos: 0 }, None).unwrap();
+	a.set_loop(true);
+	a.play();
+	b.play();
+
+	render_without_allocating(&engine);
+}
+
+
+#[test]
+fn commands () {
+	let engine = AudioEngine::new_offline(2, 48000);
+	let mut buffer = vec![0.0; 512 * 2];
+	let sine = |len| Sine { channels: 1, sample_rate: 44100, len, pos: 0 };
+
+	let mut a = engine.new_sound(sine(100000), None).unwrap();
+	a.set_loop(true);
+	a.play();
+	for _ in 0..20 {
+		// a sound that ends in the middle of the buffer and is dropped
+		let mut b = engine.new_sound(sine(100), None).unwrap();
+		b.add_effect(FnEffect::new(|x| x * 0.5));
+		b.play();
+		drop(b);
+		let mut c = engine.new_sound(sine(100000), None).unwrap();
+		c.add_effect(FnEffect::new(|x| x * 0.5));
+		c.play();
+		c.at(engine.time()).set_volume(0.5);
+		let count = allocations(|| engine.render(&mut buffer).unwrap());
+		assert_eq!(count, 0, "adding sounds allocated");
+
+		a.clear_effects();
+		c.clear_effects();
+		c.stop();
+		drop(c);
+		let bus = engine.new_bus("sfx", None);
+		bus.add_effect(FnEffect::new(|x| x * 0.5));
+		bus.clear_effects();
+		drop(bus);
+		engine.set_resample_quality(ResampleQuality::Linear);
+		let count = allocations(|| engine.render(&mut buffer).unwrap());
+		assert_eq!(count, 0, "removing sounds, effects and buses freed memory");
+	}
+}
+
+
+#[test]
+fn sfx () {
+	let engine = AudioEngine::new_offline(2, 48000);
+	let mut buffer = vec![0.0; 4000 * 2];
+	// both filters, which are reset every time the sound ends
+	let source = SfxParams::swoosh().source();
+	let renders = source.len_frames().unwrap() as usize / 3000 + 2;
+	let mut sound = engine.new_sound(source, None).unwrap();
+
+	for _ in 0..3 {
+		sound.play();
+		let count = allocations(|| {
+			for _ in 0..renders {
+				engine.render(&mut buffer).unwrap();
+			}
+		});
+		assert_eq!(count, 0, "playing a sound effect allocated");
+		assert!(!sound.is_playing());
+	}
+}
@@ -0,0 +1,417 @@
+mod common;
+
+use audio_engine::{ AudioEngine, Effect, FnEffect, Param, SoundSource };
+use common::{ frames, steps, STEP };
+
+use std::f32::consts::FRAC_1_SQRT_2;
+use std::sync::Arc;
+
+
+
+/// a mono source that outputs `1, 2, 3, ..., len` times [`STEP`]
+struct Ramp {
+	len: usize,
+	pos: usize
+}
+
+impl Ramp {
+	fn new (len: usize) -> Self {
+		Self { len, pos: 0 }
+	}
+}
+
+impl SoundSource for Ramp {
+
+	fn channels (&self) -> u16 {
+		1
+	}
+
+	fn sample_rate (&self) -> u32 {
+		48000
+	}
+
+	fn reset (&mut self) {
+		self.pos = 0;
+	}
+
+	fn write_samples (&mut self, buffer: &mut [f32]) -> usize {
+		let len = buffer.len().min(self.len - self.pos);
+		for (i, b) in buffer[..len].iter_mut().enumerate() {
+			*b = (self.pos + i + 1) as f32 * STEP;
+		}
+		self.pos += len;
+		len
+	}
+
+}
+
+
+
+/// a silent source that holds a clone of an `Arc` while it exists
+struct Token {
+	_token: Arc<()>
+}
+
+impl SoundSource for Token {
+
+	fn channels (&self) -> u16 {
+		1
+	}
+
+	fn sample_rate (&self) -> u32 {
+		48000
+	}
+
+	fn reset (&mut self) {}
+
+	fn write_samples (&mut self, buffer: &mut [f32]) -> usize {
+		buffer.fill(0.0);
+		buffer.len()
+	}
+
+}
+
+
+
+/// the stereo output of a mono sound, in steps, at the -3dB of the
+/// centered pan
+fn panned (values: &[i32]) -> Vec<f32> {
+	steps(values).into_iter().map(|x| x * FRAC_1_SQRT_2).collect()
+}
+
+
+
+#[test]
+fn silence_without_sounds () {
+	let engine = AudioEngine::new_offline(1, 48000);
+	assert_eq!(engine.render_frames(8).unwrap(), steps(&[0; 8]));
+}
+
+
+#[test]
+fn play_once () {
+	let engine = AudioEngine::new_offline(1, 48000);
+	let mut sound = engine.new_sound(Ramp::new(4), None).unwrap();
+
+	assert_eq!(engine.render_frames(2).unwrap(), steps(&[0, 0]));
+	sound.play();
+	assert_eq!(engine.render_frames(6).unwrap(), steps(&[1, 2, 3, 4, 0, 0]));
+	assert_eq!(engine.render_frames(2).unwrap(), steps(&[0, 0]));
+
+	// the sound was reset when it ended
+	sound.play();
+	assert_eq!(engine.render_frames(2).unwrap(), steps(&[1, 2]));
+}
+
+
+#[test]
+fn pause_and_stop () {
+	let engine = AudioEngine::new_offline(1, 48000);
+	let mut sound = engine.new_sound(Ramp::new(8), None).unwrap();
+
+	sound.play();
+	assert_eq!(engine.render_frames(3).unwrap(), steps(&[1, 2, 3]));
+	sound.pause();
+	assert_eq!(engine.render_frames(2).unwrap(), steps(&[0, 0]));
+	sound.play();
+	assert_eq!(engine.render_frames(2).unwrap(), steps(&[4, 5]));
+	sound.stop();
+	assert_eq!(engine.render_frames(2).unwrap(), steps(&[0, 0]));
+	sound.play();
+	assert_eq!(engine.render_frames(2).unwrap(), steps(&[1, 2]));
+}
+
+
+#[test]
+fn looping () {
+	let engine = AudioEngine::new_offline(1, 48000);
+	let mut sound = engine.new_sound(Ramp::new(3), None).unwrap();
+
+	sound.set_loop(true);
+	sound.play();
+	assert_eq!(engine.render_frames(8).unwrap(), steps(&[1, 2, 3, 1, 2, 3, 1, 2]));
+}
+
+
+#[test]
+fn effect_volume_and_upmix () {
+	let engine = AudioEngine::new_offline(2, 48000);
+	let mut sound = engine.new_sound(Ramp::new(4), None).unwrap();
+
+	sound.add_effect(FnEffect::new(|x| x * 4.0));
+	sound.set_volume(0.5);
+	sound.play();
+	assert_eq!(engine.render_frames(4).unwrap(), panned(&[2, 2, 4, 4, 6, 6, 8, 8]));
+}
+
+
+#[test]
+fn mix_two_sounds () {
+	let engine = AudioEngine::new_offline(1, 48000);
+	let mut a = engine.new_sound(Ramp::new(4), None).unwrap();
+	let mut b = engine.new_sound(Ramp::new(2), None).unwrap();
+
+	b.add_effect(FnEffect::new(|x| x * 10.0));
+	a.play();
+	b.play();
+	assert_eq!(engine.render_frames(4).unwrap(), steps(&[11, 22, 3, 4]));
+}
+
+
+#[test]
+fn render_to_wav () {
+	let engine = AudioEngine::new_offline(1, 48000);
+	let mut sound = engine.new_sound(Ramp::new(50), None).unwrap();
+	sound.set_loop(true);
+	sound.play();
+
+	let path = std::env::temp_dir().join("audio_engine_render_to_wav.wav");
+	engine.render_to_wav(&path, 6000).unwrap();
+
+	let mut reader = hound::WavReader::open(&path).unwrap();
+	let samples = reader.samples::<f32>().map(Result::unwrap).collect::<Vec<_>>();
+	std::fs::remove_file(&path).unwrap();
+
+	assert_eq!(samples.len(), 6000);
+	assert_eq!(samples[..3], steps(&[1, 2, 3]));
+	assert_eq!(samples[5998..], steps(&[49, 50]));
+}
+
+
+#[test]
+fn state_readback () {
+	let engine = AudioEngine::new_offline(2, 48000);
+	let mut sound = engine.new_sound(Ramp::new(6), None).unwrap();
+
+	sound.play();
+	assert!(!sound.is_playing());
+	engine.render_frames(4).unwrap();
+	assert!(sound.is_playing());
+	assert_eq!(sound.position(), 4);
+
+	engine.render_frames(4).unwrap();
+	assert!(!sound.is_playing());
+	assert_eq!(sound.position(), 0);
+}
+
+
+/// an effect that delays the first channel by one frame
+#[derive(Default)]
+struct DelayLeft {
+	last: f32
+}
+
+impl Effect for DelayLeft {
+
+	fn process (&mut self, buffer: &mut [f32], channels: u16, _: u32) {
+		for frame in buffer.chunks_exact_mut(channels as usize) {
+			self.last = std::mem::replace(&mut frame[0], self.last);
+		}
+	}
+
+	fn reset (&mut self) {
+		self.last = 0.0;
+	}
+
+}
+
+
+#[test]
+fn effect_chain () {
+	let engine = AudioEngine::new_offline(2, 48000);
+	let mut sound = engine.new_sound(Ramp::new(4), None).unwrap();
+
+	let gain = Param::new(2.0);
+	sound.add_effect(DelayLeft::default());
+	sound.add_effect({
+		let gain = gain.clone();
+		FnEffect::new(move |x| x * gain.get())
+	});
+	sound.play();
+	assert_eq!(engine.render_frames(2).unwrap(), panned(&[0, 2, 2, 4]));
+
+	gain.set(1.0);
+	assert_eq!(engine.render_frames(2).unwrap(), panned(&[2, 3, 3, 4]));
+
+	// the effect state is cleared with the sound
+	sound.stop();
+	sound.clear_effects();
+	sound.add_effect(DelayLeft::default());
+	sound.play();
+	assert_eq!(engine.render_frames(2).unwrap(), panned(&[0, 1, 1, 2]));
+}
+
+
+#[test]
+fn fade_to () {
+	let engine = AudioEngine::new_offline(1, 48000);
+	let mut sound = engine.new_sound(Ramp::new(1000), None).unwrap();
+
+	sound.play();
+	engine.render_frames(4).unwrap();
+	sound.fade_to(0.5, frames(4));
+	let output = engine.render_frames(6).unwrap();
+	let expected = [5.0 * 0.875, 6.0 * 0.75, 7.0 * 0.625, 8.0 * 0.5, 9.0 * 0.5, 10.0 * 0.5];
+	assert_eq!(output, expected.map(|x| x * STEP));
+
+	// pausing holds the fade
+	sound.fade_to(1.0, frames(4));
+	sound.pause();
+	engine.render_frames(4).unwrap();
+	sound.play();
+	assert_eq!(engine.render_frames(5).unwrap(), [11.0 * 0.625, 12.0 * 0.75, 13.0 * 0.875, 14.0, 15.0].map(|x| x * STEP));
+}
+
+
+#[test]
+fn fade_in_and_out () {
+	let engine = AudioEngine::new_offline(1, 48000);
+	let mut sound = engine.new_sound(Ramp::new(1000), None).unwrap();
+
+	sound.set_volume(0.5);
+	sound.fade_in(frames(4));
+	assert_eq!(engine.render_frames(5).unwrap(), [0.125, 0.5, 1.125, 2.0, 2.5].map(|x| x * STEP));
+
+	sound.stop_with_fade(frames(4));
+	assert_eq!(engine.render_frames(5).unwrap(), [2.25, 1.75, 1.0, 0.0, 0.0].map(|x| x * STEP));
+	assert!(!sound.is_playing());
+
+	// the sound was stopped, and keeps its volume
+	sound.play();
+	assert_eq!(engine.render_frames(2).unwrap(), steps(&[1, 2]).iter().map(|x| x * 0.5).collect::<Vec<_>>());
+}
+
+
+#[test]
+fn crossfade () {
+	let engine = AudioEngine::new_offline(1, 48000);
+	let mut a = engine.new_sound(Ramp::new(1000), None).unwrap();
+	let mut b = engine.new_sound(Ramp::new(1000), None).unwrap();
+
+	a.play();
+	engine.render_frames(3).unwrap();
+	engine.crossfade(&mut a, &mut b, frames(4));
+	// a plays 4, 5, 6, 7 while b plays 1, 2, 3, 4
+	assert_eq!(engine.render_frames(5).unwrap(), [3.25, 3.5, 3.75, 4.0, 5.0].map(|x| x * STEP));
+	assert!(!a.is_playing());
+}
+
+
+#[test]
+fn scheduled_commands () {
+	let engine = AudioEngine::new_offline(1, 48000);
+	let mut sound = engine.new_sound(Ramp::new(1000), None).unwrap();
+
+	sound.play_at(frames(3));
+	sound.at(frames(5)).set_volume(0.5);
+	sound.stop_at(frames(7));
+	assert_eq!(engine.render_frames(4).unwrap(), steps(&[0, 0, 0, 1]));
+	assert_eq!(engine.render_frames(4).unwrap(), [2.0, 1.5, 2.0, 0.0].map(|x| x * STEP));
+	assert!(!sound.is_playing());
+
+	assert_eq!(engine.clock(), 8);
+	assert_eq!(engine.time(), frames(8));
+
+	// a time in the past is applied on the next buffer
+	sound.play_at(frames(2));
+	assert_eq!(engine.render_frames(2).unwrap(), [0.5, 1.0].map(|x| x * STEP));
+}
+
+
+#[test]
+fn scheduled_sound_outlives_its_handle () {
+	let engine = AudioEngine::new_offline(1, 48000);
+	let sound = engine.new_sound(Ramp::new(2), None).unwrap();
+
+	sound.at(frames(1)).play();
+	drop(sound);
+	assert_eq!(engine.render_frames(4).unwrap(), steps(&[0, 1, 2, 0]));
+}
+
+
+#[test]
+fn full_schedule () {
+	let engine = AudioEngine::new_offline(1, 48000);
+	let sound = engine.new_sound(Ramp::new(4), None).unwrap();
+
+	for _ in 0..64 {
+		sound.at(frames(100)).set_volume(1.0);
+	}
+	// the commands over the capacity are dropped
+	sound.at(frames(1)).play();
+	assert_eq!(engine.render_frames(4).unwrap(), steps(&[0, 0, 0, 0]));
+}
+
+
+#[test]
+fn dropped_sound_paused_later () {
+	let engine = AudioEngine::new_offline(1, 48000);
+	let token = Arc::new(());
+	let mut paused = engine.new_sound(Token { _token: token.clone() }, None).unwrap();
+	let mut stopped = engine.new_sound(Token { _token: token.clone() }, None).unwrap();
+
+	paused.play();
+	paused.at(frames(2)).pause();
+	stopped.play();
+	stopped.at(frames(4)).stop();
+	drop(paused);
+	drop(stopped);
+	engine.render_frames(8).unwrap();
+
+	// the mixer sends the sounds back to be dropped by the next poll
+	assert_eq!(engine.poll_event(), None);
+	assert_eq!(Arc::strong_count(&token), 1);
+}
+
+
+#[test]
+fn speed () {
+	let engine = AudioEngine::new_offline(1, 48000);
+	let mut sound = engine.new_sound(Ramp::new(1000), None).unwrap();
+
+	sound.set_speed(2.0);
+	sound.play();
+	assert_eq!(engine.render_frames(4).unwrap(), steps(&[1, 3, 5, 7]));
+	assert_eq!(sound.position(), 8);
+
+	sound.stop();
+	sound.set_speed(0.5);
+	sound.play();
+	assert_eq!(engine.render_frames(4).unwrap(), [1.0, 1.5, 2.0, 2.5].map(|x| x * STEP));
+}
+
+
+#[test]
+fn speed_changes_smoothly () {
+	let engine = AudioEngine::new_offline(1, 48000);
+	let mut sound = engine.new_sound(Ramp::new(1000), None).unwrap();
+
+	sound.play();
+	engine.render_frames(10).unwrap();
+	sound.set_speed(2.0);
+	let output = engine.render_frames(101).unwrap();
+
+	// the distance between two frames goes from 1 to 2 steps
+	let deltas = output.windows(2).map(|x| (x[1] - x[0]) / STEP).collect::<Vec<_>>();
+	assert!((deltas[0] - 1.0).abs() < 0.05, "{}", deltas[0]);
+	assert!((deltas[99] - 2.0).abs() < 0.05, "{}", deltas[99]);
+	assert!(deltas.windows(2).all(|x| x[1] >= x[0] - 1e-3));
+}
+
+
+#[test]
+fn full_command_queue () {
+	let engine = AudioEngine::new_offline(1, 48000);
+	let mut sound = engine.new_sound(Ramp::new(4), None).unwrap();
+	for _ in 1..1024 {
+		sound.set_volume(1.0);
+	}
+
+	// the commands over the capacity are dropped
+	assert!(engine.new_sound(Ramp::new(4), None).is_err());
+	sound.play();
+	assert_eq!(engine.render_frames(2).unwrap(), steps(&[0, 0]));
+
+	sound.play();
+	assert_eq!(engine.render_frames(2).unwrap(), steps(&[1, 2]));
+}
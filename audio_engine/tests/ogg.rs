@@ -0,0 +1,126 @@
+//! Ogg vorbis files decoded by the ogg decoder.
+
+mod common;
+
+use audio_engine::{ SoundSource, SourceError };
+use common::vorbis::{ ogg, Stream };
+use common::{ channel, write };
+
+
+
+#[test]
+fn multichannel () {
+	let mut source = ogg(&[Stream { channels: 3, silent: vec![1], ..Stream::default() }]);
+	assert_eq!(source.channels(), 3);
+	assert_eq!(source.sample_rate(), 8000);
+
+	let output = write(&mut source, 1 << 20);
+	assert_eq!(output.len(), 40 * 128 * 3);
+	assert_eq!(source.take_error(), None);
+
+	// the channels are interleaved in order
+	let left = channel(&output, 3, 0);
+	assert!(left.iter().any(|&x| x != 0.0));
+	assert!(channel(&output, 3, 1).iter().all(|&x| x == 0.0));
+	let right = channel(&output, 3, 2);
+	assert!(right.iter().any(|&x| x != 0.0));
+	assert_ne!(left, right);
+}
+
+
+#[test]
+fn reset () {
+	let mut source = ogg(&[Stream { channels: 2, comments: vec!["TITLE=test"], ..Stream::default() }]);
+	assert_eq!(source.comments(), [("TITLE".to_string(), "test".to_string())]);
+	let output = write(&mut source, 1 << 20);
+	assert_eq!(output.len(), 40 * 128 * 2);
+	assert!(write(&mut source, 1 << 20).is_empty());
+
+	source.reset();
+	assert_eq!(write(&mut source, 1 << 20), output);
+
+	// from the middle of the file too
+	source.reset();
+	assert_eq!(write(&mut source, 1000), output[..1000]);
+	source.reset();
+	assert_eq!(write(&mut source, 1 << 20), output);
+}
+
+
+#[test]
+fn chained_streams () {
+	let stereo = Stream { channels: 2, frames: 20 * 128, ..Stream::default() };
+	let mono = Stream { frames: 10 * 128, ..Stream::default() };
+	let mut source = ogg(&[stereo, mono]);
+	assert_eq!(source.channels(), 2);
+
+	let output = write(&mut source, 1 << 20);
+	assert_eq!(output.len(), 30 * 128 * 2);
+	assert_eq!(source.take_error(), None);
+
+	// the mono stream plays on both channels
+	let (first, second) = output.split_at(20 * 128 * 2);
+	assert_ne!(channel(first, 2, 0), channel(first, 2, 1));
+	assert!(second.iter().any(|&x| x != 0.0));
+	assert_eq!(channel(second, 2, 0), channel(second, 2, 1));
+
+	source.reset();
+	assert_eq!(write(&mut source, 1 << 20), output);
+}
+
+
+#[test]
+fn chained_sample_rate () {
+	let first = Stream { frames: 20 * 128, ..Stream::default() };
+	let second = Stream { sample_rate: 16000, ..Stream::default() };
+	let mut source = ogg(&[first, second]);
+
+	// the second stream ends the file
+	assert_eq!(write(&mut source, 1 << 20).len(), 20 * 128);
+	assert_eq!(source.take_error(), Some(SourceError::Unsupported));
+}
+
+
+#[test]
+fn seek () {
+	let mut source = ogg(&[Stream { channels: 2, frames: 100 * 128, ..Stream::default() }]);
+	let output = write(&mut source, 1 << 20);
+	let len = 100 * 128;
+	let from = |frame: u64| &output[frame as usize * 2..(frame as usize * 2 + 600).min(output.len())];
+
+	// backward, to the start of pages, packets and anywhere else
+	for frame in [0, 1, 127, 128, 512, 513, 3000, 4999, 8000, 12799, 100, 0] {
+		source.seek(frame).unwrap();
+		assert_eq!(source.position(), Some(frame));
+		assert_eq!(write(&mut source, 600), from(frame), "frame {}", frame);
+	}
+
+	// forward, by decoding or by a page seek over a second
+	for frame in [500, 2000, 11000] {
+		source.seek(frame).unwrap();
+		assert_eq!(write(&mut source, 600), from(frame), "frame {}", frame);
+	}
+
+	// after the end
+	source.seek(len + 1000).unwrap();
+	assert!(write(&mut source, 600).is_empty());
+	source.seek(len - 10).unwrap();
+	assert_eq!(write(&mut source, 600), from(len - 10));
+	assert_eq!(source.take_error(), None);
+}
+
+
+#[test]
+fn seek_chained_streams () {
+	let first = Stream { frames: 20 * 128, ..Stream::default() };
+	let second = Stream { frames: 20 * 128, ..Stream::default() };
+	let mut source = ogg(&[first, second]);
+	let output = write(&mut source, 1 << 20);
+
+	// the second stream was reached, so the seeks decode from the start
+	for frame in [3000, 100, 4000] {
+		source.seek(frame).unwrap();
+		assert_eq!(write(&mut source, 600), output[frame as usize..][..600], "frame {}", frame);
+	}
+	assert_eq!(source.take_error(), None);
+}
@@ -0,0 +1,105 @@
+//! Oscillators, noise and silence generated by the engine.
+
+mod common;
+
+use audio_engine::{ AudioEngine, Noise, Oscillator, Silence, SoundSource };
+use common::write;
+
+use std::f32::consts::TAU;
+use std::time::Duration;
+
+
+
+fn mean (samples: &[f32]) -> f32 {
+	samples.iter().sum::<f32>() / samples.len() as f32
+}
+
+
+
+#[test]
+fn sine () {
+	let mut sine = Oscillator::sine(1000.0);
+	let output = write(&mut sine, 96);
+	for (i, x) in output.iter().enumerate() {
+		assert!((x - (TAU * i as f32 / 48.0).sin()).abs() < 1e-5);
+	}
+
+	// the frequency changes on the next write, without a jump
+	sine.frequency().set(2000.0);
+	let output = write(&mut sine, 24);
+	for (i, x) in output.iter().enumerate() {
+		assert!((x - (TAU * i as f32 / 24.0).sin()).abs() < 1e-5);
+	}
+}
+
+
+#[test]
+fn waveforms () {
+	// a low note, so the band limiting only touches a few samples
+	let square = write(&mut Oscillator::square(100.0, 0.25), 4800);
+	assert!((mean(&square) + 0.5).abs() < 0.01);
+	assert_eq!(square[100], 1.0);
+	assert_eq!(square[200], -1.0);
+
+	let saw = write(&mut Oscillator::saw(100.0), 4800);
+	assert!(mean(&saw).abs() < 0.01);
+	assert!((saw[240] - 0.0).abs() < 0.01);
+
+	let triangle = write(&mut Oscillator::triangle(100.0), 4800);
+	assert!(mean(&triangle).abs() < 0.01);
+	assert!((triangle[120] - 1.0).abs() < 0.01);
+	assert!((triangle[360] + 1.0).abs() < 0.01);
+}
+
+
+#[test]
+fn amplitude_is_ramped () {
+	let mut square = Oscillator::square(10.0, 1.0).with_amplitude(0.5);
+	assert_eq!(write(&mut square, 4)[1..], [0.5; 3]);
+
+	square.amplitude().set(1.0);
+	assert_eq!(write(&mut square, 4)[1..], [0.75, 0.875, 1.0]);
+	assert_eq!(write(&mut square, 4), [1.0; 4]);
+}
+
+
+#[test]
+fn duration () {
+	let engine = AudioEngine::new_offline(1, 48000);
+	let mut tone = engine.new_sound(Oscillator::sine(440.0).with_duration(Duration::from_millis(10)), None).unwrap();
+	let mut silence = engine.new_sound(Silence::new(Duration::from_millis(20)), None).unwrap();
+	assert_eq!(tone.duration(), Some(Duration::from_millis(10)));
+
+	tone.play();
+	silence.play();
+	let output = engine.render_frames(720).unwrap();
+	assert!(output[..480].iter().any(|&x| x != 0.0));
+	assert!(output[480..].iter().all(|&x| x == 0.0));
+	assert!(!tone.is_playing());
+	assert!(silence.is_playing());
+
+	// a source ends on the first write that is short
+	engine.render_frames(241).unwrap();
+	assert!(!silence.is_playing());
+}
+
+
+#[test]
+fn noise () {
+	let mut white = Noise::white();
+	let output = write(&mut white, 4800);
+	assert!(output.iter().all(|x| x.abs() <= 1.0));
+	assert!(mean(&output).abs() < 0.05);
+
+	// the same noise after a reset, another one with another seed
+	white.reset();
+	assert_eq!(write(&mut white, 4800), output);
+	assert_ne!(write(&mut Noise::white().with_seed(7), 4800), output);
+
+	// pink noise has less high frequencies, so it changes less from
+	// one sample to the next
+	let pink = write(&mut Noise::pink(), 4800);
+	assert!(pink.iter().all(|x| x.abs() <= 1.0));
+	let roughness = |x: &[f32]| x.windows(2).map(|w| (w[1] - w[0]).abs()).sum::<f32>() / x.iter().map(|x| x.abs()).sum::<f32>();
+	assert!(roughness(&pink) < roughness(&output) / 2.0);
+}
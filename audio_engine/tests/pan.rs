@@ -0,0 +1,111 @@
+//! Check the pan law and the stereo width of sounds.
+
+use audio_engine::{ AudioEngine, SoundSource };
+
+
+
+/// a source that repeats a single frame forever
+struct Constant {
+	frame: Vec<f32>
+}
+
+impl SoundSource for Constant {
+
+	fn channels (&self) -> u16 {
+		self.frame.len() as u16
+	}
+
+	fn sample_rate (&self) -> u32 {
+		48000
+	}
+
+	fn reset (&mut self) {}
+
+	fn write_samples (&mut self, buffer: &mut [f32]) -> usize {
+		for frame in buffer.chunks_exact_mut(self.frame.len()) {
+			frame.copy_from_slice(&self.frame);
+		}
+		buffer.len()
+	}
+
+}
+
+
+
+/// the last frame of a few blocks of output, once the pan settled
+fn last_frame (engine: &AudioEngine) -> Vec<f32> {
+	let output = engine.render_frames(2048).unwrap();
+	output[output.len() - engine.channels() as usize..].to_vec()
+}
+
+
+fn assert_close (a: &[f32], b: &[f32]) {
+	assert!(a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-6), "{:?} != {:?}", a, b);
+}
+
+
+#[test]
+fn constant_power () {
+	let engine = AudioEngine::new_offline(2, 48000);
+	let mut sound = engine.new_sound(Constant { frame: vec![0.5] }, None).unwrap();
+	sound.play();
+	let h = std::f32::consts::FRAC_1_SQRT_2;
+	assert_close(&last_frame(&engine), &[0.5 * h, 0.5 * h]);
+
+	// panned to one side, the sound is as loud as the source
+	sound.set_pan(-1.0);
+	assert_close(&last_frame(&engine), &[0.5, 0.0]);
+	sound.set_pan(1.0);
+	assert_close(&last_frame(&engine), &[0.0, 0.5]);
+
+	for pan in [-0.8, -0.3, 0.1, 0.6] {
+		sound.set_pan(pan);
+		let frame = last_frame(&engine);
+		assert!((frame[0].powi(2) + frame[1].powi(2) - 0.25).abs() < 1e-6);
+		assert_eq!(frame[0] > frame[1], pan < 0.0);
+	}
+}
+
+
+#[test]
+fn smooth_changes () {
+	let engine = AudioEngine::new_offline(2, 48000);
+	let mut sound = engine.new_sound(Constant { frame: vec![0.5] }, None).unwrap();
+	sound.play();
+	engine.render_frames(512).unwrap();
+
+	sound.set_pan(1.0);
+	let output = engine.render_frames(512).unwrap();
+	let left = output.iter().step_by(2).collect::<Vec<_>>();
+	assert!(left.windows(2).all(|x| x[1] <= x[0] && x[0] - x[1] < 0.01));
+	assert!(left[511].abs() < 1e-6);
+}
+
+
+#[test]
+fn stereo_balance_and_width () {
+	let engine = AudioEngine::new_offline(2, 48000);
+	let mut sound = engine.new_sound(Constant { frame: vec![0.25, 0.5] }, None).unwrap();
+	sound.play();
+	assert_eq!(last_frame(&engine), [0.25, 0.5]);
+
+	// the right channel moves to the left speaker
+	sound.set_pan(-1.0);
+	assert_close(&last_frame(&engine), &[0.75, 0.0]);
+	sound.set_pan(0.0);
+
+	sound.set_width(0.0);
+	assert_eq!(last_frame(&engine), [0.375, 0.375]);
+	sound.set_width(2.0);
+	assert_eq!(last_frame(&engine), [0.125, 0.625]);
+}
+
+
+#[test]
+fn surround () {
+	let engine = AudioEngine::new_offline(6, 48000);
+	let mut sound = engine.new_sound(Constant { frame: vec![0.5] }, None).unwrap();
+	sound.set_pan(1.0);
+	sound.play();
+	assert_close(&last_frame(&engine), &[0.0, 0.5, 0.0, 0.0, 0.0, 0.0]);
+}
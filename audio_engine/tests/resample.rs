@@ -0,0 +1,109 @@
+//! Measure the quality of the resamplers on signals with a known
+//! analytic form.
+
+use audio_engine::{ AudioEngine, ResampleQuality, SoundSource };
+
+use std::f64::consts::TAU;
+
+
+
+/// a mono signal given by a function of time, in seconds
+struct Signal <F: Fn(f64) -> f64> {
+	f: F,
+	sample_rate: u32,
+	pos: usize
+}
+
+impl <F: Fn(f64) -> f64> SoundSource for Signal<F> {
+
+	fn channels (&self) -> u16 {
+		1
+	}
+
+	fn sample_rate (&self) -> u32 {
+		self.sample_rate
+	}
+
+	fn reset (&mut self) {
+		self.pos = 0;
+	}
+
+	fn write_samples (&mut self, buffer: &mut [f32]) -> usize {
+		for (i, b) in buffer.iter_mut().enumerate() {
+			*b = (self.f)((self.pos + i) as f64 / self.sample_rate as f64) as f32;
+		}
+		self.pos += buffer.len();
+		buffer.len()
+	}
+
+}
+
+
+/// an exponential sine sweep from 20Hz to 16kHz in one second
+fn sweep (t: f64) -> f64 {
+	let (f0, f1) = (20.0f64, 16000.0f64);
+	let k = (f1 / f0).ln();
+	0.5 * (TAU * f0 / k * ((t * k).exp() - 1.0)).sin()
+}
+
+
+fn decibels (energy: f64, reference: f64) -> f64 {
+	10.0 * (energy / reference).log10()
+}
+
+
+/// render `source` with the given quality, at `sample_rate`
+fn render (source: impl SoundSource + Send + 'static, sample_rate: u32, quality: ResampleQuality, frames: usize) -> Vec<f32> {
+	let engine = AudioEngine::new_offline(1, sample_rate);
+	engine.set_resample_quality(quality);
+	let mut sound = engine.new_sound(source, None).unwrap();
+	sound.play();
+	engine.render_frames(frames).unwrap()
+}
+
+
+/// the energy of the difference between a 44.1kHz sweep resampled to
+/// 48kHz and the sweep sampled at 48kHz directly, relative to the
+/// energy of the sweep
+fn sweep_error (quality: ResampleQuality) -> f64 {
+	let output = render(Signal { f: sweep, sample_rate: 44100, pos: 0 }, 48000, quality, 48000);
+
+	let (mut error, mut energy) = (0.0, 0.0);
+	// skip the edges, where the sweep starts and ends abruptly
+	for (n, &y) in output.iter().enumerate().take(46000).skip(2000) {
+		let x = sweep(n as f64 / 48000.0);
+		error += (y as f64 - x).powi(2);
+		energy += x.powi(2);
+	}
+	decibels(error, energy)
+}
+
+
+/// the energy of a 23.5kHz tone at 48kHz resampled to 44.1kHz, where
+/// it is above the Nyquist frequency and everything left is aliasing
+fn alias_energy (quality: ResampleQuality) -> f64 {
+	let tone = |t: f64| 0.5 * (TAU * 23500.0 * t).sin();
+	let output = render(Signal { f: tone, sample_rate: 48000, pos: 0 }, 44100, quality, 20000);
+
+	let energy = output[1000..].iter().map(|&x| (x as f64).powi(2)).sum::<f64>();
+	// the energy of the tone over the same number of frames
+	decibels(energy, 0.125 * (output.len() - 1000) as f64)
+}
+
+
+#[test]
+fn sinc_sweep () {
+	let linear = sweep_error(ResampleQuality::Linear);
+	let sinc = sweep_error(ResampleQuality::Sinc);
+	assert!(sinc < -80.0, "sinc sweep error is {:.1}dB", sinc);
+	assert!(sinc < linear - 30.0, "sweep error is {:.1}dB with sinc and {:.1}dB linear", sinc, linear);
+}
+
+
+#[test]
+fn sinc_alias () {
+	let linear = alias_energy(ResampleQuality::Linear);
+	let sinc = alias_energy(ResampleQuality::Sinc);
+	assert!(sinc < -80.0, "sinc alias energy is {:.1}dB", sinc);
+	assert!(sinc < linear - 30.0, "alias energy is {:.1}dB with sinc and {:.1}dB linear", sinc, linear);
+}
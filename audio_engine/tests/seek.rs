@@ -0,0 +1,64 @@
+//! Seek, length and position of wav sounds, through the converters.
+
+mod common;
+
+use audio_engine::AudioEngine;
+use common::{ frames, wav, STEP };
+
+use std::time::Duration;
+
+
+
+#[test]
+fn seek_and_position () {
+	let engine = AudioEngine::new_offline(1, 48000);
+	let mut sound = engine.new_sound(wav(1000, 48000), None).unwrap();
+	assert_eq!(sound.duration(), Some(frames(1000)));
+
+	sound.play();
+	engine.render_frames(4).unwrap();
+	assert_eq!(sound.position(), 4);
+
+	sound.seek(frames(500));
+	let output = engine.render_frames(3).unwrap();
+	assert_eq!(output, [501.0, 502.0, 503.0].map(|x| x * STEP));
+	assert_eq!(sound.position(), 503);
+
+	// seeking back also works while the sound is paused
+	sound.pause();
+	sound.seek(frames(10));
+	engine.render_frames(4).unwrap();
+	sound.play();
+	assert_eq!(engine.render_frames(2).unwrap(), [11.0, 12.0].map(|x| x * STEP));
+}
+
+
+#[test]
+fn seek_past_the_end () {
+	let engine = AudioEngine::new_offline(1, 48000);
+	let mut sound = engine.new_sound(wav(100, 48000), None).unwrap();
+
+	sound.play();
+	sound.seek(frames(200));
+	assert_eq!(engine.render_frames(4).unwrap(), [0.0; 4]);
+	assert!(!sound.is_playing());
+	assert_eq!(sound.position(), 0);
+}
+
+
+#[test]
+fn resampled_position () {
+	let engine = AudioEngine::new_offline(1, 48000);
+	let mut sound = engine.new_sound(wav(2400, 24000), None).unwrap();
+	assert_eq!(sound.duration(), Some(Duration::from_millis(100)));
+
+	sound.play();
+	sound.seek(Duration::from_millis(10));
+	let output = engine.render_frames(96).unwrap();
+	assert_eq!(sound.position(), 480 + 96);
+
+	// the seek lands on frame 240 of the file, played every two frames
+	assert_eq!(output[0], 241.0 * STEP);
+	assert_eq!(output[1], 241.5 * STEP);
+	assert_eq!(output[2], 242.0 * STEP);
+}
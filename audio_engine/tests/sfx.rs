@@ -0,0 +1,121 @@
+//! Adsr envelopes and sound effects generated from sfxr style parameters.
+
+mod common;
+
+use audio_engine::{ Adsr, Envelope, SfxParams, SfxWave, SoundBuffer, SoundSource };
+use common::{ crossings, write };
+
+use std::time::Duration;
+
+
+
+fn ms (ms: u64) -> Duration {
+	Duration::from_millis(ms)
+}
+
+
+
+#[test]
+fn adsr () {
+	let adsr = Adsr::new(ms(100), ms(100), 0.5, ms(200));
+	let gain = |time: f64, hold: f64| (adsr.gain(time, hold) * 1e4).round() / 1e4;
+	assert_eq!(gain(0.0, 1.0), 0.0);
+	assert_eq!(gain(0.05, 1.0), 0.5);
+	assert_eq!(gain(0.15, 1.0), 0.75);
+	assert_eq!(gain(0.5, 1.0), 0.5);
+	assert_eq!(gain(1.1, 1.0), 0.25);
+	assert_eq!(gain(1.2, 1.0), 0.0);
+	assert_eq!(adsr.duration(ms(1000)), ms(1200));
+
+	// released during the attack, from where it was
+	assert_eq!(gain(0.05, 0.05), 0.5);
+	assert_eq!(gain(0.15, 0.05), 0.25);
+}
+
+
+#[test]
+fn envelope () {
+	let constant = SoundBuffer::from_samples(2, 1000, vec![1.0; 2000]).unwrap();
+	let adsr = Adsr::new(ms(4), Duration::ZERO, 1.0, ms(4));
+	let mut source = Envelope::new(constant.source(), adsr, ms(6));
+	assert_eq!(source.len_frames(), Some(10));
+
+	let output = write(&mut source, 40);
+	let left: Vec<_> = output.iter().step_by(2).copied().collect();
+	assert_eq!(left, [0.0, 0.25, 0.5, 0.75, 1.0, 1.0, 1.0, 0.75, 0.5, 0.25]);
+	assert_eq!(output[1], output[0]);
+}
+
+
+#[test]
+fn length_and_determinism () {
+	let params = SfxParams::hit();
+	let mut sfx = params.source();
+	let len = sfx.len_frames().unwrap();
+	assert_eq!(len, 48 * 160);
+
+	let output = write(&mut sfx, 48000);
+	assert_eq!(output.len() as u64, len);
+	assert!(output.iter().all(|x| x.abs() <= 1.0));
+	assert!(output.iter().any(|&x| x != 0.0));
+
+	sfx.reset();
+	assert_eq!(write(&mut sfx, 48000), output);
+	assert_eq!(write(&mut params.bake().source(), 48000), output);
+}
+
+
+#[test]
+fn mutate () {
+	let params = SfxParams::laser();
+	assert_eq!(params.mutate(3, 0.2), params.mutate(3, 0.2));
+	assert_ne!(params.mutate(3, 0.2), params.mutate(4, 0.2));
+	assert_eq!(params.mutate(3, 0.0), params);
+	assert_eq!(params.mutate(3, 1.0).wave, params.wave);
+}
+
+
+#[test]
+fn slide_and_arpeggio () {
+	let params = SfxParams {
+		wave: SfxWave::Sine,
+		hold: ms(200),
+		frequency: 1000.0,
+		arpeggio_ratio: 2.0,
+		arpeggio_delay: ms(100),
+		..SfxParams::default()
+	};
+	let output = write(&mut params.source(), 48000);
+	assert_eq!(output.len(), 9600);
+	assert!(crossings(&output[..4800]).abs_diff(200) <= 2);
+	assert!(crossings(&output[4800..]).abs_diff(400) <= 2);
+
+	// an octave per second, for half a second
+	let params = SfxParams {
+		wave: SfxWave::Sine,
+		hold: ms(500),
+		frequency: 1000.0,
+		slide: 1.0,
+		..SfxParams::default()
+	};
+	let output = write(&mut params.source(), 48000);
+	let last = crossings(&output[24000 - 480..]) as f32 / 2.0 * 100.0;
+	assert!((last - 1000.0 * 2f32.sqrt()).abs() < 100.0);
+}
+
+
+#[test]
+fn min_frequency_ends_the_sound () {
+	let params = SfxParams {
+		hold: ms(1000),
+		frequency: 1000.0,
+		min_frequency: 500.0,
+		slide: -2.0,
+		..SfxParams::default()
+	};
+	let mut sfx = params.source();
+	let output = write(&mut sfx, 48000);
+	// half a second to slide down an octave
+	assert!(output.len().abs_diff(24000) <= 32);
+	assert_eq!(sfx.len_frames(), Some(output.len() as u64));
+}
@@ -0,0 +1,148 @@
+//! Check the attenuation, pan and doppler of positional sounds.
+
+use audio_engine::{ Attenuation, AudioEngine, Listener, SoundSource, Spatial };
+
+use std::f32::consts::{ FRAC_1_SQRT_2, TAU };
+
+
+
+/// a mono sine wave, or a constant if `frequency` is 0
+struct Wave {
+	frequency: f32,
+	pos: usize
+}
+
+impl Wave {
+	fn new (frequency: f32) -> Self {
+		Self { frequency, pos: 0 }
+	}
+}
+
+impl SoundSource for Wave {
+
+	fn channels (&self) -> u16 {
+		1
+	}
+
+	fn sample_rate (&self) -> u32 {
+		48000
+	}
+
+	fn reset (&mut self) {
+		self.pos = 0;
+	}
+
+	fn write_samples (&mut self, buffer: &mut [f32]) -> usize {
+		for (i, b) in buffer.iter_mut().enumerate() {
+			let t = (self.pos + i) as f32 / 48000.0;
+			*b = if self.frequency == 0.0 { 0.5 } else { 0.5 * (TAU * self.frequency * t).sin() };
+		}
+		self.pos += buffer.len();
+		buffer.len()
+	}
+
+}
+
+
+
+/// the last stereo frame of a constant sound at `location`
+fn heard (spatial: Spatial, location: [f32; 2]) -> [f32; 2] {
+	let engine = AudioEngine::new_offline(2, 48000);
+	let mut sound = engine.new_sound(Wave::new(0.0), None).unwrap();
+	sound.set_spatial(Some(spatial));
+	sound.set_location(location);
+	sound.play();
+	let output = engine.render_frames(1024).unwrap();
+	[output[2046], output[2047]]
+}
+
+
+fn assert_close (a: [f32; 2], b: [f32; 2]) {
+	assert!((a[0] - b[0]).abs() < 1e-6 && (a[1] - b[1]).abs() < 1e-6, "{:?} != {:?}", a, b);
+}
+
+
+#[test]
+fn attenuation () {
+	let spatial = |attenuation, rolloff| Spatial {
+		attenuation,
+		min_distance: 1.0,
+		max_distance: 11.0,
+		rolloff,
+		doppler: 0.0
+	};
+
+	// in front of the listener, each side is at -3dB
+	let h = FRAC_1_SQRT_2;
+	assert_close(heard(spatial(Attenuation::Inverse, 1.0), [0.0, 4.0]), [0.125 * h, 0.125 * h]);
+	assert_close(heard(spatial(Attenuation::Linear, 1.0), [0.0, 6.0]), [0.25 * h, 0.25 * h]);
+	assert_close(heard(spatial(Attenuation::Exponential, 2.0), [0.0, 2.0]), [0.125 * h, 0.125 * h]);
+
+	// clamped to the minimum and maximum distances
+	assert_close(heard(spatial(Attenuation::Inverse, 1.0), [0.0, 0.5]), [0.5 * h, 0.5 * h]);
+	assert_close(heard(spatial(Attenuation::Linear, 1.0), [0.0, 20.0]), [0.0, 0.0]);
+}
+
+
+#[test]
+fn pan () {
+	let spatial = Spatial { min_distance: 2.0, ..Spatial::default() };
+	assert_close(heard(spatial, [2.0, 0.0]), [0.0, 0.5]);
+	assert_close(heard(spatial, [-2.0, 0.0]), [0.5, 0.0]);
+
+	// close to the listener, the sound moves toward the center
+	let [left, right] = heard(spatial, [-1.0, 0.0]);
+	assert!(left > right && right > 0.0);
+}
+
+
+#[test]
+fn listener () {
+	let engine = AudioEngine::new_offline(2, 48000);
+	let mut sound = engine.new_sound(Wave::new(0.0), None).unwrap();
+	sound.set_spatial(Some(Spatial::default()));
+	sound.set_location([10.0, 0.0]);
+	engine.set_listener(Listener { position: [10.0, 0.0], ..Listener::default() });
+	sound.play();
+	let centered = 0.5 * FRAC_1_SQRT_2;
+	assert_eq!(engine.render_frames(2).unwrap(), [centered; 4]);
+
+	// the gain is ramped when the sound moves
+	sound.set_location([10.0, 3.0]);
+	let output = engine.render_frames(512).unwrap();
+	assert!(output.windows(3).step_by(2).all(|x| x[2] <= x[0] && x[0] - x[2] < 0.01));
+	assert!((output[1022] - centered / 3.0).abs() < 1e-6);
+
+	sound.set_spatial(None);
+	assert_eq!(engine.render_frames(2).unwrap(), [centered; 4]);
+}
+
+
+/// the frequency of a mono signal, from the number of rising zero
+/// crossings in one second
+fn frequency (samples: &[f32]) -> f32 {
+	let crossings = samples.windows(2).filter(|x| x[0] < 0.0 && x[1] >= 0.0).count();
+	crossings as f32 * 48000.0 / samples.len() as f32
+}
+
+
+#[test]
+fn doppler () {
+	let engine = AudioEngine::new_offline(1, 48000);
+	let mut sound = engine.new_sound(Wave::new(1000.0), None).unwrap();
+	sound.set_spatial(Some(Spatial { min_distance: 1000.0, doppler: 1.0, ..Spatial::default() }));
+	sound.set_location([0.0, 100.0]);
+	sound.play();
+	let still = frequency(&engine.render_frames(48000).unwrap());
+	assert!((still - 1000.0).abs() < 2.0, "{}", still);
+
+	// moving toward the listener at a tenth of the speed of sound
+	sound.set_velocity([0.0, -34.3]);
+	let approaching = frequency(&engine.render_frames(48000).unwrap());
+	assert!((approaching - 1000.0 / 0.9).abs() < 2.0, "{}", approaching);
+
+	// the listener following the sound cancels the effect
+	engine.set_listener(Listener { velocity: [0.0, -34.3], ..Listener::default() });
+	let following = frequency(&engine.render_frames(48000).unwrap());
+	assert!((following - 1000.0).abs() < 2.0, "{}", following);
+}
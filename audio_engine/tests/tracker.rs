@@ -0,0 +1,325 @@
+//! Mod, s3m and xm modules played by the tracker module decoder.
+
+mod common;
+
+use audio_engine::{ AudioEngine, ModuleDecoder, ModuleFormat, ModulePosition, SoundSource, SourceError };
+use common::{ channel, crossings, write };
+
+
+
+/// a square wave of 64 samples, as signed bytes
+fn square () -> Vec<i8> {
+	(0..64).map(|i| if i < 32 { 64 } else { -64 }).collect()
+}
+
+
+/// a 4 channel protracker module, with one looped square sample and one
+/// pattern of `cells`, as rows, channels and the 4 bytes of each cell
+fn protracker (cells: &[(usize, usize, [u8; 4])]) -> Vec<u8> {
+	let sample = square();
+	let mut data = vec![0; 20];
+	data.extend_from_slice(&[0; 22]);
+	data.extend_from_slice(&(sample.len() as u16 / 2).to_be_bytes());
+	data.extend_from_slice(&[0, 64, 0, 0]);
+	data.extend_from_slice(&(sample.len() as u16 / 2).to_be_bytes());
+	data.resize(20 + 31 * 30, 0);
+	data.push(1);
+	data.push(127);
+	data.extend_from_slice(&[0; 128]);
+	data.extend_from_slice(b"M.K.");
+
+	let mut pattern = vec![0; 64 * 4 * 4];
+	for &(row, channel, cell) in cells {
+		let i = (row * 4 + channel) * 4;
+		pattern[i..i + 4].copy_from_slice(&cell);
+	}
+	data.extend_from_slice(&pattern);
+	data.extend(sample.iter().map(|&x| x as u8));
+	data
+}
+
+
+/// a protracker cell playing C-2 with the first sample
+fn note (effect: u8, param: u8) -> [u8; 4] {
+	[0x01, 0xAC, 0x10 | effect, param]
+}
+
+
+/// a scream tracker 3 module, of two channels and up to 12 `orders`,
+/// playing C-4 on the first row and the effect `command` on the second
+fn s3m (orders: &[u8], command: [u8; 2]) -> Vec<u8> {
+	let mut data = vec![0; 0x60];
+	data[0x1C] = 0x1A;
+	data[0x1D] = 16;
+	data[0x20..0x2A].copy_from_slice(&[orders.len() as u8, 0, 1, 0, 1, 0, 0, 0, 0x20, 0x13]);
+	data[0x2A] = 2;
+	data[0x2C..0x30].copy_from_slice(b"SCRM");
+	data[0x30..0x36].copy_from_slice(&[64, 6, 125, 0xB0, 0, 0]);
+	data[0x40..0x60].fill(255);
+	data[0x40] = 0;
+	data[0x41] = 8;
+	// the orders and the pointers to the instrument and the pattern
+	data.extend_from_slice(orders);
+	data.extend_from_slice(&[7, 0, 12, 0]);
+	data.resize(0x70, 0);
+
+	let mut instrument = vec![0; 80];
+	instrument[0] = 1;
+	instrument[14] = 20;
+	instrument[16] = 64;
+	instrument[24] = 64;
+	instrument[28] = 64;
+	instrument[31] = 1;
+	instrument[32..36].copy_from_slice(&8363u32.to_le_bytes());
+	instrument[76..80].copy_from_slice(b"SCRS");
+	data.extend_from_slice(&instrument);
+
+	let mut pattern = vec![0, 0, 0x20, 0x40, 1, 0, 0x80, command[0], command[1], 0];
+	pattern.extend_from_slice(&[0; 62]);
+	data.extend_from_slice(&pattern);
+	data.resize(20 * 16, 0);
+	data.extend(square().iter().map(|&x| (x as u8) ^ 0x80));
+	data
+}
+
+
+/// a fast tracker 2 module, of two channels and linear frequencies,
+/// playing C-4 on the first row of four, and releasing it on the third
+fn xm () -> Vec<u8> {
+	let mut data = b"Extended Module: ".to_vec();
+	data.extend_from_slice(&[0; 20]);
+	data.push(0x1A);
+	data.extend_from_slice(&[0; 20]);
+	data.extend_from_slice(&0x0104u16.to_le_bytes());
+	data.extend_from_slice(&276u32.to_le_bytes());
+	for x in [1u16, 0, 2, 1, 1, 1, 3, 150] {
+		data.extend_from_slice(&x.to_le_bytes());
+	}
+	data.extend_from_slice(&[0; 256]);
+
+	let cells = [
+		49, 1, 0x50, 0, 0, 0x80,
+		0x80, 0x80,
+		0x81, 97, 0x80,
+		0x80, 0x80
+	];
+	data.extend_from_slice(&9u32.to_le_bytes());
+	data.push(0);
+	data.extend_from_slice(&4u16.to_le_bytes());
+	data.extend_from_slice(&(cells.len() as u16).to_le_bytes());
+	data.extend_from_slice(&cells);
+
+	let mut instrument = vec![0; 263];
+	instrument[0..4].copy_from_slice(&263u32.to_le_bytes());
+	instrument[27] = 1;
+	instrument[29] = 40;
+	data.extend_from_slice(&instrument);
+
+	let sample = square();
+	let mut header = vec![0; 40];
+	header[0] = sample.len() as u8;
+	header[8] = sample.len() as u8;
+	header[12] = 64;
+	header[14] = 1;
+	header[15] = 128;
+	data.extend_from_slice(&header);
+	let mut old = 0i8;
+	for &x in sample.iter() {
+		data.push(x.wrapping_sub(old) as u8);
+		old = x;
+	}
+	data
+}
+
+
+fn position (order: u16, row: u16) -> ModulePosition {
+	ModulePosition { order, pattern: 0, row }
+}
+
+
+
+#[test]
+fn protracker_rows_and_end () {
+	let data = protracker(&[(0, 0, note(0, 0)), (3, 1, [0, 0, 0x0D, 0])]);
+	let mut module = ModuleDecoder::new(&data).unwrap();
+	assert_eq!(module.format(), ModuleFormat::Mod);
+	assert_eq!(module.handle().channels(), 4);
+	let rows = module.rows();
+
+	// 4 rows of 6 ticks, of 2.5 / 125 seconds
+	let output = write(&mut module, 96000);
+	assert_eq!(output.len(), 4 * 6 * 960 * 2);
+	assert_eq!(rows.try_iter().collect::<Vec<_>>(), (0..4).map(|row| position(0, row)).collect::<Vec<_>>());
+	assert_eq!(module.handle().position(), position(0, 3));
+
+	// C-2 plays the sample at 8287 Hz, a square of 64 samples
+	let expected = 8287.0 / 64.0 * 0.48 * 2.0;
+	assert!((crossings(&channel(&output, 2, 0)) as f32 - expected).abs() <= 2.0);
+
+	module.reset();
+	assert_eq!(write(&mut module, 96000), output);
+
+	// a buffer that ends inside a frame is written up to the frame
+	module.reset();
+	assert_eq!(write(&mut module, 5), output[..4]);
+}
+
+
+#[test]
+fn protracker_effects () {
+	// a speed of 3 and a tempo of 250 halve the ticks and the rows
+	let data = protracker(&[(0, 0, note(0xF, 3)), (0, 1, [0, 0, 0x0F, 250]), (1, 0, [0, 0, 0x0D, 0])]);
+	let output = write(&mut ModuleDecoder::new(&data).unwrap(), 96000);
+	assert_eq!(output.len(), 2 * 3 * 480 * 2);
+
+	// a volume of 0 is silent
+	let data = protracker(&[(0, 0, note(0xC, 0)), (1, 0, [0, 0, 0x0D, 0])]);
+	let output = write(&mut ModuleDecoder::new(&data).unwrap(), 96000);
+	assert!(output.iter().all(|&x| x == 0.0));
+
+	// the channels on the left of the amiga are louder on the left
+	let data = protracker(&[(0, 0, note(0, 0)), (1, 0, [0, 0, 0x0D, 0])]);
+	let output = write(&mut ModuleDecoder::new(&data).unwrap(), 96000);
+	let energy = |offset: usize| output.iter().skip(offset).step_by(2).map(|x| x * x).sum::<f32>();
+	assert!(energy(0) > energy(1) * 2.0);
+
+	// a position jump back loops forever
+	let data = protracker(&[(0, 0, note(0, 0)), (1, 0, [0, 0, 0x0B, 0])]);
+	let mut module = ModuleDecoder::new(&data).unwrap();
+	let rows = module.rows();
+	assert_eq!(write(&mut module, 96000).len(), 96000);
+	assert_eq!(rows.try_iter().take(4).collect::<Vec<_>>(), [position(0, 0), position(0, 1), position(0, 0), position(0, 1)]);
+}
+
+
+#[test]
+fn muted_channels () {
+	let data = protracker(&[(0, 0, note(0, 0)), (0, 3, note(0, 0)), (3, 0, [0, 0, 0x0D, 0])]);
+	let engine = AudioEngine::new_offline(2, 48000);
+	let module = ModuleDecoder::new(&data).unwrap();
+	let handle = module.handle();
+	handle.set_muted(0, true);
+	assert!(handle.is_muted(0));
+	assert!(!handle.is_muted(3));
+
+	let mut sound = engine.new_sound(module, None).unwrap();
+	sound.play();
+	let output = engine.render_frames(4800).unwrap();
+	assert!(output.iter().any(|&x| x != 0.0));
+
+	// muted channels are silent at once, and keep playing
+	handle.set_muted(3, true);
+	let output = engine.render_frames(4800).unwrap();
+	assert!(output.iter().all(|&x| x == 0.0));
+
+	handle.set_muted(0, false);
+	let output = engine.render_frames(4800).unwrap();
+	assert!(output.iter().any(|&x| x != 0.0));
+	assert_eq!(handle.position().row, 2);
+}
+
+
+#[test]
+fn scream_tracker () {
+	// a pattern break to the end
+	let mut module = ModuleDecoder::new(&s3m(&[0, 255], [3, 0])).unwrap();
+	assert_eq!(module.format(), ModuleFormat::S3m);
+	assert_eq!(module.handle().channels(), 2);
+
+	let output = write(&mut module, 96000);
+	assert_eq!(output.len(), 2 * 6 * 960 * 2);
+	let expected = 8363.0 / 64.0 * 0.24 * 2.0;
+	assert!((crossings(&channel(&output, 2, 0)) as f32 - expected).abs() <= 2.0);
+}
+
+
+#[test]
+fn scream_tracker_markers () {
+	// the markers, 254, are skipped but keep their place in the orders
+	let mut module = ModuleDecoder::new(&s3m(&[254, 0, 254, 0, 255, 0], [3, 0])).unwrap();
+	let rows = module.rows();
+	assert_eq!(write(&mut module, 96000).len(), 2 * 2 * 6 * 960 * 2);
+	assert_eq!(rows.try_iter().collect::<Vec<_>>(), [position(1, 0), position(1, 1), position(3, 0), position(3, 1)]);
+
+	// and jumps go to the orders of the file
+	let mut module = ModuleDecoder::new(&s3m(&[254, 0, 254, 0], [2, 2])).unwrap();
+	let rows = module.rows();
+	assert_eq!(write(&mut module, 96000).len(), 96000);
+	assert_eq!(rows.try_iter().take(6).collect::<Vec<_>>(), [position(1, 0), position(1, 1), position(3, 0), position(3, 1), position(3, 0), position(3, 1)]);
+}
+
+
+#[test]
+fn fast_tracker () {
+	let mut module = ModuleDecoder::new(&xm()).unwrap();
+	assert_eq!(module.format(), ModuleFormat::Xm);
+
+	// 4 rows of 3 ticks, of 2.5 / 150 seconds
+	let output = write(&mut module, 96000);
+	assert_eq!(output.len(), 4 * 3 * 800 * 2);
+	let expected = 8363.0 / 64.0 * 0.05 * 2.0;
+	assert!((crossings(&channel(&output[..4800], 2, 0)) as f32 - expected).abs() <= 2.0);
+
+	// the key off without a volume envelope cuts the note, after the
+	// ramp of a tick
+	let released = 2 * 3 * 800 * 2;
+	assert!(output[..released].iter().any(|&x| x != 0.0));
+	assert!(output[released + 800 * 2..].iter().all(|&x| x == 0.0));
+}
+
+
+#[test]
+fn fast_tracker_bad_envelope () {
+	// a volume envelope going back in time, with its sustain and loop
+	// points after its end
+	let mut data = xm();
+	let instrument = 358;
+	for (i, (x, y)) in [(0u16, 64u16), (8, 32), (2, 48), (20, 0)].into_iter().enumerate() {
+		let point = instrument + 129 + i * 4;
+		data[point..point + 2].copy_from_slice(&x.to_le_bytes());
+		data[point + 2..point + 4].copy_from_slice(&y.to_le_bytes());
+	}
+	data[instrument + 225] = 4;
+	data[instrument + 227] = 9;
+	data[instrument + 228] = 12;
+	data[instrument + 229] = 10;
+	data[instrument + 233] = 7;
+
+	let output = write(&mut ModuleDecoder::new(&data).unwrap(), 96000);
+	assert_eq!(output.len(), 4 * 3 * 800 * 2);
+	assert!(output.iter().any(|&x| x != 0.0));
+}
+
+
+#[test]
+fn invalid_modules () {
+	assert_eq!(ModuleDecoder::new(&[0; 2000]).err(), Some(SourceError::Unsupported));
+
+	let mut data = protracker(&[]);
+	data.truncate(1500);
+	assert_eq!(ModuleDecoder::new(&data).err(), Some(SourceError::Malformed));
+
+	let mut data = xm();
+	data.truncate(400);
+	assert_eq!(ModuleDecoder::new(&data).err(), Some(SourceError::Malformed));
+
+	// huge counts are rejected before the patterns are allocated
+	let mut data = xm();
+	data[68..70].copy_from_slice(&u16::MAX.to_le_bytes());
+	data[341..343].copy_from_slice(&u16::MAX.to_le_bytes());
+	assert_eq!(ModuleDecoder::new(&data).err(), Some(SourceError::Unsupported));
+	let mut data = xm();
+	data[70..72].copy_from_slice(&u16::MAX.to_le_bytes());
+	assert_eq!(ModuleDecoder::new(&data).err(), Some(SourceError::Unsupported));
+	let mut data = xm();
+	data[341..343].copy_from_slice(&u16::MAX.to_le_bytes());
+	assert_eq!(ModuleDecoder::new(&data).err(), Some(SourceError::Malformed));
+
+	// headers shorter than their fields would be read again and again
+	let mut data = xm();
+	data[336..340].copy_from_slice(&0u32.to_le_bytes());
+	assert_eq!(ModuleDecoder::new(&data).err(), Some(SourceError::Malformed));
+	let mut data = xm();
+	data[60..64].copy_from_slice(&u32::MAX.to_le_bytes());
+	assert_eq!(ModuleDecoder::new(&data).err(), Some(SourceError::Malformed));
+}
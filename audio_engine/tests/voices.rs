@@ -0,0 +1,130 @@
+//! Voice limits, priorities and voice stealing.
+
+use audio_engine::{ AudioEngine, Event, Sound, SoundBuffer, StealPolicy };
+
+
+
+/// one second of a constant mono signal
+fn buffer () -> SoundBuffer {
+	SoundBuffer::from_samples(1, 48000, vec![0.25; 48000]).unwrap()
+}
+
+
+fn events (engine: &AudioEngine) -> Vec<Event> {
+	std::iter::from_fn(|| engine.poll_event()).collect()
+}
+
+
+/// play `sound` and mix a block, so sounds start at different times
+fn play (engine: &AudioEngine, sound: &mut Sound) {
+	sound.play();
+	engine.render_frames(64).unwrap();
+}
+
+
+
+#[test]
+fn oldest_is_stolen () {
+	let engine = AudioEngine::new_offline(1, 48000);
+	engine.set_max_voices(Some(2));
+	let buffer = buffer();
+	let mut sounds: Vec<_> = (0..3).map(|_| engine.new_sound(buffer.source(), None).unwrap()).collect();
+
+	for sound in sounds.iter_mut() {
+		play(&engine, sound);
+	}
+	assert_eq!(events(&engine), [Event::Stolen(sounds[0].id())]);
+
+	// the stolen sound fades out, then stops
+	let output = engine.render_frames(960).unwrap();
+	assert!(output[0] > 0.5 && output[0] < 0.75);
+	assert_eq!(output[959], 0.5);
+	assert!(!sounds[0].is_playing());
+	assert!(sounds[1].is_playing());
+	assert!(sounds[2].is_playing());
+}
+
+
+#[test]
+fn priorities () {
+	let engine = AudioEngine::new_offline(1, 48000);
+	engine.set_max_voices(Some(1));
+	engine.set_steal_policy(StealPolicy::LowestPriority);
+	let buffer = buffer();
+	let mut high = engine.new_sound(buffer.source(), None).unwrap();
+	let mut low = engine.new_sound(buffer.source(), None).unwrap();
+	high.set_priority(1);
+
+	play(&engine, &mut high);
+	play(&engine, &mut low);
+	assert_eq!(events(&engine), [Event::Rejected(low.id())]);
+	assert!(high.is_playing());
+	assert!(!low.is_playing());
+
+	// a sound of the same priority can steal the voice
+	low.set_priority(1);
+	play(&engine, &mut low);
+	assert_eq!(events(&engine), [Event::Stolen(high.id())]);
+	assert!(low.is_playing());
+}
+
+
+#[test]
+fn quietest_is_stolen () {
+	let engine = AudioEngine::new_offline(1, 48000);
+	engine.set_max_voices(Some(2));
+	engine.set_steal_policy(StealPolicy::Quietest);
+	let buffer = buffer();
+	let mut sounds: Vec<_> = (0..3).map(|_| engine.new_sound(buffer.source(), None).unwrap()).collect();
+	sounds[1].set_volume(0.5);
+
+	for sound in sounds.iter_mut() {
+		play(&engine, sound);
+	}
+	assert_eq!(events(&engine), [Event::Stolen(sounds[1].id())]);
+}
+
+
+#[test]
+fn instance_limit () {
+	let engine = AudioEngine::new_offline(1, 48000);
+	let hit = buffer().with_max_instances(2);
+	let mut hits: Vec<_> = (0..3).map(|_| engine.new_sound(hit.source(), None).unwrap()).collect();
+	let mut other = engine.new_sound(buffer().source(), None).unwrap();
+
+	play(&engine, &mut other);
+	for sound in hits.iter_mut() {
+		play(&engine, sound);
+	}
+	assert_eq!(events(&engine), [Event::Stolen(hits[0].id())]);
+
+	// the other asset is not limited
+	engine.render_frames(960).unwrap();
+	assert!(other.is_playing());
+	assert_eq!(hits.iter().filter(|x| x.is_playing()).count(), 2);
+}
+
+
+#[test]
+fn rejected_sound_steals_nothing () {
+	let engine = AudioEngine::new_offline(1, 48000);
+	let hit = buffer().with_max_instances(1);
+	let mut first = engine.new_sound(hit.source(), None).unwrap();
+	let mut second = engine.new_sound(hit.source(), None).unwrap();
+	let mut music: Vec<_> = (0..2).map(|_| engine.new_sound(buffer().source(), None).unwrap()).collect();
+
+	play(&engine, &mut first);
+	for sound in music.iter_mut() {
+		sound.set_priority(1);
+		play(&engine, sound);
+	}
+	engine.set_max_voices(Some(2));
+
+	// the first hit could make room for the instance limit, but not for
+	// the voice limit, so it keeps playing
+	play(&engine, &mut second);
+	assert_eq!(events(&engine), [Event::Rejected(second.id())]);
+	engine.render_frames(960).unwrap();
+	assert!(first.is_playing());
+	assert!(!second.is_playing());
+}
@@ -219,8 +219,10 @@ fn run(event_loop: EventLoop<()>, app: App, audio_engine: AudioEngine) {
 
 	trace!("Running mainloop...");
 
+	// music plays once at a time, so it is streamed from the wav instead
+	// of being decoded into a `SoundBuffer`
 	let mut intro_music = audio_engine
-					.new_sound(WavDecoder::new(Cursor::new(&include_bytes!("intro.wav")[..])).unwrap(), |x| x)
+					.new_sound(WavDecoder::new(Cursor::new(&include_bytes!("intro.wav")[..])).unwrap(), None)
 					.unwrap();
 	intro_music.set_volume(1.0);
 
@@ -304,6 +306,11 @@ fn run(event_loop: EventLoop<()>, app: App, audio_engine: AudioEngine) {
 				event: WindowEvent::CloseRequested,
 				..
 			} => *control_flow = ControlFlow::Exit,
+			Event::MainEventsCleared => {
+				while let Some(event) = audio_engine.poll_event() {
+					trace!("Received audio event: {event:?}");
+				}
+			}
 			_ => {}
 		}
 	});
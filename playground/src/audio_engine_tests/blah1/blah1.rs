@@ -5,7 +5,7 @@
 extern crate log;
 use simple_logger::SimpleLogger;
 
-use audio_engine::{ AudioEngine, WavDecoder };
+use audio_engine::{ AudioEngine, FnEffect, Oscillator, Param, WavDecoder };
 
 use std::{
 	io::Cursor,
@@ -24,20 +24,25 @@ fn main () {
 
 	let engine = AudioEngine::new().unwrap();
 	let mut intro_music = engine
-							.new_sound(WavDecoder::new(Cursor::new(&include_bytes!("intro.wav")[..])).unwrap(), |x| x)
+							.new_sound(WavDecoder::new(Cursor::new(&include_bytes!("intro.wav")[..])).unwrap(), None)
 							.unwrap();
 
 	// intro_music.set_loop(true);
 	intro_music.play();
 
 	let mut s1 = engine
-					.new_sound(WavDecoder::new(Cursor::new(&include_bytes!("sin_500hz.wav")[..])).unwrap(), |x| x)
+					.new_sound(Oscillator::sine(500.0), None)
 					.unwrap();
 
-	s1.set_loop(true);
 	s1.play();
 	s1.set_volume(1.0);
 
+	let gain = Param::new(1.0);
+	s1.add_effect({
+		let gain = gain.clone();
+		FnEffect::new(move |x| x * gain.get())
+	});
+
 	let start_time = Instant::now();
 
 	// let max = Arc::new(AtomicU32::new(0));
@@ -52,19 +57,9 @@ fn main () {
 
 		// let max = max.clone();
 		// let min = min.clone();
-		s1.effect(move |x| {
-			// if x > f32::from_bits(max.load(Ordering::Relaxed)) {
-			// 	println!("{}", x);
-			// 	max.store(x.to_bits(), Ordering::Relaxed);
-			// }
-			// if x < f32::from_bits(min.load(Ordering::Relaxed)) {
-			// 	println!("{}", x);
-			// 	min.store(x.to_bits(), Ordering::Relaxed);
-			// }
-			// x * (((-4.0*(t-1.0).powf(2.0)) as f32).exp() + 0.5*((-3.0*(t-3.0).powf(2.0)) as f32).exp())
-			// x * ((44800.0*t).sin() * 0.5 + 0.5)
-			0.0
-		});
+		// gain.set(((-4.0*(t-1.0).powf(2.0)) as f32).exp() + 0.5*((-3.0*(t-3.0).powf(2.0)) as f32).exp());
+		// gain.set((44800.0*t).sin() * 0.5 + 0.5);
+		gain.set(0.0);
 
 		if t>10.0 {
 			drop(s1);